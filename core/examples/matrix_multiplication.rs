@@ -0,0 +1,68 @@
+//! Compares the time of the blocked matrix multiplication with the matrix multiplication that
+//! was used before, for a batch of 100 mnist images and a layer of 64 neurons:
+//! cargo run -p core --release --example matrix_multiplication
+
+use anyhow::{ensure, Result};
+use core::matrix::matrix::Matrix;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const ITERATIONS: u32 = 20;
+
+/// The matrix multiplication before it was split into blocks, one value of the result at a time
+fn original_matrix_multiplication(matrix_one: &Matrix, matrix_two: &Matrix) -> Result<Matrix> {
+    let (matrix_one_rows, matrix_one_cols) = (matrix_one.rows(), matrix_one.cols());
+    let (matrix_two_rows, matrix_two_cols) = (matrix_two.rows(), matrix_two.cols());
+    ensure!(
+        matrix_one_cols == matrix_two_rows,
+        "Matrix: the number of columns in the first matrix must be equal to the number of rows in the second matrix"
+    );
+    let mut new_matrix = Matrix::new(
+        matrix_one_rows,
+        matrix_two_cols,
+        vec![0.0; matrix_one_rows * matrix_two_cols],
+    )?;
+    for i in 0..matrix_one_rows {
+        for j in 0..matrix_two_cols {
+            let mut k = 0;
+            loop {
+                if k < matrix_one_cols && k < matrix_two_rows {
+                    new_matrix[(i, j)] += matrix_one[(i, k)] * matrix_two[(k, j)];
+                } else {
+                    break;
+                }
+                k += 1;
+            }
+        }
+    }
+    Ok(new_matrix)
+}
+
+fn random_matrix(rows: usize, cols: usize, rng: &mut StdRng) -> Result<Matrix> {
+    Matrix::new(
+        rows,
+        cols,
+        (0..rows * cols).map(|_| rng.gen_range(-1.0..1.0)).collect(),
+    )
+}
+
+fn time(multiply: impl Fn() -> Result<Matrix>) -> Result<Duration> {
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        black_box(multiply()?);
+    }
+    Ok(start.elapsed())
+}
+
+fn main() -> Result<()> {
+    let mut rng = StdRng::seed_from_u64(0);
+    let inputs = random_matrix(100, 784, &mut rng)?;
+    let weights = random_matrix(784, 64, &mut rng)?;
+    let blocked = time(|| &inputs * &weights)?;
+    let original = time(|| original_matrix_multiplication(&inputs, &weights))?;
+    println!("100x784 * 784x64, {ITERATIONS} times:");
+    println!("blocked:  {blocked:?}");
+    println!("original: {original:?}");
+    Ok(())
+}
@@ -0,0 +1,546 @@
+use anyhow::{ensure, Result};
+use rand::{seq::SliceRandom, Rng};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use super::dataset::Dataset;
+use crate::{matrix::float::Float, neuralnetwork::training::TrainingEntry};
+
+type Transform<'a, T> = Box<dyn Fn(TrainingEntry<T>) -> TrainingEntry<T> + 'a>;
+
+/// The entries that are used to validate the neural network after every round of training
+enum Validation<'a, T: Float> {
+    None,
+    /// The fraction of the entries of the dataset that is held out of training, optionally with
+    /// the indices of the dataset in a random order: first the indices of the training entries
+    /// and then the indices of the held out entries. Without the indices the last entries of
+    /// the dataset are held out.
+    Split(f64, Option<Vec<usize>>),
+    Dataset(Box<dyn Dataset<T> + 'a>),
+}
+
+/// Splits a dataset into batches for training. The entries of a batch are read from the dataset
+/// when the batch is used, and the transforms are applied to every entry when it is read, so
+/// only one batch is in memory at a time.
+///
+/// ```
+/// use core::dataset::dataloader::DataLoader;
+/// use core::neuralnetwork::training::{TrainingData, TrainingEntry};
+/// use rand::{rngs::StdRng, SeedableRng};
+///
+/// let training_data = TrainingData(vec![
+///     TrainingEntry { input: vec![0.0, 255.0], expected_output: vec![1.0] },
+///     TrainingEntry { input: vec![255.0, 0.0], expected_output: vec![0.0] },
+/// ]);
+/// let data_loader = DataLoader::new(training_data)
+///     .batch_size(2)
+///     .shuffle(true)
+///     .transform(|mut entry| {
+///         entry.input.iter_mut().for_each(|value| *value /= 255.0);
+///         entry
+///     });
+/// for batch in data_loader.batches(&mut StdRng::seed_from_u64(0)).unwrap() {
+///     assert_eq!(batch.unwrap().len(), 2);
+/// }
+/// ```
+pub struct DataLoader<'a, T: Float = f64> {
+    dataset: Box<dyn Dataset<T> + 'a>,
+    batch_size: usize,
+    shuffle: bool,
+    transforms: Vec<Transform<'a, T>>,
+    validation: Validation<'a, T>,
+}
+
+impl<'a, T: Float> DataLoader<'a, T> {
+    /// A data loader with a batch size of 1, that reads the entries in the order of the dataset
+    pub fn new(dataset: impl Dataset<T> + 'a) -> DataLoader<'a, T> {
+        DataLoader {
+            dataset: Box::new(dataset),
+            batch_size: 1,
+            shuffle: false,
+            transforms: Vec::new(),
+            validation: Validation::None,
+        }
+    }
+
+    /// The amount of entries that are used for one update of the weights and biases, the
+    /// adjustments of the entries in a batch are averaged. The last batch is smaller when the
+    /// amount of entries isn't a multiple of the batch size.
+    pub fn batch_size(mut self, batch_size: usize) -> DataLoader<'a, T> {
+        self.batch_size = batch_size;
+        self
+    }
+
+    /// Reads the entries in another random order every time batches is called
+    pub fn shuffle(mut self, shuffle: bool) -> DataLoader<'a, T> {
+        self.shuffle = shuffle;
+        self
+    }
+
+    /// Adds a transform that is applied to every entry when it is read, after the transforms that
+    /// were added before
+    pub fn transform(
+        mut self,
+        transform: impl Fn(TrainingEntry<T>) -> TrainingEntry<T> + 'a,
+    ) -> DataLoader<'a, T> {
+        self.transforms.push(Box::new(transform));
+        self
+    }
+
+    /// Holds out the fraction (between 0 and 1) of the last entries of the dataset, they are
+    /// not used for training but to validate the neural network after every round. At least
+    /// one entry should be held out and at least one entry should be left for training.
+    ///
+    /// The last entries are held out before the entries are shuffled. When the dataset is
+    /// sorted (for example by label or by time) the validation entries are not like the
+    /// training entries, then use shuffled_validation_split.
+    pub fn validation_split(mut self, fraction: f64) -> DataLoader<'a, T> {
+        self.validation = Validation::Split(fraction, None);
+        self
+    }
+
+    /// Like validation_split, but holds out a random selection of the entries of the dataset.
+    /// The selection is made once with the random number generator, so a seeded generator
+    /// always holds out the same entries. The training entries and the validation entries stay
+    /// in the order of the dataset.
+    pub fn shuffled_validation_split(
+        mut self,
+        fraction: f64,
+        rng: &mut impl Rng,
+    ) -> DataLoader<'a, T> {
+        self.validation = Validation::Split(fraction, None);
+        let amount_of_training_entries = self.len();
+        let mut indices = (0..self.dataset.len()).collect::<Vec<usize>>();
+        indices.shuffle(rng);
+        indices[..amount_of_training_entries].sort_unstable();
+        indices[amount_of_training_entries..].sort_unstable();
+        self.validation = Validation::Split(fraction, Some(indices));
+        self
+    }
+
+    /// Validates the neural network with another dataset after every round of training, the
+    /// transforms are applied to the entries of this dataset as well
+    pub fn validation_data(mut self, dataset: impl Dataset<T> + 'a) -> DataLoader<'a, T> {
+        self.validation = Validation::Dataset(Box::new(dataset));
+        self
+    }
+
+    /// The entries to validate the neural network with, None when there is no validation data
+    pub fn validation(&self) -> Option<ValidationSet<'_, 'a, T>> {
+        match self.validation {
+            Validation::None => None,
+            _ => Some(ValidationSet { data_loader: self }),
+        }
+    }
+
+    pub fn amount_of_batches(&self) -> usize {
+        self.len().div_ceil(self.batch_size.max(1))
+    }
+
+    /// The batches of one round (epoch) through the dataset. With shuffle the order of the entries
+    /// is shuffled with the random number generator, otherwise the rng isn't used.
+    pub fn batches(&self, rng: &mut impl Rng) -> Result<Batches<'_, 'a, T>> {
+        let cursor = self.cursor(rng)?;
+        self.resume(cursor)
+    }
+
+    /// The start of a new round through the dataset, the cursor can be saved and the round can
+    /// be resumed with it later on (see Batches::cursor)
+    pub fn cursor(&self, rng: &mut impl Rng) -> Result<DatasetCursor> {
+        ensure!(
+            self.batch_size > 0,
+            "DataLoader: batch_size should be greater than 0"
+        );
+        if let Validation::Split(fraction, _) = self.validation {
+            ensure!(
+                (0.0..1.0).contains(&fraction),
+                "DataLoader: the validation split should be between 0 and 1"
+            );
+            let held_out = self.amount_of_held_out_entries();
+            ensure!(
+                held_out > 0 && held_out < self.dataset.len(),
+                "DataLoader: the validation split {} holds out {} of {} entries, there should be at least one validation and one training entry",
+                fraction,
+                held_out,
+                self.dataset.len()
+            );
+        }
+        let mut order = (0..self.len()).collect::<Vec<usize>>();
+        if self.shuffle {
+            order.shuffle(rng);
+        }
+        Ok(DatasetCursor { order, position: 0 })
+    }
+
+    /// The batches of the round of the cursor that are not used yet
+    pub fn resume(&self, cursor: DatasetCursor) -> Result<Batches<'_, 'a, T>> {
+        ensure!(
+            self.batch_size > 0,
+            "DataLoader: batch_size should be greater than 0"
+        );
+        ensure!(
+            cursor.order.len() == self.len(),
+            "DataLoader: the cursor is for {} entries instead of {}",
+            cursor.order.len(),
+            self.len()
+        );
+        Ok(Batches {
+            data_loader: self,
+            cursor,
+        })
+    }
+}
+
+impl<T: Float> DataLoader<'_, T> {
+    /// The amount of entries of the dataset that are held out for validation
+    fn amount_of_held_out_entries(&self) -> usize {
+        match self.validation {
+            Validation::Split(fraction, _) => {
+                ((self.dataset.len() as f64 * fraction.clamp(0.0, 1.0)).round() as usize)
+                    .min(self.dataset.len())
+            }
+            _ => 0,
+        }
+    }
+
+    /// The index in the dataset of the training entries followed by the held out entries
+    fn index_in_dataset(&self, index: usize) -> usize {
+        match &self.validation {
+            Validation::Split(_, Some(indices)) => indices[index],
+            _ => index,
+        }
+    }
+
+    fn apply_transforms(&self, entry: TrainingEntry<T>) -> TrainingEntry<T> {
+        self.transforms
+            .iter()
+            .fold(entry, |entry, transform| transform(entry))
+    }
+}
+
+/// The data loader is a dataset itself, with the entries that are used for training (without
+/// the held out entries) and the transforms applied to the entries
+impl<T: Float> Dataset<T> for DataLoader<'_, T> {
+    fn len(&self) -> usize {
+        self.dataset.len() - self.amount_of_held_out_entries()
+    }
+
+    fn get(&self, index: usize) -> Result<TrainingEntry<T>> {
+        ensure!(
+            index < self.len(),
+            "DataLoader: index {} is out of bounds for {} entries",
+            index,
+            self.len()
+        );
+        Ok(self.apply_transforms(self.dataset.get(self.index_in_dataset(index))?))
+    }
+}
+
+/// The validation entries of a data loader, with the transforms applied to the entries
+#[derive(Debug)]
+pub struct ValidationSet<'l, 'a, T: Float> {
+    data_loader: &'l DataLoader<'a, T>,
+}
+
+impl<T: Float> Dataset<T> for ValidationSet<'_, '_, T> {
+    fn len(&self) -> usize {
+        match &self.data_loader.validation {
+            Validation::None => 0,
+            Validation::Split(_, _) => self.data_loader.amount_of_held_out_entries(),
+            Validation::Dataset(dataset) => dataset.len(),
+        }
+    }
+
+    fn get(&self, index: usize) -> Result<TrainingEntry<T>> {
+        ensure!(
+            index < self.len(),
+            "DataLoader: validation index {} is out of bounds for {} entries",
+            index,
+            self.len()
+        );
+        let entry = match &self.data_loader.validation {
+            Validation::Dataset(dataset) => dataset.get(index)?,
+            _ => self.data_loader.dataset.get(
+                self.data_loader
+                    .index_in_dataset(self.data_loader.len() + index),
+            )?,
+        };
+        Ok(self.data_loader.apply_transforms(entry))
+    }
+}
+
+impl<T: Float> fmt::Debug for DataLoader<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DataLoader")
+            .field("amount_of_entries", &self.len())
+            .field("batch_size", &self.batch_size)
+            .field("shuffle", &self.shuffle)
+            .field("amount_of_transforms", &self.transforms.len())
+            .field(
+                "amount_of_validation_entries",
+                &self.validation().map_or(0, |validation| validation.len()),
+            )
+            .finish()
+    }
+}
+
+/// The position in one round through the dataset, with the order of the entries of the round
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DatasetCursor {
+    order: Vec<usize>,
+    position: usize,
+}
+
+impl DatasetCursor {
+    /// The amount of entries of the round that are used
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// The amount of entries of the round
+    pub fn len(&self) -> usize {
+        self.order.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.order.is_empty()
+    }
+
+    /// True when all entries of the round are used
+    pub fn is_finished(&self) -> bool {
+        self.position >= self.order.len()
+    }
+}
+
+/// The batches of one round through the dataset, the entries are read when the batch is used
+#[derive(Debug)]
+pub struct Batches<'l, 'a, T: Float> {
+    data_loader: &'l DataLoader<'a, T>,
+    cursor: DatasetCursor,
+}
+
+impl<T: Float> Batches<'_, '_, T> {
+    /// The position after the batches that are used, to resume the round with
+    /// DataLoader::resume
+    pub fn cursor(&self) -> &DatasetCursor {
+        &self.cursor
+    }
+}
+
+impl<T: Float> Iterator for Batches<'_, '_, T> {
+    type Item = Result<Vec<TrainingEntry<T>>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.cursor.is_finished() {
+            return None;
+        }
+        let position = self.cursor.position;
+        let end = (position + self.data_loader.batch_size).min(self.cursor.len());
+        let batch = self.cursor.order[position..end]
+            .iter()
+            .map(|index| self.data_loader.get(*index))
+            .collect();
+        self.cursor.position = end;
+        Some(batch)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::neuralnetwork::training::TrainingData;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    fn training_data(amount_of_entries: usize) -> TrainingData {
+        TrainingData(
+            (0..amount_of_entries)
+                .map(|i| TrainingEntry {
+                    input: vec![i as f64],
+                    expected_output: vec![0.0],
+                })
+                .collect(),
+        )
+    }
+
+    fn inputs(batch: Result<Vec<TrainingEntry>>) -> Vec<f64> {
+        batch
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.input[0])
+            .collect()
+    }
+
+    #[test]
+    fn testing_batches() {
+        let data_loader = DataLoader::new(training_data(5)).batch_size(2);
+        assert_eq!(data_loader.amount_of_batches(), 3);
+        assert!(DataLoader::new(training_data(5))
+            .batch_size(0)
+            .batches(&mut StdRng::seed_from_u64(0))
+            .is_err());
+        assert_eq!(
+            data_loader
+                .batches(&mut StdRng::seed_from_u64(0))
+                .unwrap()
+                .map(inputs)
+                .collect::<Vec<Vec<f64>>>(),
+            vec![vec![0.0, 1.0], vec![2.0, 3.0], vec![4.0]]
+        );
+    }
+
+    #[test]
+    fn shuffle_reads_every_entry_once() {
+        let data_loader = DataLoader::new(training_data(10))
+            .batch_size(3)
+            .shuffle(true);
+        let mut rng = StdRng::seed_from_u64(0);
+        let first_round = data_loader
+            .batches(&mut rng)
+            .unwrap()
+            .flat_map(inputs)
+            .collect::<Vec<f64>>();
+        let second_round = data_loader
+            .batches(&mut rng)
+            .unwrap()
+            .flat_map(inputs)
+            .collect::<Vec<f64>>();
+        assert_ne!(first_round, second_round);
+        for round in [first_round, second_round] {
+            let mut sorted = round.clone();
+            sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            assert_eq!(sorted, (0..10).map(|i| i as f64).collect::<Vec<f64>>());
+        }
+    }
+
+    #[test]
+    fn a_round_can_be_resumed_with_the_cursor() {
+        let data_loader = DataLoader::new(training_data(10))
+            .batch_size(3)
+            .shuffle(true);
+        let whole_round = data_loader
+            .batches(&mut StdRng::seed_from_u64(0))
+            .unwrap()
+            .flat_map(inputs)
+            .collect::<Vec<f64>>();
+        let mut batches = data_loader.batches(&mut StdRng::seed_from_u64(0)).unwrap();
+        let mut resumed_round = batches
+            .by_ref()
+            .take(2)
+            .flat_map(inputs)
+            .collect::<Vec<f64>>();
+        assert_eq!(batches.cursor().position(), 6);
+        let cursor: DatasetCursor =
+            serde_json::from_str(&serde_json::to_string(batches.cursor()).unwrap()).unwrap();
+        let mut resumed = data_loader.resume(cursor).unwrap();
+        resumed_round.extend(resumed.by_ref().flat_map(inputs));
+        assert_eq!(resumed_round, whole_round);
+        assert!(resumed.cursor().is_finished());
+        let cursor = data_loader.cursor(&mut StdRng::seed_from_u64(0)).unwrap();
+        assert!(DataLoader::new(training_data(9)).resume(cursor).is_err());
+    }
+
+    #[test]
+    fn testing_validation_split() {
+        let data_loader = DataLoader::new(training_data(10))
+            .batch_size(4)
+            .shuffle(true)
+            .validation_split(0.25)
+            .transform(|mut entry| {
+                entry.input[0] *= 10.0;
+                entry
+            });
+        assert_eq!(data_loader.len(), 7);
+        assert_eq!(data_loader.amount_of_batches(), 2);
+        let mut training_inputs = data_loader
+            .batches(&mut StdRng::seed_from_u64(0))
+            .unwrap()
+            .flat_map(inputs)
+            .collect::<Vec<f64>>();
+        training_inputs.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(
+            training_inputs,
+            vec![0.0, 10.0, 20.0, 30.0, 40.0, 50.0, 60.0]
+        );
+        let validation = data_loader.validation().unwrap();
+        assert_eq!(validation.len(), 3);
+        assert_eq!(validation.get(0).unwrap().input, vec![70.0]);
+        assert!(validation.get(3).is_err());
+        assert!(data_loader.get(7).is_err());
+        assert!(DataLoader::new(training_data(10))
+            .validation_split(1.0)
+            .batches(&mut StdRng::seed_from_u64(0))
+            .is_err());
+        assert_eq!(
+            DataLoader::new(training_data(10))
+                .validation_split(0.04)
+                .batches(&mut StdRng::seed_from_u64(0))
+                .unwrap_err()
+                .to_string(),
+            "DataLoader: the validation split 0.04 holds out 0 of 10 entries, there should be at least one validation and one training entry"
+        );
+        assert!(DataLoader::new(training_data(10))
+            .validation_split(0.0)
+            .batches(&mut StdRng::seed_from_u64(0))
+            .is_err());
+    }
+
+    #[test]
+    fn testing_shuffled_validation_split() {
+        let split = |seed| {
+            let data_loader = DataLoader::new(training_data(10))
+                .batch_size(10)
+                .shuffled_validation_split(0.3, &mut StdRng::seed_from_u64(seed));
+            let training_inputs = data_loader
+                .batches(&mut StdRng::seed_from_u64(0))
+                .unwrap()
+                .flat_map(inputs)
+                .collect::<Vec<f64>>();
+            let validation = data_loader.validation().unwrap();
+            let validation_inputs = (0..validation.len())
+                .map(|index| validation.get(index).unwrap().input[0])
+                .collect::<Vec<f64>>();
+            (training_inputs, validation_inputs)
+        };
+        let (training_inputs, validation_inputs) = split(0);
+        assert_eq!(training_inputs.len(), 7);
+        assert_eq!(validation_inputs.len(), 3);
+        // not the last entries, but both parts stay in the order of the dataset
+        assert_ne!(validation_inputs, vec![7.0, 8.0, 9.0]);
+        assert!(training_inputs.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(validation_inputs.windows(2).all(|pair| pair[0] < pair[1]));
+        let mut all_inputs = [training_inputs.clone(), validation_inputs.clone()].concat();
+        all_inputs.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(all_inputs, (0..10).map(|i| i as f64).collect::<Vec<f64>>());
+        assert_eq!(split(0), (training_inputs, validation_inputs));
+    }
+
+    #[test]
+    fn testing_validation_data() {
+        let validation_data = training_data(3);
+        let data_loader = DataLoader::new(training_data(10)).validation_data(&validation_data);
+        assert_eq!(data_loader.len(), 10);
+        assert_eq!(data_loader.validation().unwrap().len(), 3);
+        assert_eq!(
+            data_loader.validation().unwrap().get(2).unwrap().input,
+            vec![2.0]
+        );
+        assert!(DataLoader::new(training_data(10)).validation().is_none());
+    }
+
+    #[test]
+    fn transforms_are_applied_in_order_when_an_entry_is_read() {
+        let training_data = training_data(2);
+        let data_loader = DataLoader::new(&training_data)
+            .transform(|mut entry| {
+                entry.input[0] += 1.0;
+                entry
+            })
+            .transform(|mut entry| {
+                entry.input[0] *= 10.0;
+                entry
+            });
+        assert_eq!(data_loader.get(1).unwrap().input, vec![20.0]);
+        // the dataset itself isn't changed
+        assert_eq!(training_data.0[1].input, vec![1.0]);
+    }
+}
@@ -0,0 +1,67 @@
+use anyhow::{Context, Result};
+
+use crate::{
+    matrix::float::Float,
+    neuralnetwork::training::{TrainingData, TrainingEntry},
+};
+
+/// A collection of training entries that can be read one by one. An entry is only created when
+/// it is read, so a dataset doesn't have to keep all entries in memory (the IdxDataset reads
+/// them from the bytes of the IDX files).
+pub trait Dataset<T: Float = f64> {
+    /// The amount of entries
+    fn len(&self) -> usize;
+
+    /// The entry at the index, an index out of bounds is an error
+    fn get(&self, index: usize) -> Result<TrainingEntry<T>>;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T: Float, D: Dataset<T> + ?Sized> Dataset<T> for &D {
+    fn len(&self) -> usize {
+        (**self).len()
+    }
+
+    fn get(&self, index: usize) -> Result<TrainingEntry<T>> {
+        (**self).get(index)
+    }
+}
+
+impl<T: Float> Dataset<T> for TrainingData<T> {
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    fn get(&self, index: usize) -> Result<TrainingEntry<T>> {
+        self.0.get(index).cloned().with_context(|| {
+            format!(
+                "Dataset: index {} is out of bounds for {} entries",
+                index,
+                self.0.len()
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn testing_training_data_as_dataset() {
+        let training_data = TrainingData(vec![TrainingEntry {
+            input: vec![0.1, 0.2],
+            expected_output: vec![1.0],
+        }]);
+        assert_eq!(Dataset::len(&training_data), 1);
+        assert_eq!(training_data.get(0).unwrap().input, vec![0.1, 0.2]);
+        assert_eq!(
+            training_data.get(1).unwrap_err().to_string(),
+            "Dataset: index 1 is out of bounds for 1 entries"
+        );
+        assert!(!Dataset::is_empty(&training_data));
+    }
+}
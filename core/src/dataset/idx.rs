@@ -0,0 +1,572 @@
+//! The IDX file format of the mnist dataset (and Fashion-MNIST, EMNIST, etc.), see
+//! mnist-dataset/README.md. An IDX file starts with a header:
+//!
+//! | offset | type           | description                                        |
+//! |--------|----------------|----------------------------------------------------|
+//! | 0000   | 2 bytes        | always 0                                           |
+//! | 0002   | unsigned byte  | the data type of the values, see IdxType           |
+//! | 0003   | unsigned byte  | the amount of dimensions                           |
+//! | 0004   | 32 bit integer | the size of the first dimension (amount of items)  |
+//! | ....   | 32 bit integer | the size of every other dimension                  |
+//!
+//! After the header the values follow, item after item. All integers and floats are stored
+//! with the most significant byte first (big endian).
+
+use anyhow::{bail, ensure, Context, Result};
+use std::io::Write;
+
+use super::dataset::Dataset;
+use crate::{matrix::float::Float, neuralnetwork::training::TrainingEntry};
+
+/// The data type of the values in an IDX file, the third byte of the magic number
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdxType {
+    /// 0x08, u8
+    UnsignedByte,
+    /// 0x09, i8
+    SignedByte,
+    /// 0x0B, i16
+    Short,
+    /// 0x0C, i32
+    Int,
+    /// 0x0D, f32
+    Float,
+    /// 0x0E, f64
+    Double,
+}
+
+impl IdxType {
+    pub fn from_byte(byte: u8) -> Result<IdxType> {
+        Ok(match byte {
+            0x08 => IdxType::UnsignedByte,
+            0x09 => IdxType::SignedByte,
+            0x0B => IdxType::Short,
+            0x0C => IdxType::Int,
+            0x0D => IdxType::Float,
+            0x0E => IdxType::Double,
+            _ => bail!("Idx: unknown data type {byte:#04x}"),
+        })
+    }
+
+    pub fn to_byte(self) -> u8 {
+        match self {
+            IdxType::UnsignedByte => 0x08,
+            IdxType::SignedByte => 0x09,
+            IdxType::Short => 0x0B,
+            IdxType::Int => 0x0C,
+            IdxType::Float => 0x0D,
+            IdxType::Double => 0x0E,
+        }
+    }
+
+    /// The amount of bytes of one value
+    pub fn size(self) -> usize {
+        match self {
+            IdxType::UnsignedByte | IdxType::SignedByte => 1,
+            IdxType::Short => 2,
+            IdxType::Int | IdxType::Float => 4,
+            IdxType::Double => 8,
+        }
+    }
+
+    /// Every value of every data type fits in an f64 without losing precision
+    fn read(self, bytes: &[u8]) -> f64 {
+        match self {
+            IdxType::UnsignedByte => bytes[0] as f64,
+            IdxType::SignedByte => bytes[0] as i8 as f64,
+            IdxType::Short => i16::from_be_bytes([bytes[0], bytes[1]]) as f64,
+            IdxType::Int => i32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64,
+            IdxType::Float => f32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64,
+            IdxType::Double => f64::from_be_bytes([
+                bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7],
+            ]),
+        }
+    }
+
+    /// Writes the value as this data type, a value that doesn't fit exactly (for example 256 or
+    /// 0.5 for an unsigned byte) is an error
+    fn write(self, value: f64, bytes: &mut Vec<u8>) -> Result<()> {
+        let fits = |min: f64, max: f64| value.fract() == 0.0 && value >= min && value <= max;
+        match self {
+            IdxType::UnsignedByte if fits(u8::MIN as f64, u8::MAX as f64) => {
+                bytes.push(value as u8)
+            }
+            IdxType::SignedByte if fits(i8::MIN as f64, i8::MAX as f64) => {
+                bytes.extend((value as i8).to_be_bytes())
+            }
+            IdxType::Short if fits(i16::MIN as f64, i16::MAX as f64) => {
+                bytes.extend((value as i16).to_be_bytes())
+            }
+            IdxType::Int if fits(i32::MIN as f64, i32::MAX as f64) => {
+                bytes.extend((value as i32).to_be_bytes())
+            }
+            IdxType::Float => bytes.extend((value as f32).to_be_bytes()),
+            IdxType::Double => bytes.extend(value.to_be_bytes()),
+            _ => bail!("Idx: the value {value} doesn't fit in the data type {self:?}"),
+        }
+        Ok(())
+    }
+}
+
+/// The header of an IDX file, the first dimension is the amount of items. For the mnist
+/// images the dimensions are [amount of images, 28, 28], for the labels [amount of labels].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IdxHeader {
+    pub data_type: IdxType,
+    pub dimensions: Vec<u32>,
+}
+
+impl IdxHeader {
+    pub fn new(data_type: IdxType, dimensions: Vec<u32>) -> Result<IdxHeader> {
+        ensure!(
+            !dimensions.is_empty() && dimensions.len() <= u8::MAX as usize,
+            "Idx: there should be between 1 and 255 dimensions"
+        );
+        Ok(IdxHeader {
+            data_type,
+            dimensions,
+        })
+    }
+
+    /// Reads the header from the start of the bytes of an IDX file
+    pub fn parse(bytes: &[u8]) -> Result<IdxHeader> {
+        ensure!(
+            bytes.len() >= 4,
+            "Idx: the file is too short for a magic number"
+        );
+        ensure!(
+            bytes[0] == 0 && bytes[1] == 0,
+            "Idx: the magic number should start with two zero bytes, this is not an IDX file"
+        );
+        let data_type = IdxType::from_byte(bytes[2])?;
+        let amount_of_dimensions = bytes[3] as usize;
+        ensure!(
+            amount_of_dimensions > 0,
+            "Idx: there should be at least one dimension"
+        );
+        ensure!(
+            bytes.len() >= 4 + 4 * amount_of_dimensions,
+            "Idx: the file is too short for the sizes of {amount_of_dimensions} dimensions"
+        );
+        let dimensions = bytes[4..4 + 4 * amount_of_dimensions]
+            .chunks(4)
+            .map(|size| u32::from_be_bytes([size[0], size[1], size[2], size[3]]))
+            .collect();
+        IdxHeader::new(data_type, dimensions)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![0, 0, self.data_type.to_byte(), self.dimensions.len() as u8];
+        for size in self.dimensions.iter() {
+            bytes.extend(size.to_be_bytes());
+        }
+        bytes
+    }
+
+    /// The amount of bytes of the header
+    pub fn size(&self) -> usize {
+        4 + 4 * self.dimensions.len()
+    }
+
+    /// The size of the first dimension
+    pub fn amount_of_items(&self) -> usize {
+        self.dimensions[0] as usize
+    }
+
+    /// The amount of values of one item, the product of all dimensions except the first.
+    /// For a 28 x 28 image this is 784, for a label this is 1. Fails when the product doesn't
+    /// fit in a usize.
+    pub fn values_per_item(&self) -> Result<usize> {
+        self.dimensions[1..]
+            .iter()
+            .try_fold(1usize, |product, size| product.checked_mul(*size as usize))
+            .context("Idx: the dimensions are too big")
+    }
+}
+
+/// An IDX file that borrows its bytes (for example from include_bytes! or std::fs::read).
+/// The header and the size of the data are checked when the file is parsed, the items are only
+/// read when they are used.
+#[derive(Debug, Clone)]
+pub struct IdxFile<'a> {
+    header: IdxHeader,
+    data: &'a [u8],
+    /// The amount of bytes of one item
+    item_size: usize,
+}
+
+impl<'a> IdxFile<'a> {
+    pub fn parse(bytes: &'a [u8]) -> Result<IdxFile<'a>> {
+        let header = IdxHeader::parse(bytes)?;
+        let item_size = header
+            .values_per_item()?
+            .checked_mul(header.data_type.size())
+            .context("Idx: the dimensions are too big")?;
+        let expected_size = header
+            .amount_of_items()
+            .checked_mul(item_size)
+            .context("Idx: the dimensions are too big")?;
+        let data = &bytes[header.size()..];
+        ensure!(
+            data.len() == expected_size,
+            "Idx: the dimensions {:?} need {} bytes of {:?} values, but there are {} bytes",
+            header.dimensions,
+            expected_size,
+            header.data_type,
+            data.len()
+        );
+        Ok(IdxFile {
+            header,
+            data,
+            item_size,
+        })
+    }
+
+    pub fn header(&self) -> &IdxHeader {
+        &self.header
+    }
+
+    /// The amount of items
+    pub fn len(&self) -> usize {
+        self.header.amount_of_items()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The item at the index, None when the index is out of bounds
+    pub fn item(&self, index: usize) -> Option<IdxItem<'a>> {
+        if index >= self.len() {
+            return None;
+        }
+        let item_size = self.item_size;
+        Some(IdxItem {
+            data_type: self.header.data_type,
+            bytes: &self.data[index * item_size..(index + 1) * item_size],
+        })
+    }
+
+    /// Iterates over the items, every item is read when the iterator gets to it
+    pub fn items(&self) -> impl ExactSizeIterator<Item = IdxItem<'a>> {
+        let data_type = self.header.data_type;
+        let data = self.data;
+        let item_size = self.item_size;
+        (0..self.len()).map(move |index| IdxItem {
+            data_type,
+            bytes: &data[index * item_size..(index + 1) * item_size],
+        })
+    }
+}
+
+/// One item of an IDX file, for example one image or one label
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IdxItem<'a> {
+    data_type: IdxType,
+    bytes: &'a [u8],
+}
+
+impl<'a> IdxItem<'a> {
+    /// The raw bytes of the values, for unsigned bytes (like the pixels of mnist) this is one
+    /// byte per value
+    pub fn bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    /// The values converted to f64
+    pub fn values(&self) -> impl ExactSizeIterator<Item = f64> + 'a {
+        let data_type = self.data_type;
+        self.bytes
+            .chunks(data_type.size())
+            .map(move |value| data_type.read(value))
+    }
+}
+
+/// Writes an IDX file item by item, the header is written first. finish checks that the
+/// amount of items matches the header.
+#[derive(Debug)]
+pub struct IdxWriter<W: Write> {
+    writer: W,
+    header: IdxHeader,
+    amount_of_written_items: usize,
+}
+
+impl<W: Write> IdxWriter<W> {
+    pub fn new(mut writer: W, header: IdxHeader) -> Result<IdxWriter<W>> {
+        writer
+            .write_all(&header.to_bytes())
+            .context("Idx: cannot write the header")?;
+        Ok(IdxWriter {
+            writer,
+            header,
+            amount_of_written_items: 0,
+        })
+    }
+
+    pub fn write_item(&mut self, values: &[f64]) -> Result<()> {
+        ensure!(
+            self.amount_of_written_items < self.header.amount_of_items(),
+            "Idx: the header has room for {} items",
+            self.header.amount_of_items()
+        );
+        let values_per_item = self.header.values_per_item()?;
+        ensure!(
+            values.len() == values_per_item,
+            "Idx: an item should have {} values, but it has {} values",
+            values_per_item,
+            values.len()
+        );
+        let mut bytes = Vec::with_capacity(values.len() * self.header.data_type.size());
+        for value in values {
+            self.header.data_type.write(*value, &mut bytes)?;
+        }
+        self.writer
+            .write_all(&bytes)
+            .context("Idx: cannot write an item")?;
+        self.amount_of_written_items += 1;
+        Ok(())
+    }
+
+    /// Returns the writer when all items of the header are written
+    pub fn finish(mut self) -> Result<W> {
+        ensure!(
+            self.amount_of_written_items == self.header.amount_of_items(),
+            "Idx: {} of the {} items are written",
+            self.amount_of_written_items,
+            self.header.amount_of_items()
+        );
+        self.writer
+            .flush()
+            .context("Idx: cannot flush the writer")?;
+        Ok(self.writer)
+    }
+}
+
+/// A dataset of two IDX files with the same amount of items, the inputs (for example the
+/// images of mnist) and the labels. The input values are used as they are, the expected output
+/// is 1 for the output neuron of the label and 0 for the other output neurons. Use a transform
+/// of the DataLoader to scale the values.
+#[derive(Debug, Clone)]
+pub struct IdxDataset<'a> {
+    inputs: IdxFile<'a>,
+    labels: IdxFile<'a>,
+    amount_of_classes: usize,
+}
+
+impl<'a> IdxDataset<'a> {
+    pub fn new(
+        inputs: IdxFile<'a>,
+        labels: IdxFile<'a>,
+        amount_of_classes: usize,
+    ) -> Result<IdxDataset<'a>> {
+        ensure!(
+            amount_of_classes > 0,
+            "Idx: there should be at least one class"
+        );
+        ensure!(
+            inputs.len() == labels.len(),
+            "Idx: there are {} inputs and {} labels",
+            inputs.len(),
+            labels.len()
+        );
+        ensure!(
+            labels.header().values_per_item()? == 1,
+            "Idx: a label should be one value"
+        );
+        Ok(IdxDataset {
+            inputs,
+            labels,
+            amount_of_classes,
+        })
+    }
+}
+
+impl<T: Float> Dataset<T> for IdxDataset<'_> {
+    fn len(&self) -> usize {
+        self.inputs.len()
+    }
+
+    fn get(&self, index: usize) -> Result<TrainingEntry<T>> {
+        let (input, label) = self
+            .inputs
+            .item(index)
+            .zip(self.labels.item(index))
+            .with_context(|| {
+                format!(
+                    "Idx: index {} is out of bounds for {} items",
+                    index,
+                    self.inputs.len()
+                )
+            })?;
+        let label = label.values().next().unwrap_or_default();
+        ensure!(
+            label >= 0.0 && label < self.amount_of_classes as f64 && label.fract() == 0.0,
+            "Idx: the label {} should be a class between 0 and {}",
+            label,
+            self.amount_of_classes - 1
+        );
+        let mut expected_output = vec![T::ZERO; self.amount_of_classes];
+        expected_output[label as usize] = T::ONE;
+        Ok(TrainingEntry {
+            input: input.values().map(T::from_f64).collect(),
+            expected_output,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(header: IdxHeader, items: &[&[f64]]) -> Result<Vec<u8>> {
+        let mut writer = IdxWriter::new(Vec::new(), header)?;
+        for item in items {
+            writer.write_item(item)?;
+        }
+        writer.finish()
+    }
+
+    #[test]
+    fn testing_parse() {
+        // two 2 x 2 images of unsigned bytes
+        let bytes = [
+            0, 0, 0x08, 3, 0, 0, 0, 2, 0, 0, 0, 2, 0, 0, 0, 2, 1, 2, 3, 4, 5, 6, 7, 255,
+        ];
+        let file = IdxFile::parse(&bytes).unwrap();
+        assert_eq!(
+            file.header(),
+            &IdxHeader {
+                data_type: IdxType::UnsignedByte,
+                dimensions: vec![2, 2, 2],
+            }
+        );
+        assert_eq!(file.len(), 2);
+        assert_eq!(file.header().values_per_item().unwrap(), 4);
+        assert_eq!(file.item(1).unwrap().bytes(), &[5, 6, 7, 255]);
+        assert_eq!(
+            file.items()
+                .map(|item| item.values().collect())
+                .collect::<Vec<Vec<f64>>>(),
+            vec![vec![1.0, 2.0, 3.0, 4.0], vec![5.0, 6.0, 7.0, 255.0]]
+        );
+        assert!(file.item(2).is_none());
+    }
+
+    #[test]
+    fn write_and_parse_every_data_type() {
+        let items: [&[f64]; 2] = [&[-3.0, 0.0, 100.0], &[1.0, -128.0, 127.0]];
+        for data_type in [
+            IdxType::SignedByte,
+            IdxType::Short,
+            IdxType::Int,
+            IdxType::Float,
+            IdxType::Double,
+        ] {
+            let header = IdxHeader::new(data_type, vec![2, 3]).unwrap();
+            let bytes = write(header.clone(), &items).unwrap();
+            assert_eq!(bytes.len(), header.size() + 6 * data_type.size());
+            let file = IdxFile::parse(&bytes).unwrap();
+            assert_eq!(file.header(), &header);
+            for (item, expected) in file.items().zip(items) {
+                assert_eq!(item.values().collect::<Vec<f64>>(), expected);
+            }
+        }
+        let header = IdxHeader::new(IdxType::Double, vec![1]).unwrap();
+        let bytes = write(header, &[&[0.1]]).unwrap();
+        let file = IdxFile::parse(&bytes).unwrap();
+        assert_eq!(file.item(0).unwrap().values().next(), Some(0.1));
+    }
+
+    #[test]
+    fn testing_parse_ensure() {
+        let error = |bytes: &[u8]| IdxFile::parse(bytes).unwrap_err().to_string();
+        assert_eq!(
+            error(&[0, 0, 0x08]),
+            "Idx: the file is too short for a magic number"
+        );
+        assert_eq!(
+            error(&[0x89, b'P', b'N', b'G']),
+            "Idx: the magic number should start with two zero bytes, this is not an IDX file"
+        );
+        assert_eq!(error(&[0, 0, 0x0A, 1]), "Idx: unknown data type 0x0a");
+        assert_eq!(
+            error(&[0, 0, 0x08, 0]),
+            "Idx: there should be at least one dimension"
+        );
+        assert_eq!(
+            error(&[0, 0, 0x08, 2, 0, 0, 0, 1]),
+            "Idx: the file is too short for the sizes of 2 dimensions"
+        );
+        assert_eq!(
+            error(&[0, 0, 0x0B, 1, 0, 0, 0, 2, 0, 1, 0]),
+            "Idx: the dimensions [2] need 4 bytes of Short values, but there are 3 bytes"
+        );
+        // 1 x 65536 x 65536 x 65536 x 65536 values don't fit in a usize
+        assert_eq!(
+            error(&[0, 0, 0x08, 5, 0, 0, 0, 1, 0, 1, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0]),
+            "Idx: the dimensions are too big"
+        );
+    }
+
+    #[test]
+    fn testing_idx_dataset() {
+        let inputs = write(
+            IdxHeader::new(IdxType::UnsignedByte, vec![2, 2]).unwrap(),
+            &[&[0.0, 255.0], &[10.0, 20.0]],
+        )
+        .unwrap();
+        let labels = write(
+            IdxHeader::new(IdxType::UnsignedByte, vec![2]).unwrap(),
+            &[&[2.0], &[3.0]],
+        )
+        .unwrap();
+        let dataset = IdxDataset::new(
+            IdxFile::parse(&inputs).unwrap(),
+            IdxFile::parse(&labels).unwrap(),
+            3,
+        )
+        .unwrap();
+        assert_eq!(Dataset::<f64>::len(&dataset), 2);
+        let entry: TrainingEntry = dataset.get(0).unwrap();
+        assert_eq!(entry.input, vec![0.0, 255.0]);
+        assert_eq!(entry.expected_output, vec![0.0, 0.0, 1.0]);
+        assert_eq!(
+            Dataset::<f64>::get(&dataset, 1).unwrap_err().to_string(),
+            "Idx: the label 3 should be a class between 0 and 2"
+        );
+        assert_eq!(
+            IdxDataset::new(
+                IdxFile::parse(&inputs).unwrap(),
+                IdxFile::parse(&labels).unwrap(),
+                0,
+            )
+            .unwrap_err()
+            .to_string(),
+            "Idx: there should be at least one class"
+        );
+        assert!(Dataset::<f64>::get(&dataset, 2).is_err());
+        assert!(IdxDataset::new(
+            IdxFile::parse(&inputs).unwrap(),
+            IdxFile::parse(&inputs).unwrap(),
+            3
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn testing_writer_ensure() {
+        let header = IdxHeader::new(IdxType::UnsignedByte, vec![1, 2]).unwrap();
+        let mut writer = IdxWriter::new(Vec::new(), header.clone()).unwrap();
+        assert!(writer.write_item(&[1.0]).is_err());
+        assert!(writer.write_item(&[1.0, 256.0]).is_err());
+        assert!(writer.write_item(&[1.0, 0.5]).is_err());
+        assert!(writer.write_item(&[-1.0, 0.0]).is_err());
+        writer.write_item(&[1.0, 2.0]).unwrap();
+        assert!(writer.write_item(&[1.0, 2.0]).is_err());
+        assert!(IdxWriter::new(Vec::new(), header)
+            .unwrap()
+            .finish()
+            .is_err());
+        assert!(IdxHeader::new(IdxType::UnsignedByte, vec![]).is_err());
+    }
+}
@@ -0,0 +1,4 @@
+pub mod dataloader;
+#[allow(clippy::module_inception)]
+pub mod dataset;
+pub mod idx;
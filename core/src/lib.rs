@@ -1,4 +1,5 @@
 #![crate_type = "lib"]
-pub mod matrix;
+pub mod dataset;
 pub mod logic;
-pub mod neuralnetwork;
\ No newline at end of file
+pub mod matrix;
+pub mod neuralnetwork;
@@ -1,24 +1,31 @@
-use crate::logic::feedforward::Feedforward;
+use crate::logic::feedforward::{Dropout, Feedforward};
+use crate::matrix::float::Float;
 use crate::matrix::matrix::Matrix;
+use crate::matrix::operators::{matmul_transposed_lhs, matmul_transposed_rhs};
 use crate::neuralnetwork::layer::Layer;
+use crate::neuralnetwork::loss::Loss;
+use crate::neuralnetwork::optimizer::LayerGradients;
 use anyhow::{ensure, Context, Result};
 
 #[derive(Debug)]
-pub(crate) struct Backpropagation {
-    pub(crate) new_layers: Vec<Layer>,
+pub(crate) struct Backpropagation<T: Float> {
+    /// The gradients of the weights and biases of every layer, averaged over the batch
+    pub(crate) gradients: Vec<LayerGradients<T>>,
 }
 
 #[derive(Debug)]
-struct ErrorRatePerLayer(Vec<Matrix>);
+struct ErrorRatePerLayer<T: Float>(Vec<Matrix<T>>);
 
-impl Backpropagation {
+impl<T: Float> Backpropagation<T> {
+    /// Calculates the gradients for a batch of inputs. Every column of the target is the
+    /// expected output of the input in the same column of the feedforward. The optimizer
+    /// decides how big the steps in the opposite direction of the gradients are.
     pub(crate) fn run(
-        learning_rate: &f64,
-        layers: &Vec<Layer>,
-        target: &Vec<f64>,
-        feedforward: &Feedforward,
-    ) -> Result<Backpropagation> {
-        let target = Matrix::from_vec(target);
+        loss: &dyn Loss<T>,
+        layers: &[Layer<T>],
+        target: &Matrix<T>,
+        feedforward: &Feedforward<T>,
+    ) -> Result<Backpropagation<T>> {
         let actual = feedforward
             .results
             .last()
@@ -28,13 +35,12 @@ impl Backpropagation {
             "Backpropagation: actual and target should be of same size"
         );
         ensure!(
-            &target.0.len()
-                == &layers
+            target.rows()
+                == layers
                     .last()
                     .context("Backpropagation: layers has no last")?
-                    .0
-                     .0
-                    .len(),
+                    .weights
+                    .rows(),
             "Backpropagation: target and last layer should be of same size"
         );
         ensure!(
@@ -42,20 +48,25 @@ impl Backpropagation {
             "Backpropagation: layers and feedforward should be of same size"
         );
         Ok(Backpropagation {
-            new_layers: new_weights_based_on_error_rate_and_gradient_descent(
-                learning_rate,
+            gradients: calculate_gradients(
+                loss,
                 layers,
                 feedforward,
-                &calculate_error_rate_per_layer(&target, actual, layers)?,
+                &calculate_error_rate_per_layer(
+                    loss,
+                    target,
+                    actual,
+                    layers,
+                    &feedforward.dropout,
+                )?,
             )?,
         })
     }
 }
 
-/// new_weights_based_on_error_rate_and_gradient_descent
-/// This method will returns the updated weights for each layer. With the help of the error rate
-/// and gradient descent. By changing the weights in incremental steps, the neural network will try
-/// to find the minimum error rate.
+/// calculate_gradients
+/// This method returns the gradients of the weights and biases for each layer, with the help of
+/// the error rate of each layer.
 ///
 /// The formula from the book:
 /// ∆Wih = α * Eh * sigmoid (Oh) * (1- sigmoid (Oh)) * OiT
@@ -67,78 +78,124 @@ impl Backpropagation {
 ///  α = learning rate
 ///  Eh = error rate hidden layer
 ///  Oh = output of hidden layer before the activation function
-///  sigmoid () = activation function
-///  sigmoid (Oh) * (1- sigmoid (Oh)) = derivative of activation function
+///  sigmoid () = activation function, every layer can have its own activation function
+///  sigmoid (Oh) * (1- sigmoid (Oh)) = derivative of activation function, see Activation::derivative
 ///  OiT = output of input layer after activation function transposed
-fn new_weights_based_on_error_rate_and_gradient_descent(
-    learning_rate: &f64,
-    layers: &Vec<Layer>,
-    feedforward: &Feedforward,
-    error_rate_per_layer: &ErrorRatePerLayer,
-) -> Result<Vec<Layer>> {
-    let mut new_layers = vec![];
+///
+/// The error rate is target - actual, so ∆Wih moves the weights towards the target. The gradient
+/// points in the opposite direction (towards a bigger error), and the learning rate is applied by
+/// the optimizer:
+/// gradient of Wih = - Eh * sigmoid (Oh) * (1- sigmoid (Oh)) * OiT
+///
+/// The biases are calculated the same way, but without the output of the previous layer,
+/// because a bias is a weight with an input that is always 1:
+/// gradient of Bh = - Eh * sigmoid (Oh) * (1- sigmoid (Oh))
+///
+/// With a batch of inputs every column of Eh and Oh belongs to one input. The matrix
+/// multiplication with OiT adds up the gradients of all inputs, so the gradients are
+/// divided by the batch size to get the average gradient of the batch.
+///
+/// When the loss function is fused with the activation function of the output layer, the
+/// error rate of the output layer already includes the derivative of the activation function.
+///
+/// With dropout Oh is the output before dropout, the error rate of a dropped neuron is already
+/// 0 (see calculate_error_rate_per_layer), so its weights and biases are not changed.
+fn calculate_gradients<T: Float>(
+    loss: &dyn Loss<T>,
+    layers: &[Layer<T>],
+    feedforward: &Feedforward<T>,
+    error_rate_per_layer: &ErrorRatePerLayer<T>,
+) -> Result<Vec<LayerGradients<T>>> {
+    let batch_size = feedforward.results.first().map_or(1, |input| input.cols()) as f64;
+    let factor = T::from_f64(-1.0 / batch_size);
+    let mut gradients = vec![];
     for (i, layer) in layers.iter().enumerate() {
-        let weight_adjustments = learning_rate
-            * ((&error_rate_per_layer.0[i]
-                * &feedforward.results[i + 1].derivative_of_sigmoid())?
-                * &feedforward.results[i].transpose())?;
-        let old_layer_matrix = &layer.0;
-        let new_matrix = (old_layer_matrix + &weight_adjustments)?;
-        new_layers.push(Layer(new_matrix));
+        let gradient = if i == layers.len() - 1 && loss.is_fused_with(&layer.activation) {
+            error_rate_per_layer.0[i].clone()
+        } else {
+            let output = feedforward
+                .dropout_of_layer(i)
+                .map_or(&feedforward.results[i + 1], |dropout| &dropout.output);
+            layer
+                .activation
+                .derivative(output, &error_rate_per_layer.0[i])?
+        };
+        gradients.push(LayerGradients {
+            weights: matmul_transposed_rhs(&gradient, &feedforward.results[i])?
+                .map(|value| factor * value),
+            biases: gradient.sum_per_row().map(|value| factor * value),
+        });
     }
-    Ok(new_layers)
+    Ok(gradients)
 }
 
 /// calculate_error_rate_per_layer:
 /// This method returns the error rate per layer, by calculating the error rate from the
-/// output layer (target - actual). Then going through each layer from the back to the front,
-/// while skipping the input layer. This way you can see how much each layer contributed to
-/// the error rate of the neural network
-fn calculate_error_rate_per_layer(
-    target: &Matrix,
-    actual: &Matrix,
-    layers: &Vec<Layer>,
-) -> Result<ErrorRatePerLayer> {
-    let mut result: Vec<Matrix> = vec![error_rate_from_last_layer(&target, &actual)?];
-    for layer in layers.iter().skip(1).rev() {
+/// output layer with the loss function (target - actual in the book). Then going through
+/// each layer from the back to the front, while skipping the input layer. This way you can
+/// see how much each layer contributed to the error rate of the neural network
+///
+/// The error rate of a layer with dropout is multiplied with the dropout mask, like its output
+/// in feedforward, so a dropped neuron doesn't pass on any error to the layers before it.
+fn calculate_error_rate_per_layer<T: Float>(
+    loss: &dyn Loss<T>,
+    target: &Matrix<T>,
+    actual: &Matrix<T>,
+    layers: &[Layer<T>],
+    dropout: &[Option<Dropout<T>>],
+) -> Result<ErrorRatePerLayer<T>> {
+    let output_layer = layers
+        .last()
+        .context("Backpropagation: layers has no last")?;
+    let mut result: Vec<Matrix<T>> = vec![loss.error(target, actual, &output_layer.activation)?];
+    for (i, layer) in layers.iter().enumerate().skip(1).rev() {
         let result_from_last_processed_layer =
             result.last().context("Backpropagation: No last layer")?;
-        let current_matrix = layer.0.transpose();
-        result.push((current_matrix * result_from_last_processed_layer)?);
+        let error_rate = matmul_transposed_lhs(&layer.weights, result_from_last_processed_layer)?;
+        result.push(match dropout.get(i - 1).and_then(Option::as_ref) {
+            Some(dropout) => error_rate.zip_map(&dropout.mask, |error, mask| error * mask)?,
+            None => error_rate,
+        });
     }
     result.reverse();
     Ok(ErrorRatePerLayer(result))
 }
 
-fn error_rate_from_last_layer(target: &Matrix, actual: &Matrix) -> Result<Matrix> {
-    Ok((target - actual)?)
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::neuralnetwork::neuralnetwork::NeuralNetwork;
+    use crate::neuralnetwork::{
+        activation::Activation,
+        loss::{CategoricalCrossEntropy, MeanSquaredError},
+        neuralnetwork::NeuralNetwork,
+        optimizer::{Optimizer, Sgd},
+    };
 
     #[test]
     fn calculate_error_rate_example_from_the_book() {
         let layers = vec![
             // input to hidden weights
-            Layer(Matrix(vec![vec![0.42, 0.28], vec![0.16, 1.1375]])),
-            Layer(Matrix(vec![
-                vec![0.4333333333333333, 0.7333333333333334],
-                vec![0.1, 0.4],
-            ])),
+            Layer::from(Matrix::from_rows(vec![vec![0.42, 0.28], vec![0.16, 1.1375]]).unwrap()),
+            Layer::from(
+                Matrix::from_rows(vec![
+                    vec![0.4333333333333333, 0.7333333333333334],
+                    vec![0.1, 0.4],
+                ])
+                .unwrap(),
+            ),
         ];
         let actual = calculate_error_rate_per_layer(
-            &Matrix(vec![vec![3.0], vec![3.0]]),
-            &Matrix(vec![vec![1.5], vec![2.5]]),
+            &MeanSquaredError,
+            &Matrix::from_rows(vec![vec![3.0], vec![3.0]]).unwrap(),
+            &Matrix::from_rows(vec![vec![1.5], vec![2.5]]).unwrap(),
             &layers,
+            &[],
         )
         .unwrap()
         .0;
         let expected = vec![
-            Matrix(vec![vec![0.7], vec![1.3]]),
-            Matrix(vec![vec![1.5], vec![0.5]]),
+            Matrix::from_rows(vec![vec![0.7], vec![1.3]]).unwrap(),
+            Matrix::from_rows(vec![vec![1.5], vec![0.5]]).unwrap(),
         ];
         assert_eq!(actual, expected);
     }
@@ -147,226 +204,349 @@ mod tests {
     fn calculate_error_rate_example_with_one_hidden_layers_and_with_different_sizes() {
         let layers = vec![
             // input to hidden weights
-            Layer(Matrix(vec![
-                vec![-0.62091563, -1.25154723, -0.14334102],
-                vec![0.07702094, -0.04681276, 0.62532329],
-                vec![0.64765397, -0.13824144, 0.04829786],
-                vec![0.58148507, -0.63079071, -0.38690071],
-            ])),
+            Layer::from(
+                Matrix::from_rows(vec![
+                    vec![-0.62091563, -1.25154723, -0.14334102],
+                    vec![0.07702094, -0.04681276, 0.62532329],
+                    vec![0.64765397, -0.13824144, 0.04829786],
+                    vec![0.58148507, -0.63079071, -0.38690071],
+                ])
+                .unwrap(),
+            ),
             // hidden to output weights
-            Layer(Matrix(vec![
-                vec![-0.12369625, 0.16732224, -0.02174357, -0.06230827],
-                vec![0.32413962, 0.2632736, -0.01882388, 0.12343084],
-                vec![0.23642887, -0.44747458, 0.14426986, -0.11192151],
-            ])),
+            Layer::from(
+                Matrix::from_rows(vec![
+                    vec![-0.12369625, 0.16732224, -0.02174357, -0.06230827],
+                    vec![0.32413962, 0.2632736, -0.01882388, 0.12343084],
+                    vec![0.23642887, -0.44747458, 0.14426986, -0.11192151],
+                ])
+                .unwrap(),
+            ),
         ];
         let actual = calculate_error_rate_per_layer(
-            &Matrix(vec![vec![1.0], vec![0.0], vec![0.0]]),
-            &Matrix(vec![vec![0.50024943], vec![0.58025132], vec![0.46906931]]),
+            &MeanSquaredError,
+            &Matrix::from_rows(vec![vec![1.0], vec![0.0], vec![0.0]]).unwrap(),
+            &Matrix::from_rows(vec![vec![0.50024943], vec![0.58025132], vec![0.46906931]]).unwrap(),
             &layers,
+            &[],
         )
         .unwrap()
         .0;
         let expected = vec![
-            Matrix(vec![
+            Matrix::from_rows(vec![
                 vec![-0.3608012407286406],
                 vec![0.14075112337566462],
                 vec![-0.0676163439678099],
                 vec![-0.05026055581706461],
-            ]),
-            Matrix(vec![vec![0.49975057], vec![-0.58025132], vec![-0.46906931]]),
+            ])
+            .unwrap(),
+            Matrix::from_rows(vec![vec![0.49975057], vec![-0.58025132], vec![-0.46906931]])
+                .unwrap(),
         ];
         assert_eq!(actual, expected);
     }
 
     #[test]
     fn calculate_error_rate_example_with_multiple_hidden_layers_and_with_different_sizes() {
-        let layers = &vec![
+        let layers = &[
             // input to hidden weights
-            Layer(Matrix(vec![
-                vec![1.1, 2.1, 3.1],
-                vec![1.2, 2.2, 3.2],
-                vec![1.3, 2.3, 3.3],
-                vec![1.4, 2.4, 3.4],
-            ])),
+            Layer::from(
+                Matrix::from_rows(vec![
+                    vec![1.1, 2.1, 3.1],
+                    vec![1.2, 2.2, 3.2],
+                    vec![1.3, 2.3, 3.3],
+                    vec![1.4, 2.4, 3.4],
+                ])
+                .unwrap(),
+            ),
             // hidden to hidden weights
-            Layer(Matrix(vec![
-                vec![1.1, 2.1, 3.1, 4.1],
-                vec![1.2, 2.2, 3.2, 4.2],
-                vec![1.3, 2.3, 3.3, 4.3],
-                vec![1.4, 2.4, 3.4, 4.4],
-            ])),
+            Layer::from(
+                Matrix::from_rows(vec![
+                    vec![1.1, 2.1, 3.1, 4.1],
+                    vec![1.2, 2.2, 3.2, 4.2],
+                    vec![1.3, 2.3, 3.3, 4.3],
+                    vec![1.4, 2.4, 3.4, 4.4],
+                ])
+                .unwrap(),
+            ),
             // hidden to output weights
-            Layer(Matrix(vec![
-                vec![1.1, 2.1, 3.1, 4.1],
-                vec![1.2, 2.2, 3.2, 4.2],
-                vec![1.3, 2.3, 3.3, 4.3],
-            ])),
+            Layer::from(
+                Matrix::from_rows(vec![
+                    vec![1.1, 2.1, 3.1, 4.1],
+                    vec![1.2, 2.2, 3.2, 4.2],
+                    vec![1.3, 2.3, 3.3, 4.3],
+                ])
+                .unwrap(),
+            ),
         ];
         let actual = calculate_error_rate_per_layer(
-            &Matrix(vec![vec![2.0], vec![3.0], vec![6.0]]),
-            &Matrix(vec![
+            &MeanSquaredError,
+            &Matrix::from_rows(vec![vec![2.0], vec![3.0], vec![6.0]]).unwrap(),
+            &Matrix::from_rows(vec![
                 vec![0.9999695369356685],
                 vec![0.9999795788316486],
                 vec![0.9999863105453931],
-            ]),
-            &layers,
+            ])
+            .unwrap(),
+            layers,
+            &[],
         )
         .unwrap()
         .0;
         let expected = vec![
-            Matrix(vec![
+            Matrix::from_rows(vec![
                 vec![114.0008956448172],
                 vec![202.00158633119605],
                 vec![290.0022770175749],
                 vec![378.0029677039537],
-            ]),
-            Matrix(vec![
+            ])
+            .unwrap(),
+            Matrix::from_rows(vec![
                 vec![10.000075811063777],
                 vec![18.000140384751067],
                 vec![26.000204958438356],
                 vec![34.00026953212564],
-            ]),
-            Matrix(vec![
+            ])
+            .unwrap(),
+            Matrix::from_rows(vec![
                 vec![1.0000304630643315],
                 vec![2.0000204211683514],
                 vec![5.000013689454607],
-            ]),
+            ])
+            .unwrap(),
         ];
         assert_eq!(actual, expected);
     }
 
     #[test]
-    fn new_weights_based_on_error_rate_and_gradient_descent_with_multiple_hidden_layers_and_with_different_sizes(
-    ) {
+    fn gradient_descent_with_multiple_hidden_layers_and_with_different_sizes() {
         let layers = vec![
             // input to hidden weights
-            Layer(Matrix(vec![
-                vec![1.1, 2.1, 3.1],
-                vec![1.2, 2.2, 3.2],
-                vec![1.3, 2.3, 3.3],
-                vec![1.4, 2.4, 3.4],
-            ])),
+            Layer::from(
+                Matrix::from_rows(vec![
+                    vec![1.1, 2.1, 3.1],
+                    vec![1.2, 2.2, 3.2],
+                    vec![1.3, 2.3, 3.3],
+                    vec![1.4, 2.4, 3.4],
+                ])
+                .unwrap(),
+            ),
             // hidden to hidden weights
-            Layer(Matrix(vec![
-                vec![1.1, 2.1, 3.1, 4.1],
-                vec![1.2, 2.2, 3.2, 4.2],
-                vec![1.3, 2.3, 3.3, 4.3],
-                vec![1.4, 2.4, 3.4, 4.4],
-            ])),
+            Layer::from(
+                Matrix::from_rows(vec![
+                    vec![1.1, 2.1, 3.1, 4.1],
+                    vec![1.2, 2.2, 3.2, 4.2],
+                    vec![1.3, 2.3, 3.3, 4.3],
+                    vec![1.4, 2.4, 3.4, 4.4],
+                ])
+                .unwrap(),
+            ),
             // hidden to output weights
-            Layer(Matrix(vec![
-                vec![1.1, 2.1, 3.1, 4.1],
-                vec![1.2, 2.2, 3.2, 4.2],
-                vec![1.3, 2.3, 3.3, 4.3],
-            ])),
+            Layer::from(
+                Matrix::from_rows(vec![
+                    vec![1.1, 2.1, 3.1, 4.1],
+                    vec![1.2, 2.2, 3.2, 4.2],
+                    vec![1.3, 2.3, 3.3, 4.3],
+                ])
+                .unwrap(),
+            ),
         ];
         let error_rate_per_layer = calculate_error_rate_per_layer(
-            &Matrix(vec![vec![2.0], vec![3.0], vec![6.0]]),
-            &Matrix(vec![
+            &MeanSquaredError,
+            &Matrix::from_rows(vec![vec![2.0], vec![3.0], vec![6.0]]).unwrap(),
+            &Matrix::from_rows(vec![
                 vec![0.9999695369356685],
                 vec![0.9999795788316486],
                 vec![0.9999863105453931],
-            ]),
+            ])
+            .unwrap(),
             &layers,
+            &[],
         )
         .unwrap();
         let feedforward = Feedforward::run(
             &NeuralNetwork {
                 layers: vec![
                     // input to hidden weights
-                    Layer(Matrix(vec![
-                        vec![1.1, 2.1, 3.1],
-                        vec![1.2, 2.2, 3.2],
-                        vec![1.3, 2.3, 3.3],
-                        vec![1.4, 2.4, 3.4],
-                    ])),
+                    Layer::from(
+                        Matrix::from_rows(vec![
+                            vec![1.1, 2.1, 3.1],
+                            vec![1.2, 2.2, 3.2],
+                            vec![1.3, 2.3, 3.3],
+                            vec![1.4, 2.4, 3.4],
+                        ])
+                        .unwrap(),
+                    ),
                     // hidden to hidden weights
-                    Layer(Matrix(vec![
-                        vec![1.1, 2.1, 3.1, 4.1],
-                        vec![1.2, 2.2, 3.2, 4.2],
-                        vec![1.3, 2.3, 3.3, 4.3],
-                        vec![1.4, 2.4, 3.4, 4.4],
-                    ])),
+                    Layer::from(
+                        Matrix::from_rows(vec![
+                            vec![1.1, 2.1, 3.1, 4.1],
+                            vec![1.2, 2.2, 3.2, 4.2],
+                            vec![1.3, 2.3, 3.3, 4.3],
+                            vec![1.4, 2.4, 3.4, 4.4],
+                        ])
+                        .unwrap(),
+                    ),
                     // hidden to output weights
-                    Layer(Matrix(vec![
-                        vec![1.1, 2.1, 3.1, 4.1],
-                        vec![1.2, 2.2, 3.2, 4.2],
-                        vec![1.3, 2.3, 3.3, 4.3],
-                    ])),
+                    Layer::from(
+                        Matrix::from_rows(vec![
+                            vec![1.1, 2.1, 3.1, 4.1],
+                            vec![1.2, 2.2, 3.2, 4.2],
+                            vec![1.3, 2.3, 3.3, 4.3],
+                        ])
+                        .unwrap(),
+                    ),
                 ],
-                amount_of_input_neurons: 3,
-                amount_of_hidden_neurons: 4,
-                amount_of_output_neurons: 3,
-                amount_of_hidden_layers: 2,
             },
-            &vec![0.1, 0.2, 0.3],
+            &[0.1, 0.2, 0.3],
         )
         .unwrap();
         let expected = vec![
             // input to hidden weights
-            Layer(Matrix(vec![
-                vec![1.6230841920849253, 3.1461683841698505, 4.669252576254776],
-                vec![2.0923728075635353, 3.9847456151270704, 5.8771184226906055],
-                vec![2.5321418269215714, 4.764283653843143, 6.9964254807647155],
-                vec![2.9430477971611615, 5.4860955943223235, 8.029143391483483],
-            ])),
+            Layer {
+                weights: Matrix::from_rows(vec![
+                    vec![1.6230841920849253, 3.1461683841698505, 4.669252576254776],
+                    vec![2.0923728075635353, 3.9847456151270704, 5.8771184226906055],
+                    vec![2.5321418269215714, 4.764283653843143, 6.9964254807647155],
+                    vec![2.9430477971611615, 5.4860955943223235, 8.029143391483483],
+                ])
+                .unwrap(),
+                biases: Matrix::from_rows(vec![
+                    vec![5.230841920849252],
+                    vec![8.923728075635351],
+                    vec![12.321418269215718],
+                    vec![15.430477971611612],
+                ])
+                .unwrap(),
+                activation: Activation::Sigmoid,
+                dropout: 0.0,
+            },
             // hidden to hidden weights
-            Layer(Matrix(vec![
-                vec![
-                    1.1004390858706103,
-                    2.100443958529723,
-                    3.1004486473545705,
-                    4.1004531545908,
-                ],
-                vec![
-                    1.2005683299771444,
-                    2.2005746368943733,
-                    3.2005807058660634,
-                    4.200586539798865,
-                ],
-                vec![
-                    1.3005902929217656,
-                    2.3005968435679534,
-                    3.300603147073267,
-                    4.300609206456684,
-                ],
-                vec![
-                    1.400555047820614,
-                    2.4005612073420246,
-                    3.4005671344788033,
-                    4.40057283206967,
-                ],
-            ])),
+            Layer {
+                weights: Matrix::from_rows(vec![
+                    vec![
+                        1.1004390858706103,
+                        2.100443958529723,
+                        3.1004486473545705,
+                        4.1004531545908,
+                    ],
+                    vec![
+                        1.2005683299771444,
+                        2.2005746368943733,
+                        3.2005807058660634,
+                        4.200586539798865,
+                    ],
+                    vec![
+                        1.3005902929217656,
+                        2.3005968435679534,
+                        3.300603147073267,
+                        4.300609206456684,
+                    ],
+                    vec![
+                        1.400555047820614,
+                        2.4005612073420246,
+                        3.4005671344788033,
+                        4.40057283206967,
+                    ],
+                ])
+                .unwrap(),
+                biases: Matrix::from_rows(vec![
+                    vec![0.0005410575374872607],
+                    vec![0.0007003168138537027],
+                    vec![0.0007273803509156752],
+                    vec![0.0006839500587703672],
+                ])
+                .unwrap(),
+                activation: Activation::Sigmoid,
+                dropout: 0.0,
+            },
             // hidden to output weights
-            Layer(Matrix(vec![
-                vec![
-                    1.1000091372707792,
-                    2.100009137733936,
-                    3.1000091380669783,
-                    4.100009138306454,
-                ],
-                vec![
-                    1.2000122503657362,
-                    2.200012250986692,
-                    3.2000122514332032,
-                    4.200012251754269,
-                ],
-                vec![
-                    1.3000205302530374,
-                    2.3000205312936894,
-                    3.300020532041993,
-                    4.3000205325800644,
-                ],
-            ])),
+            Layer {
+                weights: Matrix::from_rows(vec![
+                    vec![
+                        1.1000091372707792,
+                        2.100009137733936,
+                        3.1000091380669783,
+                        4.100009138306454,
+                    ],
+                    vec![
+                        1.2000122503657362,
+                        2.200012250986692,
+                        3.2000122514332032,
+                        4.200012251754269,
+                    ],
+                    vec![
+                        1.3000205302530374,
+                        2.3000205312936894,
+                        3.300020532041993,
+                        4.3000205325800644,
+                    ],
+                ])
+                .unwrap(),
+                biases: Matrix::from_rows(vec![
+                    vec![9.138919290975304e-6],
+                    vec![1.2252575901072069e-5],
+                    vec![2.0533957028166078e-5],
+                ])
+                .unwrap(),
+                activation: Activation::Sigmoid,
+                dropout: 0.0,
+            },
         ];
-        let actual = new_weights_based_on_error_rate_and_gradient_descent(
-            &0.3,
+        let gradients = calculate_gradients(
+            &MeanSquaredError,
             &layers,
             &feedforward,
             &error_rate_per_layer,
         )
         .unwrap();
+        let mut actual = layers.clone();
+        Sgd::new(0.3).update(&mut actual, &gradients).unwrap();
         assert_eq!(actual, expected);
     }
+
+    #[test]
+    fn calculate_gradients_with_softmax_and_categorical_cross_entropy() {
+        let layers =
+            vec![
+                Layer::from(Matrix::from_rows(vec![vec![0.5, -0.5], vec![0.25, 0.75]]).unwrap())
+                    .with_activation(Activation::Softmax),
+            ];
+        let feedforward = Feedforward::run(
+            &NeuralNetwork {
+                layers: layers.clone(),
+            },
+            &[1.0, 2.0],
+        )
+        .unwrap();
+        let target = Matrix::from_rows(vec![vec![0.0], vec![1.0]]).unwrap();
+        let actual = feedforward.results.last().unwrap();
+        let error_rate_per_layer =
+            calculate_error_rate_per_layer(&CategoricalCrossEntropy, &target, actual, &layers, &[])
+                .unwrap();
+        let gradients = calculate_gradients(
+            &CategoricalCrossEntropy,
+            &layers,
+            &feedforward,
+            &error_rate_per_layer,
+        )
+        .unwrap();
+        // the gradient of softmax combined with cross entropy is actual - target
+        let expected_bias_gradient = (actual - &target).unwrap();
+        assert_eq!(gradients[0].biases, expected_bias_gradient);
+        assert_eq!(
+            gradients[0].weights,
+            Matrix::from_rows(vec![
+                vec![
+                    expected_bias_gradient[(0, 0)],
+                    2.0 * expected_bias_gradient[(0, 0)]
+                ],
+                vec![
+                    expected_bias_gradient[(1, 0)],
+                    2.0 * expected_bias_gradient[(1, 0)]
+                ],
+            ])
+            .unwrap()
+        );
+    }
 }
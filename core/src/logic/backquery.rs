@@ -0,0 +1,134 @@
+use anyhow::{ensure, Result};
+
+use crate::matrix::float::Float;
+use crate::matrix::matrix::Matrix;
+use crate::matrix::operators::matmul_transposed_lhs;
+use crate::neuralnetwork::layer::Layer;
+
+/// The inputs are scaled between 0.01 and 0.99 at the end, the same range as the normalized
+/// pixels of the mnist images in the book
+const INPUT_RANGE: (f64, f64) = (0.01, 0.99);
+
+/// run:
+/// This method runs the neural network in reverse, from a target output to the input that
+/// belongs to it (what the neural network "imagines" for the target). Starting at the output
+/// layer and then going through the whole neural network to the input layer.
+///
+/// The formula for each layer, the feedforward turned around:
+/// Oi = WihT * (inverse activation (Oh) - Bh)
+///
+/// Meaning:
+///  Oi = output of the previous layer
+///  WihT = weights between the previous and the current layer transposed
+///  inverse activation () = input of the activation function, see Activation::inverse
+///  Oh = output of the current layer after the activation function
+///  Bh = biases of the current layer
+///
+/// Oi is not always an output the activation function of the previous layer can give (for
+/// example a value above 1 for sigmoid), so Oi is scaled to the output range of the activation
+/// function before going to the previous layer. The target is clamped to the output range of
+/// the output layer, so a target of exactly 0 or 1 can be used for sigmoid.
+pub(crate) fn run<T: Float>(layers: &[Layer<T>], target: &[T]) -> Result<Matrix<T>> {
+    ensure!(
+        layers
+            .last()
+            .is_some_and(|layer| layer.weights.rows() == target.len()),
+        "Backquery: the target should have the same size as the amount of output neurons"
+    );
+    let mut output = Matrix::from_vec(target);
+    if let Some((min, max)) = layers[layers.len() - 1].activation.output_range() {
+        let (min, max) = (T::from_f64(min), T::from_f64(max));
+        output = output.map(|value| value.clamp(min, max));
+    }
+    for (i, layer) in layers.iter().enumerate().rev() {
+        let input = (&layer.activation.inverse(&output) - &layer.biases)?;
+        let output_of_previous_layer = matmul_transposed_lhs(&layer.weights, &input)?;
+        let range = match i {
+            0 => Some(INPUT_RANGE),
+            _ => layers[i - 1].activation.output_range(),
+        };
+        output = match range {
+            Some(range) => scale(&output_of_previous_layer, range),
+            None => output_of_previous_layer,
+        };
+    }
+    Ok(output)
+}
+
+/// Scales the values linearly, so the lowest value becomes min and the highest value becomes max
+fn scale<T: Float>(matrix: &Matrix<T>, (min, max): (f64, f64)) -> Matrix<T> {
+    let lowest = matrix
+        .values()
+        .fold(f64::INFINITY, |lowest, value| lowest.min(value.to_f64()));
+    let highest = matrix.values().fold(f64::NEG_INFINITY, |highest, value| {
+        highest.max(value.to_f64())
+    });
+    if highest - lowest <= 0.0 {
+        return matrix.map(|_| T::from_f64((min + max) / 2.0));
+    }
+    matrix.map(|value| {
+        T::from_f64((value.to_f64() - lowest) / (highest - lowest) * (max - min) + min)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::neuralnetwork::activation::Activation;
+
+    #[test]
+    fn backquery_with_identity() {
+        let layers = [Layer::from(
+            Matrix::from_rows(vec![vec![1.0, 0.0, 1.0], vec![0.0, 2.0, 1.0]]).unwrap(),
+        )
+        .with_activation(Activation::Identity)];
+        // Oi = WihT * [0.5, 0.25] = [0.5, 0.5, 0.75], then scaled between 0.01 and 0.99
+        assert_eq!(
+            run(&layers, &[0.5, 0.25]).unwrap(),
+            Matrix::from_rows(vec![vec![0.01], vec![0.01], vec![0.99]]).unwrap()
+        );
+    }
+
+    #[test]
+    fn backquery_example_from_the_book() {
+        let layers = [
+            // input to hidden weights
+            Layer::from(
+                Matrix::from_rows(vec![
+                    vec![0.9, 0.3, 0.4],
+                    vec![0.2, 0.8, 0.2],
+                    vec![0.1, 0.5, 0.6],
+                ])
+                .unwrap(),
+            ),
+            // hidden to output weights
+            Layer::from(
+                Matrix::from_rows(vec![
+                    vec![0.3, 0.7, 0.5],
+                    vec![0.6, 0.5, 0.2],
+                    vec![0.8, 0.1, 0.9],
+                ])
+                .unwrap(),
+            ),
+        ];
+        // a target of 0 and 1 is clamped to 0.01 and 0.99, so the logit isn't infinite
+        let input = run(&layers, &[0.0, 1.0, 0.0]).unwrap();
+        assert_eq!(input, run(&layers, &[0.01, 0.99, 0.01]).unwrap());
+        assert_eq!(
+            input,
+            Matrix::from_rows(vec![vec![0.9309424576729508], vec![0.99], vec![0.01]]).unwrap()
+        );
+    }
+
+    #[test]
+    fn backquery_with_a_target_of_the_wrong_size() {
+        let layers = [Layer::from(
+            Matrix::from_rows(vec![vec![1.0, 0.0]]).unwrap(),
+        )];
+        assert_eq!(
+            run(&layers, &[0.5, 0.5]).unwrap_err().to_string(),
+            "Backquery: the target should have the same size as the amount of output neurons"
+        );
+        assert!(run::<f64>(&[], &[]).is_err());
+    }
+}
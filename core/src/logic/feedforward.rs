@@ -1,98 +1,254 @@
 use anyhow::{ensure, Context, Result};
+use rand::Rng;
 
+use crate::matrix::float::Float;
 use crate::matrix::matrix::Matrix;
+use crate::matrix::operators::matrix_multiplication;
 use crate::neuralnetwork::layer::Layer;
 use crate::neuralnetwork::neuralnetwork::NeuralNetwork;
 
 #[derive(Debug, PartialEq)]
-pub(crate) struct Feedforward {
-    pub(crate) results: Vec<Matrix>,
+pub(crate) struct Feedforward<T: Float> {
+    /// The output of every layer, after dropout in training mode
+    pub(crate) results: Vec<Matrix<T>>,
+    /// In training mode the dropout of every layer that has dropout, empty when querying
+    pub(crate) dropout: Vec<Option<Dropout<T>>>,
 }
 
-impl Feedforward {
-    pub(crate) fn run(neural_network: &NeuralNetwork, input_data: &Vec<f64>) -> Result<Feedforward> {
+/// The dropout of one layer in training mode
+#[derive(Debug, PartialEq)]
+pub(crate) struct Dropout<T: Float> {
+    pub(crate) mask: Matrix<T>,
+    /// The output of the layer before the mask was applied, the derivative of the activation
+    /// function is calculated with this output
+    pub(crate) output: Matrix<T>,
+}
+
+/// Querying uses every neuron, training drops neurons with the dropout masks
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Mode<'a, T: Float> {
+    Inference,
+    Training(&'a DropoutMasks<T>),
+}
+
+/// The dropout masks of a batch, a mask has a row per output neuron of the layer and a column
+/// per input of the batch. A dropped neuron has a 0 in the mask, the other neurons have
+/// 1 / (1 - dropout), so the output is on average the same as without dropout (inverted
+/// dropout). Layers without dropout have no mask.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct DropoutMasks<T: Float>(pub(crate) Vec<Option<Matrix<T>>>);
+
+impl<T: Float> DropoutMasks<T> {
+    /// Samples the masks with the random number generator, the rng isn't used when no layer
+    /// has dropout
+    pub(crate) fn sample(
+        layers: &[Layer<T>],
+        batch_size: usize,
+        rng: &mut impl Rng,
+    ) -> Result<DropoutMasks<T>> {
         ensure!(
-            neural_network.amount_of_input_neurons == (input_data.len() as u32),
-            "Feedforward: The input data should have the same size as the amount of input neurons"
+            layers
+                .iter()
+                .all(|layer| (0.0..1.0).contains(&layer.dropout)),
+            "Feedforward: dropout should be between 0 and 1"
         );
-        let feedforward = calculate_results_per_layer(
-            &neural_network.layers,
-            Matrix::from_vec(input_data),
-        )?;
-        ensure!((feedforward.results
-            .get(1)
-            .context("Feedforward: No data in hidden layer")?
+        ensure!(
+            layers.last().is_none_or(|layer| layer.dropout == 0.0),
+            "Feedforward: the output layer should not have dropout"
+        );
+        let masks = layers
+            .iter()
+            .map(|layer| {
+                if layer.dropout == 0.0 {
+                    return Ok(None);
+                }
+                let scale = T::from_f64(1.0 / (1.0 - layer.dropout));
+                let rows = layer.weights.rows();
+                Matrix::new(
+                    rows,
+                    batch_size,
+                    (0..rows * batch_size)
+                        .map(|_| {
+                            if rng.gen_bool(layer.dropout) {
+                                T::ZERO
+                            } else {
+                                scale
+                            }
+                        })
+                        .collect(),
+                )
+                .map(Some)
+            })
+            .collect::<Result<Vec<Option<Matrix<T>>>>>()?;
+        Ok(DropoutMasks(masks))
+    }
+
+    /// The masks of a part of the batch
+    #[cfg(feature = "parallel")]
+    pub(crate) fn columns(&self, first: usize, amount: usize) -> Result<DropoutMasks<T>> {
+        let masks = self
             .0
-            .len() as u32) == neural_network.amount_of_hidden_neurons,
-            "Feedforward: Result of hidden layer should be of same size as amount of hidden neurons"
+            .iter()
+            .map(|mask| {
+                mask.as_ref()
+                    .map(|mask| mask.columns(first, amount))
+                    .transpose()
+            })
+            .collect::<Result<Vec<Option<Matrix<T>>>>>()?;
+        Ok(DropoutMasks(masks))
+    }
+}
+
+impl<T: Float> Feedforward<T> {
+    pub(crate) fn run(
+        neural_network: &NeuralNetwork<T>,
+        input_data: &[T],
+    ) -> Result<Feedforward<T>> {
+        Feedforward::run_batch(neural_network, Matrix::from_vec(input_data))
+    }
+
+    /// Runs a batch of inputs through the neural network at once, every column of the
+    /// input data is one input. The results of every layer have a column per input.
+    pub(crate) fn run_batch(
+        neural_network: &NeuralNetwork<T>,
+        input_data: Matrix<T>,
+    ) -> Result<Feedforward<T>> {
+        Feedforward::run_batch_in_mode(neural_network, input_data, Mode::Inference)
+    }
+
+    pub(crate) fn run_batch_in_mode(
+        neural_network: &NeuralNetwork<T>,
+        input_data: Matrix<T>,
+        mode: Mode<T>,
+    ) -> Result<Feedforward<T>> {
+        ensure!(
+            neural_network.amount_of_input_neurons() == (input_data.rows() as u32),
+            "Feedforward: The input data should have the same size as the amount of input neurons"
         );
+        let feedforward = calculate_results_per_layer(&neural_network.layers, input_data, mode)?;
         ensure!((feedforward.results
             .last()
             .context("Feedforward: no last layer")?
-            .0
-            .len() as u32) == neural_network.amount_of_output_neurons,
+            .rows() as u32) == neural_network.amount_of_output_neurons(),
             "Feedforward: Result of output layer should be of same size as amount of output neurons"
         );
         Ok(feedforward)
     }
+
+    /// The dropout of the layer, None when querying or when the layer has no dropout
+    pub(crate) fn dropout_of_layer(&self, layer: usize) -> Option<&Dropout<T>> {
+        self.dropout.get(layer).and_then(Option::as_ref)
+    }
 }
 
 /// calculate_results_per_layer:
 /// This method returns the result of each layer. Starting at the input data and then going
 /// through the whole neural network. This way you can see how much each layer contributed to
 /// the end result of the neural network
-fn calculate_results_per_layer(layers: &Vec<Layer>, input_data: Matrix) -> Result<Feedforward> {
-    let mut result: Vec<Matrix> = vec![input_data.clone()];
-    for layer in layers {
+///
+/// The formula for each layer:
+/// Oh = activation (Wih * Oi + Bh)
+///
+/// When the input data contains a batch of inputs (one input per column), the biases are added
+/// to every column. In training mode the output of a layer with dropout is multiplied with its
+/// mask: Oh = Oh * Mh
+///
+/// Meaning:
+///  Oh = output of the current layer after the activation function
+///  Wih = weights between the previous and the current layer
+///  Oi = output of the previous layer
+///  Bh = biases of the current layer
+///  Mh = dropout mask of the current layer
+fn calculate_results_per_layer<T: Float>(
+    layers: &[Layer<T>],
+    input_data: Matrix<T>,
+    mode: Mode<T>,
+) -> Result<Feedforward<T>> {
+    let mut result: Vec<Matrix<T>> = vec![input_data.clone()];
+    let mut dropout = vec![];
+    for (i, layer) in layers.iter().enumerate() {
         let result_from_last_layer = if result.is_empty() {
             &input_data
         } else {
             result.last().context("Feedforward: No last layer")?
         };
-        let result_from_current_layer: Matrix =
-            (&layer.0 * result_from_last_layer)?.apply_sigmoid();
-        result.push(result_from_current_layer);
+        let result_from_current_layer: Matrix<T> = layer.activation.apply(
+            &matrix_multiplication(&layer.weights, result_from_last_layer)?
+                .add_to_every_column(&layer.biases)?,
+        );
+        match mode {
+            Mode::Inference => result.push(result_from_current_layer),
+            Mode::Training(masks) => match masks.0.get(i).cloned().flatten() {
+                Some(mask) => {
+                    result.push(
+                        result_from_current_layer.zip_map(&mask, |value, mask| value * mask)?,
+                    );
+                    dropout.push(Some(Dropout {
+                        mask,
+                        output: result_from_current_layer,
+                    }));
+                }
+                None => {
+                    result.push(result_from_current_layer);
+                    dropout.push(None);
+                }
+            },
+        }
     }
-    Ok(Feedforward { results: result })
+    Ok(Feedforward {
+        results: result,
+        dropout,
+    })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::neuralnetwork::activation::Activation;
+    use rand::{rngs::StdRng, SeedableRng};
 
     #[test]
     fn feedforward_example_from_the_book() {
         let input = vec![0.9, 0.1, 0.8];
         let actual_result = calculate_results_per_layer(
-            &vec![
+            &[
                 // input to hidden weights
-                Layer(Matrix(vec![
-                    vec![0.9, 0.3, 0.4],
-                    vec![0.2, 0.8, 0.2],
-                    vec![0.1, 0.5, 0.6],
-                ])),
+                Layer::from(
+                    Matrix::from_rows(vec![
+                        vec![0.9, 0.3, 0.4],
+                        vec![0.2, 0.8, 0.2],
+                        vec![0.1, 0.5, 0.6],
+                    ])
+                    .unwrap(),
+                ),
                 // hidden to output weights
-                Layer(Matrix(vec![
-                    vec![0.3, 0.7, 0.5],
-                    vec![0.6, 0.5, 0.2],
-                    vec![0.8, 0.1, 0.9],
-                ])),
+                Layer::from(
+                    Matrix::from_rows(vec![
+                        vec![0.3, 0.7, 0.5],
+                        vec![0.6, 0.5, 0.2],
+                        vec![0.8, 0.1, 0.9],
+                    ])
+                    .unwrap(),
+                ),
             ],
             Matrix::from_vec(&input),
+            Mode::Inference,
         )
         .unwrap();
         let expected_result = vec![
-            Matrix(vec![vec![0.9], vec![0.1], vec![0.8]]),
-            Matrix(vec![
+            Matrix::from_rows(vec![vec![0.9], vec![0.1], vec![0.8]]).unwrap(),
+            Matrix::from_rows(vec![
                 vec![0.7613327148429104],
                 vec![0.6034832498647263],
                 vec![0.6502185485738271],
-            ]),
-            Matrix(vec![
+            ])
+            .unwrap(),
+            Matrix::from_rows(vec![
                 vec![0.7263033450139793],
                 vec![0.7085980724248232],
                 vec![0.778097059561142],
-            ]),
+            ])
+            .unwrap(),
         ];
         assert_eq!(actual_result.results, expected_result);
     }
@@ -101,58 +257,175 @@ mod tests {
     fn feedforward_my_own_example_with_multiple_hidden_layers_and_with_different_sizes() {
         let input = vec![0.1, 0.2, 0.3];
         let actual_result = calculate_results_per_layer(
-            &vec![
+            &[
                 // input to hidden weights
-                Layer(Matrix(vec![
-                    vec![1.1, 2.1, 3.1],
-                    vec![1.2, 2.2, 3.2],
-                    vec![1.3, 2.3, 3.3],
-                    vec![1.4, 2.4, 3.4],
-                ])),
+                Layer::from(
+                    Matrix::from_rows(vec![
+                        vec![1.1, 2.1, 3.1],
+                        vec![1.2, 2.2, 3.2],
+                        vec![1.3, 2.3, 3.3],
+                        vec![1.4, 2.4, 3.4],
+                    ])
+                    .unwrap(),
+                ),
                 // hidden to hidden weights
-                Layer(Matrix(vec![
-                    vec![1.1, 2.1, 3.1, 4.1],
-                    vec![1.2, 2.2, 3.2, 4.2],
-                    vec![1.3, 2.3, 3.3, 4.3],
-                    vec![1.4, 2.4, 3.4, 4.4],
-                ])),
+                Layer::from(
+                    Matrix::from_rows(vec![
+                        vec![1.1, 2.1, 3.1, 4.1],
+                        vec![1.2, 2.2, 3.2, 4.2],
+                        vec![1.3, 2.3, 3.3, 4.3],
+                        vec![1.4, 2.4, 3.4, 4.4],
+                    ])
+                    .unwrap(),
+                ),
                 // hidden to output weights
-                Layer(Matrix(vec![
-                    vec![1.1, 2.1, 3.1, 4.1],
-                    vec![1.2, 2.2, 3.2, 4.2],
-                    vec![1.3, 2.3, 3.3, 4.3],
-                ])),
+                Layer::from(
+                    Matrix::from_rows(vec![
+                        vec![1.1, 2.1, 3.1, 4.1],
+                        vec![1.2, 2.2, 3.2, 4.2],
+                        vec![1.3, 2.3, 3.3, 4.3],
+                    ])
+                    .unwrap(),
+                ),
             ],
             Matrix::from_vec(&input),
+            Mode::Inference,
         )
         .unwrap();
         let expected_result = vec![
-            Matrix(vec![vec![0.1], vec![0.2], vec![0.3]]),
-            Matrix(vec![
+            Matrix::from_rows(vec![vec![0.1], vec![0.2], vec![0.3]]).unwrap(),
+            Matrix::from_rows(vec![
                 vec![0.8115326747861805],
                 vec![0.8205384805926733],
                 vec![0.8292045179776254],
                 vec![0.8375349374193038],
-            ]),
-            Matrix(vec![
+            ])
+            .unwrap(),
+            Matrix::from_rows(vec![
                 vec![0.9998196163164922],
                 vec![0.9998702958894329],
                 vec![0.9999067381461998],
                 vec![0.9999329421074687],
-            ]),
-            Matrix(vec![
+            ])
+            .unwrap(),
+            Matrix::from_rows(vec![
                 vec![0.9999695369356685],
                 vec![0.9999795788316486],
                 vec![0.9999863105453931],
-            ]),
+            ])
+            .unwrap(),
         ];
         assert_eq!(actual_result.results, expected_result);
     }
 
+    #[test]
+    fn feedforward_with_biases() {
+        let actual_result = calculate_results_per_layer(
+            &[Layer {
+                weights: Matrix::from_rows(vec![vec![0.9, 0.3], vec![0.2, 0.8]]).unwrap(),
+                biases: Matrix::from_rows(vec![vec![0.5], vec![-1.0]]).unwrap(),
+                activation: Activation::Sigmoid,
+                dropout: 0.0,
+            }],
+            Matrix::from_vec(&[0.0, 0.0]),
+            Mode::Inference,
+        )
+        .unwrap();
+        assert_eq!(
+            actual_result.results[1],
+            Matrix::from_rows(vec![vec![0.6224593312018546], vec![0.2689414213699951]]).unwrap()
+        );
+    }
+
+    #[test]
+    fn feedforward_with_different_activations() {
+        let actual_result = calculate_results_per_layer(
+            &[
+                Layer::from(Matrix::from_rows(vec![vec![1.0, -1.0], vec![-1.0, 1.0]]).unwrap())
+                    .with_activation(Activation::Relu),
+                Layer::from(Matrix::from_rows(vec![vec![1.0, 0.0], vec![0.0, 1.0]]).unwrap())
+                    .with_activation(Activation::Softmax),
+            ],
+            Matrix::from_vec(&[0.5, 0.25]),
+            Mode::Inference,
+        )
+        .unwrap();
+        assert_eq!(
+            actual_result.results,
+            vec![
+                Matrix::from_rows(vec![vec![0.5], vec![0.25]]).unwrap(),
+                Matrix::from_rows(vec![vec![0.25], vec![0.0]]).unwrap(),
+                Matrix::from_rows(vec![vec![0.5621765008857981], vec![0.4378234991142019]])
+                    .unwrap(),
+            ]
+        );
+    }
+
+    #[test]
+    fn feedforward_batch_is_the_same_as_feedforward_per_input() {
+        let nn = NeuralNetwork {
+            layers: vec![
+                Layer {
+                    weights: Matrix::from_rows(vec![vec![0.9, 0.3, 0.4], vec![0.2, 0.8, 0.2]])
+                        .unwrap(),
+                    biases: Matrix::from_rows(vec![vec![0.1], vec![-0.2]]).unwrap(),
+                    activation: Activation::Sigmoid,
+                    dropout: 0.0,
+                },
+                Layer::from(Matrix::from_rows(vec![vec![0.3, 0.7], vec![0.6, 0.5]]).unwrap()),
+            ],
+        };
+        let batch = Feedforward::run_batch(
+            &nn,
+            Matrix::from_columns(&[&[0.9, 0.1, 0.8], &[0.1, 0.2, 0.3]]).unwrap(),
+        )
+        .unwrap();
+        let first = Feedforward::run(&nn, &[0.9, 0.1, 0.8]).unwrap();
+        let second = Feedforward::run(&nn, &[0.1, 0.2, 0.3]).unwrap();
+        for (i, result) in batch.results.iter().enumerate() {
+            assert_eq!(
+                result.transpose().to_rows(),
+                vec![
+                    first.results[i].as_slice().to_vec(),
+                    second.results[i].as_slice().to_vec()
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn training_mode_applies_the_dropout_masks() {
+        let nn = NeuralNetwork {
+            layers: vec![
+                Layer::from(Matrix::from_rows(vec![vec![0.9, 0.3], vec![0.2, 0.8]]).unwrap())
+                    .with_dropout(0.5),
+                Layer::from(Matrix::from_rows(vec![vec![0.3, 0.7]]).unwrap()),
+            ],
+        };
+        let input = Matrix::from_vec(&[0.5, 0.25]);
+        let masks = DropoutMasks(vec![
+            Some(Matrix::from_rows(vec![vec![0.0], vec![2.0]]).unwrap()),
+            None,
+        ]);
+        let inference = Feedforward::run_batch(&nn, input.clone()).unwrap();
+        let training = Feedforward::run_batch_in_mode(&nn, input, Mode::Training(&masks)).unwrap();
+        assert!(inference.dropout.is_empty());
+        let dropout = training.dropout_of_layer(0).unwrap();
+        assert_eq!(dropout.output, inference.results[1]);
+        assert_eq!(
+            training.results[1],
+            Matrix::from_rows(vec![vec![0.0], vec![2.0 * inference.results[1][(1, 0)]]]).unwrap()
+        );
+        assert!(training.dropout_of_layer(1).is_none());
+    }
+
     #[test]
     fn test_input_ensure() {
-        let result = Feedforward::run(&NeuralNetwork::new_with_random_values(3, 3, 3, 1), &vec![0.0]);
-        assert_eq!(result.is_err(), true);
+        let result = Feedforward::run(
+            &NeuralNetwork::new_with_random_values(3, 3, 3, 1, &mut StdRng::seed_from_u64(0)),
+            &[0.0],
+        );
+        assert!(result.is_err());
         let error = result.unwrap_err();
         let mut chain = error.chain();
         assert_eq!(
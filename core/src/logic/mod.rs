@@ -1,2 +1,5 @@
+pub(crate) mod backpropagation;
+pub(crate) mod backquery;
 pub(crate) mod feedforward;
-pub(crate) mod backpropagation;
\ No newline at end of file
+#[cfg(feature = "parallel")]
+pub(crate) mod parallel;
@@ -0,0 +1,226 @@
+use std::cell::Cell;
+use std::ops::Range;
+use std::panic;
+use std::thread;
+
+use anyhow::Result;
+
+use crate::matrix::float::Float;
+use crate::neuralnetwork::optimizer::LayerGradients;
+use crate::neuralnetwork::training::BatchMetrics;
+
+/// The average gradients of a batch and the metrics of the batch
+type GradientsAndMetrics<T> = (Vec<LayerGradients<T>>, BatchMetrics);
+
+/// Matrix multiplications and batches with less multiplications than this are faster on one
+/// thread, because starting the threads takes longer than the multiplications
+const MINIMUM_AMOUNT_OF_MULTIPLICATIONS: usize = 1 << 18;
+
+thread_local! {
+    /// Work that already runs on a worker thread isn't split again, so a batch that is split over
+    /// the threads doesn't also split every matrix multiplication over the threads
+    static IS_WORKER: Cell<bool> = const { Cell::new(false) };
+}
+
+/// The amount of threads the work can be split over, this is 1 on a worker thread
+pub(crate) fn amount_of_threads() -> usize {
+    if IS_WORKER.with(Cell::get) {
+        1
+    } else {
+        thread::available_parallelism().map_or(1, |amount| amount.get())
+    }
+}
+
+/// Runs the function for every item on its own thread and returns the results in the same order
+/// as the items. A panic in one of the threads is passed on to the calling thread.
+pub(crate) fn map_in_parallel<T: Send, R: Send>(
+    items: Vec<T>,
+    function: impl Fn(T) -> R + Sync,
+) -> Vec<R> {
+    let function = &function;
+    thread::scope(|scope| {
+        let handles = items
+            .into_iter()
+            .map(|item| {
+                scope.spawn(move || {
+                    IS_WORKER.with(|is_worker| is_worker.set(true));
+                    function(item)
+                })
+            })
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .map(|handle| {
+                handle
+                    .join()
+                    .unwrap_or_else(|error| panic::resume_unwind(error))
+            })
+            .collect()
+    })
+}
+
+/// Splits the rows of the result of a matrix multiplication over the threads, every thread
+/// calculates the values of its own rows with the kernel. Returns false when the
+/// multiplication is too small to split, then the caller should calculate all rows itself.
+pub(crate) fn calculate_rows_in_parallel<T: Float>(
+    amount_of_threads: usize,
+    result: &mut [T],
+    rows: usize,
+    cols: usize,
+    amount_of_multiplications: usize,
+    kernel: &(impl Fn(Range<usize>, &mut [T]) + Sync),
+) -> bool {
+    let amount_of_threads = amount_of_threads.min(rows);
+    if amount_of_threads < 2
+        || cols == 0
+        || amount_of_multiplications < MINIMUM_AMOUNT_OF_MULTIPLICATIONS
+    {
+        return false;
+    }
+    let rows_per_thread = rows.div_ceil(amount_of_threads);
+    map_in_parallel(
+        result
+            .chunks_mut(rows_per_thread * cols)
+            .enumerate()
+            .collect(),
+        |(i, chunk)| {
+            let first_row = i * rows_per_thread;
+            kernel(first_row..first_row + chunk.len() / cols, chunk)
+        },
+    );
+    true
+}
+
+/// Splits the batch over the threads, every thread calculates the average gradients of its
+/// part of the batch. The average of the whole batch is the average of the parts, weighted by
+/// the size of the parts, and the metrics of the parts are added up. Returns None when the
+/// batch is too small to split, the multiplications per entry are the multiplications of the
+/// feedforward and the backpropagation of one entry.
+///
+/// The gradients of the parts are added up in a different order than on one thread, and the
+/// amount of parts depends on the amount of threads (std::thread::available_parallelism). So
+/// the same seed can give slightly different weights (rounding errors of the floats) on
+/// machines with a different amount of CPU cores.
+pub(crate) fn average_gradients_in_parallel<E: Sync, T: Float>(
+    amount_of_threads: usize,
+    batch: &[E],
+    multiplications_per_entry: usize,
+    gradients_of_part: impl Fn(&[E]) -> Result<GradientsAndMetrics<T>> + Sync,
+) -> Option<Result<GradientsAndMetrics<T>>> {
+    let amount_of_threads = amount_of_threads.min(batch.len());
+    if amount_of_threads < 2
+        || batch.len().saturating_mul(multiplications_per_entry) < MINIMUM_AMOUNT_OF_MULTIPLICATIONS
+    {
+        return None;
+    }
+    let parts = batch
+        .chunks(batch.len().div_ceil(amount_of_threads))
+        .collect::<Vec<&[E]>>();
+    let results = map_in_parallel(parts, |part| {
+        gradients_of_part(part).map(|gradients| (part.len(), gradients))
+    });
+    Some(combine_gradients(results, batch.len()))
+}
+
+fn combine_gradients<T: Float>(
+    results: Vec<Result<(usize, GradientsAndMetrics<T>)>>,
+    batch_size: usize,
+) -> Result<GradientsAndMetrics<T>> {
+    let mut combined: Option<Vec<LayerGradients<T>>> = None;
+    let mut combined_metrics = BatchMetrics::default();
+    for result in results {
+        let (part_size, (gradients, metrics)) = result?;
+        combined_metrics = combined_metrics + metrics;
+        let weight = T::from_f64(part_size as f64 / batch_size as f64);
+        let weighted = gradients.into_iter().map(|gradient| LayerGradients {
+            weights: gradient.weights.map(|value| value * weight),
+            biases: gradient.biases.map(|value| value * weight),
+        });
+        combined = Some(match combined {
+            None => weighted.collect(),
+            Some(combined) => combined
+                .into_iter()
+                .zip(weighted)
+                .map(|(sum, gradient)| {
+                    Ok(LayerGradients {
+                        weights: (sum.weights + gradient.weights)?,
+                        biases: (sum.biases + gradient.biases)?,
+                    })
+                })
+                .collect::<Result<Vec<LayerGradients<T>>>>()?,
+        });
+    }
+    Ok((combined.unwrap_or_default(), combined_metrics))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn testing_map_in_parallel() {
+        assert_eq!(
+            map_in_parallel(vec![1, 2, 3], |value| value * 2),
+            vec![2, 4, 6]
+        );
+    }
+
+    #[test]
+    fn testing_calculate_rows_in_parallel() {
+        let (rows, cols) = (7, 1 << 16);
+        let mut result = vec![0.0; rows * cols];
+        let kernel = |rows: Range<usize>, result: &mut [f64]| {
+            for (i, row) in rows.zip(result.chunks_mut(cols)) {
+                row.iter_mut().for_each(|value| *value = i as f64);
+            }
+        };
+        assert!(calculate_rows_in_parallel(
+            3,
+            &mut result,
+            rows,
+            cols,
+            rows * cols,
+            &kernel
+        ));
+        for (i, row) in result.chunks(cols).enumerate() {
+            assert!(row.iter().all(|value| *value == i as f64));
+        }
+        // too small to split
+        assert!(!calculate_rows_in_parallel(
+            3,
+            &mut result,
+            rows,
+            cols,
+            10,
+            &kernel
+        ));
+    }
+
+    #[test]
+    fn small_batches_are_not_split() {
+        let gradients_of_part = |part: &[usize]| -> Result<GradientsAndMetrics<f64>> {
+            Ok((
+                vec![],
+                BatchMetrics {
+                    amount_of_entries: part.len() as u32,
+                    ..BatchMetrics::default()
+                },
+            ))
+        };
+        assert!(average_gradients_in_parallel(4, &[0, 1, 2, 3], 100, gradients_of_part).is_none());
+        let (_, metrics) = average_gradients_in_parallel(
+            4,
+            &[0, 1, 2, 3],
+            MINIMUM_AMOUNT_OF_MULTIPLICATIONS,
+            gradients_of_part,
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(metrics.amount_of_entries, 4);
+    }
+
+    #[test]
+    fn worker_threads_do_not_split_work_again() {
+        assert_eq!(map_in_parallel(vec![()], |_| amount_of_threads()), vec![1]);
+    }
+}
@@ -0,0 +1,127 @@
+use serde::{de::DeserializeOwned, Serialize};
+use std::fmt::{Debug, Display};
+use std::iter::Sum;
+use std::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Sub, SubAssign};
+
+/// The type of the values of a matrix, implemented for f32 and f64. A neural network with f32
+/// values uses half the memory (and half the space when serialized), but is less precise.
+///
+/// The settings of a neural network, like the learning rate or the alpha of an activation
+/// function, are always an f64 and are converted to the float type when they are used.
+pub trait Float:
+    Copy
+    + Debug
+    + Display
+    + Default
+    + PartialEq
+    + PartialOrd
+    + Send
+    + Sync
+    + 'static
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Div<Output = Self>
+    + Neg<Output = Self>
+    + AddAssign
+    + SubAssign
+    + MulAssign
+    + DivAssign
+    + Sum
+    + Serialize
+    + DeserializeOwned
+{
+    const ZERO: Self;
+    const ONE: Self;
+    /// The difference between 1 and the next larger value
+    const EPSILON: Self;
+    const NEG_INFINITY: Self;
+
+    fn from_f64(value: f64) -> Self;
+    fn to_f64(self) -> f64;
+
+    fn exp(self) -> Self;
+    fn ln(self) -> Self;
+    fn ln_1p(self) -> Self;
+    fn tanh(self) -> Self;
+    fn powf(self, exponent: Self) -> Self;
+    fn sqrt(self) -> Self;
+    fn abs(self) -> Self;
+    fn max(self, other: Self) -> Self;
+    fn min(self, other: Self) -> Self;
+    fn clamp(self, min: Self, max: Self) -> Self;
+}
+
+macro_rules! impl_float {
+    ($float:ty) => {
+        impl Float for $float {
+            const ZERO: Self = 0.0;
+            const ONE: Self = 1.0;
+            const EPSILON: Self = <$float>::EPSILON;
+            const NEG_INFINITY: Self = <$float>::NEG_INFINITY;
+
+            fn from_f64(value: f64) -> Self {
+                value as $float
+            }
+
+            fn to_f64(self) -> f64 {
+                self as f64
+            }
+
+            fn exp(self) -> Self {
+                <$float>::exp(self)
+            }
+
+            fn ln(self) -> Self {
+                <$float>::ln(self)
+            }
+
+            fn ln_1p(self) -> Self {
+                <$float>::ln_1p(self)
+            }
+
+            fn tanh(self) -> Self {
+                <$float>::tanh(self)
+            }
+
+            fn powf(self, exponent: Self) -> Self {
+                <$float>::powf(self, exponent)
+            }
+
+            fn sqrt(self) -> Self {
+                <$float>::sqrt(self)
+            }
+
+            fn abs(self) -> Self {
+                <$float>::abs(self)
+            }
+
+            fn max(self, other: Self) -> Self {
+                <$float>::max(self, other)
+            }
+
+            fn min(self, other: Self) -> Self {
+                <$float>::min(self, other)
+            }
+
+            fn clamp(self, min: Self, max: Self) -> Self {
+                <$float>::clamp(self, min, max)
+            }
+        }
+    };
+}
+
+impl_float!(f32);
+impl_float!(f64);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn testing_conversion() {
+        assert_eq!(f32::from_f64(0.1), 0.1_f32);
+        assert_eq!(Float::to_f64(0.5_f32), 0.5);
+        assert_eq!(f64::from_f64(0.1), 0.1);
+    }
+}
@@ -1,112 +1,300 @@
-use rand::Rng;
-use serde::{Deserialize, Serialize};
-
-#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
-pub struct Matrix(pub Vec<Vec<f64>>);
-
-impl Matrix {
-    /// This function creates a matrix with random values an y amount of rows and an x amount of cols
-    pub(crate) fn new_with_random_values(amount_of_rows: u32, amount_of_cols: u32) -> Matrix {
-        let mut rng = rand::thread_rng();
-        let mut rows: Vec<Vec<f64>> = Vec::with_capacity(amount_of_rows as usize);
-        for _ in 0..amount_of_rows {
-            let mut cols: Vec<f64> = Vec::with_capacity(amount_of_cols as usize);
-            for _ in 0..amount_of_cols {
-                let value: f64 = if rng.gen() {
-                    rng.gen::<f64>() * -1.0
-                } else {
-                    rng.gen()
-                };
-                cols.push(value);
-            }
-            rows.push(cols);
+use anyhow::{ensure, Result};
+use serde::{Deserialize, Serialize, Serializer};
+use std::convert::TryFrom;
+use std::ops::{Index, IndexMut};
+
+use super::float::Float;
+
+/// A matrix with all values in one contiguous buffer, row after row (row-major). The value
+/// at (row, col) is stored at row * cols + col.
+///
+/// A matrix is serialized as a list of rows, so the models that were saved when every row
+/// was a separate vector can still be read.
+///
+/// The values are an f64 by default, see Float for f32 values.
+#[derive(Debug, PartialEq, Clone, Deserialize)]
+#[serde(try_from = "Vec<Vec<T>>", bound = "T: Float")]
+pub struct Matrix<T: Float = f64> {
+    rows: usize,
+    cols: usize,
+    data: Vec<T>,
+}
+
+impl<T: Float> Matrix<T> {
+    /// Creates a matrix from the values, row after row
+    pub fn new(rows: usize, cols: usize, data: Vec<T>) -> Result<Matrix<T>> {
+        ensure!(
+            data.len() == rows * cols,
+            "Matrix: a matrix of {} rows and {} cols should have {} values instead of {}",
+            rows,
+            cols,
+            rows * cols,
+            data.len()
+        );
+        Ok(Matrix { rows, cols, data })
+    }
+
+    /// Creates a matrix from a list of rows, all rows should have the same length
+    pub fn from_rows(rows: Vec<Vec<T>>) -> Result<Matrix<T>> {
+        let cols = rows.first().map_or(0, |row| row.len());
+        ensure!(
+            rows.iter().all(|row| row.len() == cols),
+            "Matrix: all rows should be of same size"
+        );
+        Ok(Matrix {
+            rows: rows.len(),
+            cols,
+            data: rows.into_iter().flatten().collect(),
+        })
+    }
+
+    /// This function creates a matrix with an y amount of rows and an x amount of cols, the values
+    /// are created row by row with the function (for example with a random number generator)
+    pub(crate) fn new_with_values(
+        amount_of_rows: u32,
+        amount_of_cols: u32,
+        mut value: impl FnMut() -> f64,
+    ) -> Matrix<T> {
+        let data = (0..amount_of_rows as usize * amount_of_cols as usize)
+            .map(|_| T::from_f64(value()))
+            .collect();
+        Matrix {
+            rows: amount_of_rows as usize,
+            cols: amount_of_cols as usize,
+            data,
+        }
+    }
+
+    pub(crate) fn new_with_zeros(amount_of_rows: u32, amount_of_cols: u32) -> Matrix<T> {
+        Matrix {
+            rows: amount_of_rows as usize,
+            cols: amount_of_cols as usize,
+            data: vec![T::ZERO; amount_of_rows as usize * amount_of_cols as usize],
         }
-        Matrix(rows)
     }
 
     // This function creates a matrix from an one dimensional vector
-    pub(crate) fn from_vec(data: &Vec<f64>) -> Matrix {
-        Matrix(
-            data.into_iter()
-                .map(|value| vec![*value])
-                .collect::<Vec<Vec<f64>>>(),
-        )
+    pub(crate) fn from_vec(data: &[T]) -> Matrix<T> {
+        Matrix {
+            rows: data.len(),
+            cols: 1,
+            data: data.to_vec(),
+        }
     }
 
-    // Check if matrices are of same size
-    pub(crate) fn same_size(&self, matrix: &Matrix) -> bool {
-        if self.0.len() != matrix.0.len() {
-            return false;
+    /// This function creates a matrix where every one dimensional vector becomes a column,
+    /// this is used to stack a batch of inputs into one matrix
+    pub(crate) fn from_columns(columns: &[&[T]]) -> Result<Matrix<T>> {
+        let amount_of_rows = columns.first().map_or(0, |column| column.len());
+        ensure!(
+            columns.iter().all(|column| column.len() == amount_of_rows),
+            "Matrix: all columns should be of same size"
+        );
+        Ok(Matrix {
+            rows: amount_of_rows,
+            cols: columns.len(),
+            data: (0..amount_of_rows)
+                .flat_map(|row| columns.iter().map(move |column| column[row]))
+                .collect(),
+        })
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    /// All values of the matrix, row after row
+    pub fn as_slice(&self) -> &[T] {
+        &self.data
+    }
+
+    /// The values of one row
+    pub fn row(&self, row: usize) -> &[T] {
+        &self.data[row * self.cols..(row + 1) * self.cols]
+    }
+
+    pub(crate) fn row_mut(&mut self, row: usize) -> &mut [T] {
+        &mut self.data[row * self.cols..(row + 1) * self.cols]
+    }
+
+    /// The values of one column, from the first row to the last row
+    pub fn column(&self, col: usize) -> impl Iterator<Item = &T> {
+        assert!(col < self.cols, "Matrix: column {} is out of bounds", col);
+        self.data[col..].iter().step_by(self.cols)
+    }
+
+    /// Copies the amount of columns starting at the first column into a new matrix
+    pub fn columns(&self, first: usize, amount: usize) -> Result<Matrix<T>> {
+        ensure!(
+            first + amount <= self.cols,
+            "Matrix: columns {}..{} are out of bounds",
+            first,
+            first + amount
+        );
+        Ok(Matrix {
+            rows: self.rows,
+            cols: amount,
+            data: (0..self.rows)
+                .flat_map(|row| self.row(row)[first..first + amount].iter().copied())
+                .collect(),
+        })
+    }
+
+    /// Copies the values into a list of rows
+    pub fn to_rows(&self) -> Vec<Vec<T>> {
+        (0..self.rows).map(|row| self.row(row).to_vec()).collect()
+    }
+
+    /// Converts the values to another precision, for example from f64 to f32
+    pub fn to_precision<U: Float>(&self) -> Matrix<U> {
+        Matrix {
+            rows: self.rows,
+            cols: self.cols,
+            data: self
+                .data
+                .iter()
+                .map(|value| U::from_f64(value.to_f64()))
+                .collect(),
         }
-        for (i, values_in_row) in self.0.iter().enumerate() {
-            if matrix.0.get(i).is_some() && values_in_row.len() != matrix.0.get(i).unwrap().len() {
-                return false;
-            }
+    }
+
+    // Check if matrices are of same size
+    pub(crate) fn same_size(&self, matrix: &Matrix<T>) -> bool {
+        self.rows == matrix.rows && self.cols == matrix.cols
+    }
+
+    /// Applies the function on every value of the matrix
+    pub(crate) fn map(&self, function: impl Fn(T) -> T) -> Matrix<T> {
+        Matrix {
+            rows: self.rows,
+            cols: self.cols,
+            data: self.data.iter().map(|value| function(*value)).collect(),
         }
-        return true;
-    }
-
-    /// Sigmoid function:
-    /// A sigmoid function is a mathematical function having a characteristic "S"-shaped curve or sigmoid curve.
-    /// Sigmoid functions have domain of all real numbers, with return value monotonically increasing most often
-    /// from 0 to 1 or alternatively from −1 to 1, depending on convention.
-    pub(crate) fn apply_sigmoid(self) -> Matrix {
-        let (rows, cols) = matrix_rows_and_cols(&self);
-        let mut rows = Vec::with_capacity(rows);
-        for row in self.0.iter() {
-            let mut cols = Vec::with_capacity(cols);
-            for value in row.iter() {
-                cols.push(sigmoid(*value));
-            }
-            rows.push(cols);
+    }
+
+    /// Applies the function on every value of this matrix together with the value at the same
+    /// position in the other matrix
+    pub(crate) fn zip_map(
+        &self,
+        matrix: &Matrix<T>,
+        function: impl Fn(T, T) -> T,
+    ) -> Result<Matrix<T>> {
+        ensure!(
+            self.same_size(matrix),
+            "Matrix: both matrices should be of same size"
+        );
+        Ok(Matrix {
+            rows: self.rows,
+            cols: self.cols,
+            data: self
+                .data
+                .iter()
+                .zip(matrix.data.iter())
+                .map(|(value, other_value)| function(*value, *other_value))
+                .collect(),
+        })
+    }
+
+    /// Iterates over all values of the matrix, row by row
+    pub(crate) fn values(&self) -> impl Iterator<Item = &T> {
+        self.data.iter()
+    }
+
+    /// Iterates mutable over all values of the matrix, row by row
+    pub(crate) fn values_mut(&mut self) -> impl Iterator<Item = &mut T> {
+        self.data.iter_mut()
+    }
+
+    /// Adds the column vector to every column of the matrix, this is used to add the biases
+    /// of a layer to the result of every input in a batch
+    pub(crate) fn add_to_every_column(&self, column: &Matrix<T>) -> Result<Matrix<T>> {
+        ensure!(
+            self.rows == column.rows && column.cols == 1,
+            "Matrix: the column vector should have one column and the same amount of rows as the matrix"
+        );
+        let mut result = self.clone();
+        for row in 0..self.rows {
+            let value = column.data[row];
+            result
+                .row_mut(row)
+                .iter_mut()
+                .for_each(|current| *current += value);
         }
-        Matrix(rows)
+        Ok(result)
     }
 
-    /// Derivative of Sigmoid function = sigmoid(input) * (1 - sigmoid(input))
-    pub(crate) fn derivative_of_sigmoid(&self) -> Matrix {
-        let (rows, cols) = matrix_rows_and_cols(&self);
-        let mut rows = Vec::with_capacity(rows);
-        for row in self.0.iter() {
-            let mut cols = Vec::with_capacity(cols);
-            for value in row.into_iter() {
-                cols.push(value * (1.0 - value));
-            }
-            rows.push(cols);
+    /// Returns a column vector with the sum of every row
+    pub(crate) fn sum_per_row(&self) -> Matrix<T> {
+        Matrix {
+            rows: self.rows,
+            cols: 1,
+            data: (0..self.rows)
+                .map(|row| self.row(row).iter().copied().sum::<T>())
+                .collect(),
         }
-        Matrix(rows)
     }
 
     /// Transpose:
     /// In linear algebra, the transpose of a matrix is an operator which flips a matrix over its diagonal,
     /// that is it switches the row and column indices of the matrix by producing another matrix denoted
     /// as AT (also written A′, Atr, tA or At).
-    pub(crate) fn transpose(&self) -> Matrix {
-        let (self_rows, self_cols) = matrix_rows_and_cols(self);
-        let mut new_matrix = Matrix(vec![vec![0.0; self_rows]; self_cols]);
-        for y in 0..self_rows {
-            for x in 0..self_cols {
-                new_matrix.0[x][y] = self.0[y][x];
+    pub fn transpose(&self) -> Matrix<T> {
+        let mut new_matrix = Matrix::new_with_zeros(self.cols as u32, self.rows as u32);
+        for y in 0..self.rows {
+            for x in 0..self.cols {
+                new_matrix[(x, y)] = self[(y, x)];
             }
         }
         new_matrix
     }
 }
 
-pub(crate) fn matrix_rows_and_cols(matrix: &Matrix) -> (usize, usize) {
-    let rows = matrix.0.len();
-    let cols = if matrix.0.len() <= 0 {
-        0
-    } else {
-        matrix.0[0].len()
-    };
-    (rows, cols)
+impl<T: Float> Index<(usize, usize)> for Matrix<T> {
+    type Output = T;
+
+    fn index(&self, (row, col): (usize, usize)) -> &Self::Output {
+        assert!(
+            row < self.rows && col < self.cols,
+            "Matrix: ({}, {}) is out of bounds",
+            row,
+            col
+        );
+        &self.data[row * self.cols + col]
+    }
 }
 
-fn sigmoid(input: f64) -> f64 {
-    // Euler's number (e)
-    let e: f64 = std::f64::consts::E;
-    1.0 / (1.0 + e.powf(-input))
+impl<T: Float> IndexMut<(usize, usize)> for Matrix<T> {
+    fn index_mut(&mut self, (row, col): (usize, usize)) -> &mut Self::Output {
+        assert!(
+            row < self.rows && col < self.cols,
+            "Matrix: ({}, {}) is out of bounds",
+            row,
+            col
+        );
+        &mut self.data[row * self.cols + col]
+    }
+}
+
+impl<T: Float> TryFrom<Vec<Vec<T>>> for Matrix<T> {
+    type Error = anyhow::Error;
+
+    fn try_from(rows: Vec<Vec<T>>) -> Result<Self, Self::Error> {
+        Matrix::from_rows(rows)
+    }
+}
+
+impl<T: Float> Serialize for Matrix<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq((0..self.rows).map(|row| self.row(row)))
+    }
+}
+
+pub(crate) fn matrix_rows_and_cols<T: Float>(matrix: &Matrix<T>) -> (usize, usize) {
+    (matrix.rows, matrix.cols)
 }
 
 #[cfg(test)]
@@ -115,99 +303,182 @@ mod tests {
 
     #[test]
     fn create_matrix() {
-        let matrix = Matrix::new_with_random_values(3, 3);
-        assert_eq!(matrix.0.len(), 3);
-        assert_eq!(matrix.0[0].len(), 3);
+        let mut counter = 0.0;
+        let matrix: Matrix = Matrix::new_with_values(2, 3, || {
+            counter += 1.0;
+            counter
+        });
+        assert_eq!(
+            matrix,
+            Matrix::from_rows(vec![vec![1.0, 2.0, 3.0], vec![4.0, 5.0, 6.0]]).unwrap()
+        );
     }
 
     #[test]
-    fn testing_transpose() {
-        let matrix_one = Matrix(vec![vec![1.0, 2.0], vec![3.0, 4.0], vec![5.0, 6.0]]);
+    fn create_matrix_with_zeros() {
         assert_eq!(
-            matrix_one.transpose(),
-            Matrix(vec![vec![1.0, 3.0, 5.0], vec![2.0, 4.0, 6.0]])
+            Matrix::new_with_zeros(2, 1),
+            Matrix::from_rows(vec![vec![0.0], vec![0.0]]).unwrap()
         );
     }
 
     #[test]
-    fn testing_sigmoid() {
-        assert_eq!(sigmoid(3.0), 0.9525741268224331);
+    fn create_matrix_from_columns() {
+        assert_eq!(
+            Matrix::from_columns(&[&[1.0, 2.0, 3.0], &[4.0, 5.0, 6.0]]).unwrap(),
+            Matrix::from_rows(vec![vec![1.0, 4.0], vec![2.0, 5.0], vec![3.0, 6.0]]).unwrap()
+        );
+        assert!(Matrix::from_columns(&[&[1.0, 2.0, 3.0], &[4.0, 5.0]]).is_err());
     }
 
     #[test]
-    fn testing_matrix_rows_and_cols() {
-        let (rows, cols) =
-            matrix_rows_and_cols(&Matrix(vec![vec![1.0, 3.0, 5.0], vec![2.0, 4.0, 6.0]]));
-        assert_eq!(rows, 2);
-        assert_eq!(cols, 3);
+    fn testing_add_to_every_column() {
+        let matrix = Matrix::from_rows(vec![vec![1.0, 2.0], vec![3.0, 4.0]]).unwrap();
+        assert_eq!(
+            matrix
+                .add_to_every_column(&Matrix::from_rows(vec![vec![0.5], vec![-1.0]]).unwrap())
+                .unwrap(),
+            Matrix::from_rows(vec![vec![1.5, 2.5], vec![2.0, 3.0]]).unwrap()
+        );
+        assert!(matrix
+            .add_to_every_column(
+                &Matrix::from_rows(vec![vec![0.5], vec![-1.0], vec![1.0]]).unwrap()
+            )
+            .is_err());
     }
 
     #[test]
-    fn testing_same_size_1() {
+    fn testing_sum_per_row() {
         assert_eq!(
-            true,
-            Matrix(vec![vec![1.0, 2.0], vec![3.0, 4.0], vec![5.0, 6.0]]).same_size(&Matrix(vec![
-                vec![7.0, 8.0],
-                vec![9.0, 10.0],
-                vec![11.0, 12.0]
-            ]))
+            Matrix::from_rows(vec![vec![1.0, 2.0], vec![3.0, 4.0]])
+                .unwrap()
+                .sum_per_row(),
+            Matrix::from_rows(vec![vec![3.0], vec![7.0]]).unwrap()
         );
     }
 
     #[test]
-    fn testing_same_size_2() {
+    fn testing_transpose() {
+        let matrix_one =
+            Matrix::from_rows(vec![vec![1.0, 2.0], vec![3.0, 4.0], vec![5.0, 6.0]]).unwrap();
         assert_eq!(
-            false,
-            Matrix(vec![vec![1.0, 2.0], vec![4.0], vec![5.0, 6.0]]).same_size(&Matrix(vec![
-                vec![7.0, 8.0],
-                vec![9.0, 10.0],
-                vec![11.0, 12.0]
-            ]))
+            matrix_one.transpose(),
+            Matrix::from_rows(vec![vec![1.0, 3.0, 5.0], vec![2.0, 4.0, 6.0]]).unwrap()
         );
+    }
 
+    #[test]
+    fn testing_map() {
         assert_eq!(
-            false,
-            Matrix(vec![vec![1.0, 2.0], vec![3.0, 4.0], vec![5.0, 6.0]]).same_size(&Matrix(vec![
-                vec![7.0, 8.0],
-                vec![9.0],
-                vec![11.0, 12.0]
-            ]))
+            Matrix::from_rows(vec![vec![1.0, 2.0], vec![3.0, 4.0]])
+                .unwrap()
+                .map(|value| value * 2.0),
+            Matrix::from_rows(vec![vec![2.0, 4.0], vec![6.0, 8.0]]).unwrap()
         );
+    }
 
+    #[test]
+    fn testing_zip_map() {
+        let matrix = Matrix::from_rows(vec![vec![1.0, 2.0], vec![3.0, 4.0]]).unwrap();
         assert_eq!(
-            false,
-            Matrix(vec![vec![1.0, 2.0], vec![5.0, 6.0]]).same_size(&Matrix(vec![
-                vec![7.0, 8.0],
-                vec![9.0, 10.0],
-                vec![11.0, 12.0]
-            ]))
+            matrix
+                .zip_map(
+                    &Matrix::from_rows(vec![vec![4.0, 3.0], vec![2.0, 1.0]]).unwrap(),
+                    |a, b| a * 10.0 + b
+                )
+                .unwrap(),
+            Matrix::from_rows(vec![vec![14.0, 23.0], vec![32.0, 41.0]]).unwrap()
         );
+        assert!(matrix
+            .zip_map(&Matrix::from_rows(vec![vec![1.0]]).unwrap(), |a, b| a + b)
+            .is_err());
+    }
 
+    #[test]
+    fn testing_columns() {
+        let matrix = Matrix::from_rows(vec![vec![1.0, 2.0, 3.0], vec![4.0, 5.0, 6.0]]).unwrap();
         assert_eq!(
-            false,
-            Matrix(vec![
-                vec![1.0, 2.0],
-                vec![3.0, 4.0],
-                vec![5.0, 6.0]
-            ])
-            .same_size(&Matrix(vec![
-                vec![7.0, 8.0],
-                vec![9.0, 10.0]
-            ]))
+            matrix.columns(1, 2).unwrap(),
+            Matrix::from_rows(vec![vec![2.0, 3.0], vec![5.0, 6.0]]).unwrap()
         );
+        assert!(matrix.columns(2, 2).is_err());
+    }
 
-        assert_eq!(
-            false,
-            Matrix(vec![vec![1.0, 2.0], vec![3.0, 4.0], vec![5.0, 6.0, 7.0]]).same_size(&Matrix(
-                vec![vec![7.0, 8.0], vec![9.0, 10.0], vec![11.0, 12.0]]
-            ))
+    #[test]
+    fn testing_matrix_rows_and_cols() {
+        let (rows, cols) = matrix_rows_and_cols(
+            &Matrix::from_rows(vec![vec![1.0, 3.0, 5.0], vec![2.0, 4.0, 6.0]]).unwrap(),
         );
+        assert_eq!(rows, 2);
+        assert_eq!(cols, 3);
+    }
 
+    #[test]
+    fn testing_same_size_1() {
+        assert!(
+            Matrix::from_rows(vec![vec![1.0, 2.0], vec![3.0, 4.0], vec![5.0, 6.0]])
+                .unwrap()
+                .same_size(
+                    &Matrix::from_rows(vec![vec![7.0, 8.0], vec![9.0, 10.0], vec![11.0, 12.0]])
+                        .unwrap()
+                )
+        );
+    }
+
+    #[test]
+    fn testing_same_size_2() {
+        assert!(!Matrix::from_rows(vec![vec![1.0, 2.0], vec![5.0, 6.0]])
+            .unwrap()
+            .same_size(
+                &Matrix::from_rows(vec![vec![7.0, 8.0], vec![9.0, 10.0], vec![11.0, 12.0]])
+                    .unwrap()
+            ));
+
+        assert!(
+            !Matrix::from_rows(vec![vec![1.0, 2.0], vec![3.0, 4.0], vec![5.0, 6.0]])
+                .unwrap()
+                .same_size(
+                    &Matrix::from_rows(vec![vec![7.0, 8.0, 9.0], vec![10.0, 11.0, 12.0]]).unwrap()
+                )
+        );
+    }
+
+    #[test]
+    fn create_matrix_with_rows_of_different_sizes() {
+        assert!(Matrix::from_rows(vec![vec![1.0, 2.0], vec![4.0], vec![5.0, 6.0]]).is_err());
+        assert!(Matrix::new(2, 2, vec![1.0, 2.0, 3.0]).is_err());
+    }
+
+    #[test]
+    fn testing_index_and_views() {
+        let mut matrix = Matrix::new(2, 3, vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0]).unwrap();
+        assert_eq!(matrix[(1, 0)], 4.0);
+        matrix[(0, 2)] = 9.0;
+        assert_eq!(matrix.row(0), &[1.0, 2.0, 9.0]);
+        assert_eq!(
+            matrix.column(1).copied().collect::<Vec<f64>>(),
+            vec![2.0, 5.0]
+        );
         assert_eq!(
-            false,
-            Matrix(vec![vec![1.0, 2.0], vec![3.0, 4.0], vec![5.0, 6.0]]).same_size(&Matrix(
-                vec![vec![7.0, 8.0], vec![9.0, 10.0], vec![11.0, 12.0, 13.0]]
-            ))
+            matrix.to_rows(),
+            vec![vec![1.0, 2.0, 9.0], vec![4.0, 5.0, 6.0]]
         );
     }
+
+    #[test]
+    #[should_panic]
+    fn index_out_of_bounds() {
+        let matrix: Matrix = Matrix::new_with_zeros(2, 2);
+        // (0, 2) would be (1, 0) when only the position in the buffer was checked
+        let _ = matrix[(0, 2)];
+    }
+
+    #[test]
+    fn serialize_matrix_as_nested_arrays() {
+        let matrix = Matrix::from_rows(vec![vec![1.0, 2.0], vec![3.0, 4.0]]).unwrap();
+        let json = serde_json::to_string(&matrix).unwrap();
+        assert_eq!(json, "[[1.0,2.0],[3.0,4.0]]");
+        assert_eq!(serde_json::from_str::<Matrix>(&json).unwrap(), matrix);
+        assert!(serde_json::from_str::<Matrix>("[[1.0,2.0],[3.0]]").is_err());
+    }
 }
@@ -1,2 +1,4 @@
+pub mod float;
+#[allow(clippy::module_inception)]
 pub mod matrix;
-pub(crate) mod operators;
\ No newline at end of file
+pub(crate) mod operators;
@@ -1,13 +1,17 @@
+use super::float::Float;
 use super::matrix::{matrix_rows_and_cols, Matrix};
-use anyhow::{ensure, Context, Result};
+use anyhow::{ensure, Result};
 use std::fmt;
-use std::ops::{Add, Mul, Sub};
+use std::ops::{Add, Mul, Range, Sub};
+
+#[cfg(feature = "parallel")]
+use crate::logic::parallel;
 
 #[derive(Debug)]
 pub(crate) enum Operator {
-    ADD,
-    SUB,
-    MUL,
+    Add,
+    Sub,
+    Mul,
 }
 
 impl fmt::Display for Operator {
@@ -16,274 +20,473 @@ impl fmt::Display for Operator {
     }
 }
 
-impl Add<Matrix> for Matrix {
-    type Output = Result<Matrix, anyhow::Error>;
+impl<T: Float> Add<Matrix<T>> for Matrix<T> {
+    type Output = Result<Matrix<T>, anyhow::Error>;
 
-    fn add(self, rhs: Matrix) -> Self::Output {
-        apply_operation_on_matrices_of_same_size(&self, &rhs, Operator::ADD)
+    fn add(self, rhs: Matrix<T>) -> Self::Output {
+        apply_operation_on_matrices_of_same_size(&self, &rhs, Operator::Add)
     }
 }
 
-impl Add<&Matrix> for &Matrix {
-    type Output = Result<Matrix, anyhow::Error>;
+impl<T: Float> Add<&Matrix<T>> for &Matrix<T> {
+    type Output = Result<Matrix<T>, anyhow::Error>;
 
-    fn add(self, rhs: &Matrix) -> Self::Output {
-        apply_operation_on_matrices_of_same_size(self, rhs, Operator::ADD)
+    fn add(self, rhs: &Matrix<T>) -> Self::Output {
+        apply_operation_on_matrices_of_same_size(self, rhs, Operator::Add)
     }
 }
 
-impl Sub<Matrix> for Matrix {
-    type Output = Result<Matrix, anyhow::Error>;
+impl<T: Float> Sub<Matrix<T>> for Matrix<T> {
+    type Output = Result<Matrix<T>, anyhow::Error>;
 
-    fn sub(self, rhs: Matrix) -> Self::Output {
-        apply_operation_on_matrices_of_same_size(&self, &rhs, Operator::SUB)
+    fn sub(self, rhs: Matrix<T>) -> Self::Output {
+        apply_operation_on_matrices_of_same_size(&self, &rhs, Operator::Sub)
     }
 }
 
-impl Sub<&Matrix> for &Matrix {
-    type Output = Result<Matrix, anyhow::Error>;
+impl<T: Float> Sub<&Matrix<T>> for &Matrix<T> {
+    type Output = Result<Matrix<T>, anyhow::Error>;
 
-    fn sub(self, rhs: &Matrix) -> Self::Output {
-        apply_operation_on_matrices_of_same_size(self, rhs, Operator::SUB)
+    fn sub(self, rhs: &Matrix<T>) -> Self::Output {
+        apply_operation_on_matrices_of_same_size(self, rhs, Operator::Sub)
     }
 }
 
-impl Mul<Matrix> for Matrix {
-    type Output = Result<Matrix, anyhow::Error>;
+impl<T: Float> Mul<Matrix<T>> for Matrix<T> {
+    type Output = Result<Matrix<T>, anyhow::Error>;
 
-    fn mul(self, rhs: Matrix) -> Self::Output {
-        if self.0.len() == rhs.0.len() && self.0[0].len() == rhs.0[0].len() {
-            apply_operation_on_matrices_of_same_size(&self, &rhs, Operator::MUL)
+    fn mul(self, rhs: Matrix<T>) -> Self::Output {
+        if self.same_size(&rhs) {
+            apply_operation_on_matrices_of_same_size(&self, &rhs, Operator::Mul)
         } else {
             matrix_multiplication(&self, &rhs)
         }
     }
 }
 
-impl Mul<&Matrix> for Matrix {
-    type Output = Result<Matrix, anyhow::Error>;
+impl<T: Float> Mul<&Matrix<T>> for Matrix<T> {
+    type Output = Result<Matrix<T>, anyhow::Error>;
 
-    fn mul(self, rhs: &Matrix) -> Self::Output {
-        if self.0.len() == rhs.0.len() && self.0[0].len() == rhs.0[0].len() {
-            apply_operation_on_matrices_of_same_size(&self, rhs, Operator::MUL)
+    fn mul(self, rhs: &Matrix<T>) -> Self::Output {
+        if self.same_size(rhs) {
+            apply_operation_on_matrices_of_same_size(&self, rhs, Operator::Mul)
         } else {
             matrix_multiplication(&self, rhs)
         }
     }
 }
 
-impl Mul<&Matrix> for &Matrix {
-    type Output = Result<Matrix, anyhow::Error>;
+impl<T: Float> Mul<&Matrix<T>> for &Matrix<T> {
+    type Output = Result<Matrix<T>, anyhow::Error>;
 
-    fn mul(self, rhs: &Matrix) -> Self::Output {
-        if self.0.len() == rhs.0.len() && self.0[0].len() == rhs.0[0].len() {
-            apply_operation_on_matrices_of_same_size(self, rhs, Operator::MUL)
+    fn mul(self, rhs: &Matrix<T>) -> Self::Output {
+        if self.same_size(rhs) {
+            apply_operation_on_matrices_of_same_size(self, rhs, Operator::Mul)
         } else {
             matrix_multiplication(self, rhs)
         }
     }
 }
 
-impl Mul<Matrix> for &f64 {
-    type Output = Matrix;
+macro_rules! impl_scalar_multiplication {
+    ($float:ty) => {
+        impl Mul<Matrix<$float>> for &$float {
+            type Output = Matrix<$float>;
 
-    fn mul(self, rhs: Matrix) -> Self::Output {
-        let (amount_of_rows, amount_of_cols) = matrix_rows_and_cols(&rhs);
-        let mut rows: Vec<Vec<f64>> = Vec::with_capacity(amount_of_rows as usize);
-        for i in 0..amount_of_rows {
-            let mut cols: Vec<f64> = Vec::with_capacity(amount_of_cols as usize);
-            for j in 0..amount_of_cols {
-                cols.push(self * rhs.0[i][j])
+            fn mul(self, rhs: Matrix<$float>) -> Self::Output {
+                rhs.map(|value| self * value)
             }
-            rows.push(cols);
         }
-        Matrix(rows)
-    }
+    };
 }
 
-fn apply_operation_on_matrices_of_same_size(
-    matrix_one: &Matrix,
-    matrix_two: &Matrix,
+impl_scalar_multiplication!(f32);
+impl_scalar_multiplication!(f64);
+
+fn apply_operation_on_matrices_of_same_size<T: Float>(
+    matrix_one: &Matrix<T>,
+    matrix_two: &Matrix<T>,
     operation: Operator,
-) -> Result<Matrix> {
+) -> Result<Matrix<T>> {
     ensure!(
-        matrix_one.0.len() == matrix_two.0.len(),
+        matrix_one.rows() == matrix_two.rows(),
         format!(
             "Matrix {}: the row size of both matrices should be the same",
             operation
         )
     );
-    let mut rows: Vec<Vec<f64>> = Vec::with_capacity(matrix_one.0.len());
-    for (i, row) in matrix_one.0.iter().enumerate() {
-        ensure!(
-            row.len()
-                == matrix_two
-                    .0
-                    .get(i)
-                    .context(format!("Matrix {}: cannot retrieve row", operation))?
-                    .len(),
-            format!(
-                "Matrix {}: the column size of both matrices should be the same",
-                operation
-            )
-        );
-        let mut cols: Vec<f64> = Vec::with_capacity(row.len());
-        for (j, current) in row.iter().enumerate() {
-            match operation {
-                Operator::ADD => cols.push(current + matrix_two.0[i][j]),
-                Operator::SUB => cols.push(current - matrix_two.0[i][j]),
-                Operator::MUL => cols.push(current * matrix_two.0[i][j]),
-            }
-        }
-        rows.push(cols);
-    }
-    Ok(Matrix(rows))
+    ensure!(
+        matrix_one.cols() == matrix_two.cols(),
+        format!(
+            "Matrix {}: the column size of both matrices should be the same",
+            operation
+        )
+    );
+    matrix_one.zip_map(matrix_two, |current, other| match operation {
+        Operator::Add => current + other,
+        Operator::Sub => current - other,
+        Operator::Mul => current * other,
+    })
 }
 
+/// The matrices are multiplied in blocks of BLOCK_SIZE x BLOCK_SIZE values, so the values
+/// of a block are still in the cache when they are used again
+const BLOCK_SIZE: usize = 64;
+
 /// Matrix Multiplication:
 /// In mathematics, particularly in linear algebra, matrix multiplication is a binary operation that produces a matrix from two matrices.
 /// For matrix multiplication, the number of columns in the first matrix must be equal to the number of rows in the second matrix.
 /// The resulting matrix, known as the matrix product, has the number of rows of the first and the number of columns of the second matrix.
 /// The product of matrices A and B is denoted as AB.
-fn matrix_multiplication(matrix_one: &Matrix, matrix_two: &Matrix) -> Result<Matrix> {
-    let (matrix_one_rows, matrix_one_cols) = matrix_rows_and_cols(&matrix_one);
-    let (matrix_two_rows, matrix_two_cols) = matrix_rows_and_cols(&matrix_two);
+///
+/// The Mul operator does an element wise multiplication when both matrices have the same size,
+/// so use this function when the matrix product is needed, for example with a batch of inputs.
+///
+/// Every value of the result is still the sum of A[i][k] * B[k][j] from the first k to the last
+/// k, the blocks only change the order in which the values of the result are calculated.
+pub(crate) fn matrix_multiplication<T: Float>(
+    matrix_one: &Matrix<T>,
+    matrix_two: &Matrix<T>,
+) -> Result<Matrix<T>> {
+    let (matrix_one_rows, matrix_one_cols) = matrix_rows_and_cols(matrix_one);
+    let (matrix_two_rows, matrix_two_cols) = matrix_rows_and_cols(matrix_two);
     ensure!(
             matrix_one_cols == matrix_two_rows,
             "Matrix: the number of columns in the first matrix must be equal to the number of rows in the second matrix"
         );
-    let mut new_matrix = Matrix(vec![vec![0.0; matrix_two_cols]; matrix_one_rows]);
-    for i in 0..matrix_one_rows {
-        for j in 0..matrix_two_cols {
-            let mut k = 0;
-            loop {
-                if k < matrix_one_cols && k < matrix_two_rows {
-                    new_matrix.0[i][j] += matrix_one.0[i][k] * matrix_two.0[k][j];
-                } else {
-                    break;
+    let (a, b) = (matrix_one.as_slice(), matrix_two.as_slice());
+    let kernel = |rows: Range<usize>, result: &mut [T]| {
+        for i_block in rows.clone().step_by(BLOCK_SIZE) {
+            let i_end = (i_block + BLOCK_SIZE).min(rows.end);
+            for k_block in (0..matrix_one_cols).step_by(BLOCK_SIZE) {
+                let k_end = (k_block + BLOCK_SIZE).min(matrix_one_cols);
+                for j_block in (0..matrix_two_cols).step_by(BLOCK_SIZE) {
+                    let j_end = (j_block + BLOCK_SIZE).min(matrix_two_cols);
+                    for i in i_block..i_end {
+                        let start = (i - rows.start) * matrix_two_cols;
+                        let result_row = &mut result[start + j_block..start + j_end];
+                        for k in k_block..k_end {
+                            let value = a[i * matrix_one_cols + k];
+                            let row =
+                                &b[k * matrix_two_cols + j_block..k * matrix_two_cols + j_end];
+                            for (current, other) in result_row.iter_mut().zip(row) {
+                                *current += value * *other;
+                            }
+                        }
+                    }
                 }
-                k += 1;
             }
         }
+    };
+    Matrix::new(
+        matrix_one_rows,
+        matrix_two_cols,
+        calculate_rows(matrix_one_rows, matrix_two_cols, matrix_one_cols, kernel),
+    )
+}
+
+/// Calculates AT * B without creating the transposed copy of A, this is used in backpropagation
+/// to send the error of a layer back through the weights (WT * E).
+///
+/// Every value of the result is the sum of A[k][i] * B[k][j], so both A and B are read row by row.
+pub(crate) fn matmul_transposed_lhs<T: Float>(
+    matrix_one: &Matrix<T>,
+    matrix_two: &Matrix<T>,
+) -> Result<Matrix<T>> {
+    let (matrix_one_rows, matrix_one_cols) = matrix_rows_and_cols(matrix_one);
+    let (matrix_two_rows, matrix_two_cols) = matrix_rows_and_cols(matrix_two);
+    ensure!(
+        matrix_one_rows == matrix_two_rows,
+        "Matrix: the number of rows in the first matrix must be equal to the number of rows in the second matrix"
+    );
+    let (a, b) = (matrix_one.as_slice(), matrix_two.as_slice());
+    let kernel = |rows: Range<usize>, result: &mut [T]| {
+        for i_block in rows.clone().step_by(BLOCK_SIZE) {
+            let i_end = (i_block + BLOCK_SIZE).min(rows.end);
+            for k_block in (0..matrix_one_rows).step_by(BLOCK_SIZE) {
+                let k_end = (k_block + BLOCK_SIZE).min(matrix_one_rows);
+                for j_block in (0..matrix_two_cols).step_by(BLOCK_SIZE) {
+                    let j_end = (j_block + BLOCK_SIZE).min(matrix_two_cols);
+                    for i in i_block..i_end {
+                        let start = (i - rows.start) * matrix_two_cols;
+                        let result_row = &mut result[start + j_block..start + j_end];
+                        for k in k_block..k_end {
+                            let value = a[k * matrix_one_cols + i];
+                            let row =
+                                &b[k * matrix_two_cols + j_block..k * matrix_two_cols + j_end];
+                            for (current, other) in result_row.iter_mut().zip(row) {
+                                *current += value * *other;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    };
+    Matrix::new(
+        matrix_one_cols,
+        matrix_two_cols,
+        calculate_rows(matrix_one_cols, matrix_two_cols, matrix_one_rows, kernel),
+    )
+}
+
+/// Calculates A * BT without creating the transposed copy of B, this is used in backpropagation
+/// to calculate the gradient of the weights (E * OT).
+///
+/// Every value of the result is the sum of A[i][k] * B[j][k], so it is the dot product of a row
+/// of A and a row of B.
+pub(crate) fn matmul_transposed_rhs<T: Float>(
+    matrix_one: &Matrix<T>,
+    matrix_two: &Matrix<T>,
+) -> Result<Matrix<T>> {
+    let (matrix_one_rows, matrix_one_cols) = matrix_rows_and_cols(matrix_one);
+    let (matrix_two_rows, matrix_two_cols) = matrix_rows_and_cols(matrix_two);
+    ensure!(
+        matrix_one_cols == matrix_two_cols,
+        "Matrix: the number of columns in the first matrix must be equal to the number of columns in the second matrix"
+    );
+    let kernel = |rows: Range<usize>, result: &mut [T]| {
+        for i_block in rows.clone().step_by(BLOCK_SIZE) {
+            let i_end = (i_block + BLOCK_SIZE).min(rows.end);
+            for j_block in (0..matrix_two_rows).step_by(BLOCK_SIZE) {
+                let j_end = (j_block + BLOCK_SIZE).min(matrix_two_rows);
+                for i in i_block..i_end {
+                    let row_one = matrix_one.row(i);
+                    for j in j_block..j_end {
+                        result[(i - rows.start) * matrix_two_rows + j] = row_one
+                            .iter()
+                            .zip(matrix_two.row(j))
+                            .fold(T::ZERO, |sum, (value, other)| sum + *value * *other);
+                    }
+                }
+            }
+        }
+    };
+    Matrix::new(
+        matrix_one_rows,
+        matrix_two_rows,
+        calculate_rows(matrix_one_rows, matrix_two_rows, matrix_one_cols, kernel),
+    )
+}
+
+/// Creates the values of the result and lets the kernel calculate the rows of the result. The
+/// kernel gets the rows it should calculate and the values of only those rows. With the parallel
+/// feature the rows of a large multiplication are split over multiple threads.
+#[cfg_attr(not(feature = "parallel"), allow(unused_variables))]
+fn calculate_rows<T: Float>(
+    rows: usize,
+    cols: usize,
+    inner_size: usize,
+    kernel: impl Fn(Range<usize>, &mut [T]) + Sync,
+) -> Vec<T> {
+    let mut result = vec![T::ZERO; rows * cols];
+    #[cfg(feature = "parallel")]
+    if parallel::calculate_rows_in_parallel(
+        parallel::amount_of_threads(),
+        &mut result,
+        rows,
+        cols,
+        rows * cols * inner_size,
+        &kernel,
+    ) {
+        return result;
     }
-    Ok(new_matrix)
+    kernel(0..rows, &mut result);
+    result
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
 
     #[test]
     fn testing_add() {
-        let matrix_one = Matrix(vec![
+        let matrix_one = Matrix::from_rows(vec![
             vec![0.1, 0.2, 0.3],
             vec![0.4, 0.5, 0.6],
             vec![0.7, 0.8, 0.9],
-        ]);
-        let matrix_two = Matrix(vec![
+        ])
+        .unwrap();
+        let matrix_two = Matrix::from_rows(vec![
             vec![0.1, 0.2, 0.3],
             vec![0.4, 0.5, 0.6],
             vec![0.7, 0.8, 0.9],
-        ]);
+        ])
+        .unwrap();
         assert_eq!(
             &(&matrix_one + &matrix_two).unwrap(),
-            &Matrix(vec![
+            &Matrix::from_rows(vec![
                 vec![0.2, 0.4, 0.6],
                 vec![0.8, 1.0, 1.2],
                 vec![1.4, 1.6, 1.8],
             ])
+            .unwrap()
         );
         assert_eq!(
             (matrix_one + matrix_two).unwrap(),
-            Matrix(vec![
+            Matrix::from_rows(vec![
                 vec![0.2, 0.4, 0.6],
                 vec![0.8, 1.0, 1.2],
                 vec![1.4, 1.6, 1.8],
             ])
+            .unwrap()
         );
     }
 
     #[test]
     fn testing_sub() {
-        let matrix_one = Matrix(vec![
+        let matrix_one = Matrix::from_rows(vec![
             vec![0.1, 0.2, 0.3],
             vec![0.4, 0.5, 0.6],
             vec![0.7, 0.8, 0.9],
-        ]);
-        let matrix_two = Matrix(vec![
+        ])
+        .unwrap();
+        let matrix_two = Matrix::from_rows(vec![
             vec![0.1, 0.2, 0.3],
             vec![0.4, 0.5, 0.6],
             vec![0.7, 0.8, 0.9],
-        ]);
+        ])
+        .unwrap();
         assert_eq!(
             &(&matrix_one - &matrix_two).unwrap(),
-            &Matrix(vec![
+            &Matrix::from_rows(vec![
                 vec![0.0, 0.0, 0.0],
                 vec![0.0, 0.0, 0.0],
                 vec![0.0, 0.0, 0.0],
             ])
+            .unwrap()
         );
         assert_eq!(
             (matrix_one - matrix_two).unwrap(),
-            Matrix(vec![
+            Matrix::from_rows(vec![
                 vec![0.0, 0.0, 0.0],
                 vec![0.0, 0.0, 0.0],
                 vec![0.0, 0.0, 0.0],
             ])
+            .unwrap()
         );
     }
 
     #[test]
     fn testing_mul() {
-        let matrix_one = Matrix(vec![
+        let matrix_one = Matrix::from_rows(vec![
             vec![0.1, 0.2, 0.3],
             vec![0.4, 0.5, 0.6],
             vec![0.7, 0.8, 0.9],
-        ]);
-        let matrix_two = Matrix(vec![
+        ])
+        .unwrap();
+        let matrix_two = Matrix::from_rows(vec![
             vec![0.1, 0.2, 0.3],
             vec![0.4, 0.5, 0.6],
             vec![0.7, 0.8, 0.9],
-        ]);
+        ])
+        .unwrap();
         assert_eq!(
             &(&matrix_one * &matrix_two).unwrap(),
-            &Matrix(vec![
+            &Matrix::from_rows(vec![
                 vec![0.010000000000000002, 0.04000000000000001, 0.09],
                 vec![0.16000000000000003, 0.25, 0.36],
                 vec![0.48999999999999994, 0.6400000000000001, 0.81],
             ])
+            .unwrap()
         );
         assert_eq!(
             (matrix_one * matrix_two).unwrap(),
-            Matrix(vec![
+            Matrix::from_rows(vec![
                 vec![0.010000000000000002, 0.04000000000000001, 0.09],
                 vec![0.16000000000000003, 0.25, 0.36],
                 vec![0.48999999999999994, 0.6400000000000001, 0.81],
             ])
+            .unwrap()
         );
     }
 
     #[test]
     fn testing_matrix_multiplication() {
-        let matrix_one = Matrix(vec![
+        let matrix_one = Matrix::from_rows(vec![
             vec![1.0, 0.0, 1.0],
             vec![2.0, 1.0, 1.0],
             vec![0.0, 1.0, 1.0],
             vec![1.0, 1.0, 2.0],
-        ]);
-        let matrix_two = Matrix(vec![
+        ])
+        .unwrap();
+        let matrix_two = Matrix::from_rows(vec![
             vec![1.0, 2.0, 1.0],
             vec![2.0, 3.0, 1.0],
             vec![4.0, 2.0, 2.0],
-        ]);
+        ])
+        .unwrap();
         assert_eq!(
             (matrix_one * matrix_two).unwrap(),
-            Matrix(vec![
+            Matrix::from_rows(vec![
                 vec![5.0, 4.0, 3.0],
                 vec![8.0, 9.0, 5.0],
                 vec![6.0, 5.0, 3.0],
                 vec![11.0, 9.0, 6.0],
             ])
+            .unwrap()
+        );
+    }
+
+    /// The matrix multiplication before it was split into blocks
+    fn naive_matrix_multiplication(matrix_one: &Matrix, matrix_two: &Matrix) -> Matrix {
+        let mut new_matrix =
+            Matrix::new_with_zeros(matrix_one.rows() as u32, matrix_two.cols() as u32);
+        for i in 0..matrix_one.rows() {
+            for j in 0..matrix_two.cols() {
+                for k in 0..matrix_one.cols() {
+                    new_matrix[(i, j)] += matrix_one[(i, k)] * matrix_two[(k, j)];
+                }
+            }
+        }
+        new_matrix
+    }
+
+    fn random_matrix(rows: u32, cols: u32, rng: &mut StdRng) -> Matrix {
+        Matrix::new_with_values(rows, cols, || rng.gen_range(-1.0..1.0))
+    }
+
+    fn assert_almost_equal(actual: &Matrix, expected: &Matrix) {
+        assert!(actual.same_size(expected));
+        for (actual_value, expected_value) in actual.values().zip(expected.values()) {
+            assert!((actual_value - expected_value).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn matrix_multiplication_of_mnist_sized_layer() {
+        // larger than a block in every direction and not a multiple of the block size
+        let mut rng = StdRng::seed_from_u64(0);
+        let weights = random_matrix(100, 784, &mut rng);
+        let inputs = random_matrix(784, 70, &mut rng);
+        assert_almost_equal(
+            &matrix_multiplication(&weights, &inputs).unwrap(),
+            &naive_matrix_multiplication(&weights, &inputs),
+        );
+    }
+
+    #[test]
+    fn testing_matmul_transposed_lhs() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let weights = random_matrix(100, 130, &mut rng);
+        let errors = random_matrix(100, 70, &mut rng);
+        assert_almost_equal(
+            &matmul_transposed_lhs(&weights, &errors).unwrap(),
+            &naive_matrix_multiplication(&weights.transpose(), &errors),
+        );
+        assert!(matmul_transposed_lhs(&weights, &Matrix::new_with_zeros(99, 70)).is_err());
+    }
+
+    #[test]
+    fn testing_matmul_transposed_rhs() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let errors = random_matrix(100, 70, &mut rng);
+        let inputs = random_matrix(130, 70, &mut rng);
+        assert_almost_equal(
+            &matmul_transposed_rhs(&errors, &inputs).unwrap(),
+            &naive_matrix_multiplication(&errors, &inputs.transpose()),
         );
+        assert!(matmul_transposed_rhs(&errors, &Matrix::new_with_zeros(130, 69)).is_err());
     }
 }
@@ -0,0 +1,364 @@
+use anyhow::{ensure, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::matrix::{
+    float::Float,
+    matrix::{matrix_rows_and_cols, Matrix},
+};
+
+/// The activation function of a layer, it is applied to the weighted input of every neuron in
+/// the layer. Every activation function has a matching derivative that is used during
+/// backpropagation. The derivatives are calculated from the output of the activation function,
+/// so the feedforward only has to remember the output of each layer.
+#[derive(Debug, PartialEq, Clone, Copy, Default, Serialize, Deserialize)]
+pub enum Activation {
+    /// 1 / (1 + e^-x), output between 0 and 1
+    #[default]
+    Sigmoid,
+    /// (e^x - e^-x) / (e^x + e^-x), output between -1 and 1
+    Tanh,
+    /// max(0, x)
+    Relu,
+    /// x when x > 0, otherwise alpha * x
+    LeakyRelu { alpha: f64 },
+    /// x when x > 0, otherwise alpha * (e^x - 1)
+    Elu { alpha: f64 },
+    /// ln(1 + e^x), a smooth version of relu
+    Softplus,
+    /// x, the input is passed through unchanged
+    Identity,
+    /// e^x / sum(e^x) over all neurons of the layer, the outputs add up to 1
+    Softmax,
+}
+
+impl Activation {
+    /// Applies the activation function on every value of the matrix. Softmax is applied per
+    /// column, because every column is the output of the layer for one input.
+    pub(crate) fn apply<T: Float>(&self, input: &Matrix<T>) -> Matrix<T> {
+        match self {
+            Activation::Sigmoid => input.map(sigmoid),
+            Activation::Tanh => input.map(T::tanh),
+            Activation::Relu => input.map(|x| x.max(T::ZERO)),
+            Activation::LeakyRelu { alpha } => {
+                let alpha = T::from_f64(*alpha);
+                input.map(|x| if x > T::ZERO { x } else { alpha * x })
+            }
+            Activation::Elu { alpha } => {
+                let alpha = T::from_f64(*alpha);
+                input.map(|x| {
+                    if x > T::ZERO {
+                        x
+                    } else {
+                        alpha * (x.exp() - T::ONE)
+                    }
+                })
+            }
+            Activation::Softplus => input.map(|x| x.max(T::ZERO) + (-x.abs()).exp().ln_1p()),
+            Activation::Identity => input.clone(),
+            Activation::Softmax => softmax(input),
+        }
+    }
+
+    /// Multiplies the error of the layer with the derivative of the activation function,
+    /// with the output of the layer (after the activation function) as input.
+    ///
+    /// Sigmoid = output * (1 - output)
+    /// Tanh = 1 - output²
+    /// Relu = 1 when output > 0, otherwise 0
+    /// LeakyRelu = 1 when output > 0, otherwise alpha
+    /// Elu = 1 when output > 0, otherwise output + alpha
+    /// Softplus = sigmoid(input) = 1 - e^-output
+    /// Identity = 1
+    /// Softmax = output * (error - sum(output * error)), the jacobian of softmax applied to the error
+    pub(crate) fn derivative<T: Float>(
+        &self,
+        output: &Matrix<T>,
+        error: &Matrix<T>,
+    ) -> Result<Matrix<T>> {
+        ensure!(
+            output.same_size(error),
+            "Activation: output and error should be of same size"
+        );
+        let derivative = match self {
+            Activation::Sigmoid => output.map(|a| a * (T::ONE - a)),
+            Activation::Tanh => output.map(|a| T::ONE - a * a),
+            Activation::Relu => output.map(|a| if a > T::ZERO { T::ONE } else { T::ZERO }),
+            Activation::LeakyRelu { alpha } => {
+                let alpha = T::from_f64(*alpha);
+                output.map(|a| if a > T::ZERO { T::ONE } else { alpha })
+            }
+            Activation::Elu { alpha } => {
+                let alpha = T::from_f64(*alpha);
+                output.map(|a| if a > T::ZERO { T::ONE } else { a + alpha })
+            }
+            Activation::Softplus => output.map(|a| T::ONE - (-a).exp()),
+            Activation::Identity => output.map(|_| T::ONE),
+            Activation::Softmax => return Ok(derivative_of_softmax(output, error)),
+        };
+        error * &derivative
+    }
+
+    /// Calculates the input of the activation function from the output, this is used by
+    /// backquery to run the neural network in reverse.
+    ///
+    /// Sigmoid = ln(output / (1 - output)), the logit
+    /// Tanh = ln((1 + output) / (1 - output)) / 2
+    /// Relu = output, the input of an output of 0 could be any negative value so it stays 0
+    /// LeakyRelu = output when output > 0, otherwise output / alpha
+    /// Elu = output when output > 0, otherwise ln(output / alpha + 1)
+    /// Softplus = ln(e^output - 1)
+    /// Identity = output
+    /// Softmax = ln(output), softmax only depends on the differences between the inputs, so
+    /// this is one of the inputs that give the output
+    ///
+    /// An output outside of the output_range of the activation function has no input, the
+    /// inverse of those values is not a number.
+    pub(crate) fn inverse<T: Float>(&self, output: &Matrix<T>) -> Matrix<T> {
+        let two = T::from_f64(2.0);
+        match self {
+            Activation::Sigmoid => output.map(|a| (a / (T::ONE - a)).ln()),
+            Activation::Tanh => output.map(|a| ((T::ONE + a) / (T::ONE - a)).ln() / two),
+            Activation::Relu | Activation::Identity => output.clone(),
+            Activation::LeakyRelu { alpha } => {
+                let alpha = T::from_f64(*alpha);
+                output.map(|a| if a > T::ZERO { a } else { a / alpha })
+            }
+            Activation::Elu { alpha } => {
+                let alpha = T::from_f64(*alpha);
+                output.map(|a| {
+                    if a > T::ZERO {
+                        a
+                    } else {
+                        (a / alpha + T::ONE).ln()
+                    }
+                })
+            }
+            Activation::Softplus => output.map(|a| (a.exp() - T::ONE).ln()),
+            Activation::Softmax => output.map(T::ln),
+        }
+    }
+
+    /// The range the values are scaled to before the inverse is calculated during backquery,
+    /// so every value has an inverse. The book scales the outputs of sigmoid between 0.01
+    /// and 0.99. None when every value has an inverse.
+    pub(crate) fn output_range(&self) -> Option<(f64, f64)> {
+        match self {
+            Activation::Sigmoid | Activation::Softmax | Activation::Relu | Activation::Softplus => {
+                Some((0.01, 0.99))
+            }
+            Activation::Tanh => Some((-0.99, 0.99)),
+            Activation::Elu { alpha } => Some((-0.99 * alpha, 0.99)),
+            Activation::LeakyRelu { .. } | Activation::Identity => None,
+        }
+    }
+}
+
+fn sigmoid<T: Float>(input: T) -> T {
+    // Euler's number (e)
+    let e = T::from_f64(std::f64::consts::E);
+    T::ONE / (T::ONE + e.powf(-input))
+}
+
+/// Softmax per column, the maximum of the column is subtracted before taking the exponent
+/// so large inputs don't overflow
+fn softmax<T: Float>(input: &Matrix<T>) -> Matrix<T> {
+    let (rows, cols) = matrix_rows_and_cols(input);
+    let mut result = Matrix::new_with_zeros(rows as u32, cols as u32);
+    for col in 0..cols {
+        let max = (0..rows)
+            .map(|row| input[(row, col)])
+            .fold(T::NEG_INFINITY, T::max);
+        let sum = (0..rows)
+            .map(|row| (input[(row, col)] - max).exp())
+            .sum::<T>();
+        for row in 0..rows {
+            result[(row, col)] = (input[(row, col)] - max).exp() / sum;
+        }
+    }
+    result
+}
+
+fn derivative_of_softmax<T: Float>(output: &Matrix<T>, error: &Matrix<T>) -> Matrix<T> {
+    let (rows, cols) = matrix_rows_and_cols(output);
+    let mut result = Matrix::new_with_zeros(rows as u32, cols as u32);
+    for col in 0..cols {
+        let dot = (0..rows)
+            .map(|row| output[(row, col)] * error[(row, col)])
+            .sum::<T>();
+        for row in 0..rows {
+            result[(row, col)] = output[(row, col)] * (error[(row, col)] - dot);
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn testing_sigmoid() {
+        assert_eq!(sigmoid(3.0), 0.9525741268224331);
+    }
+
+    #[test]
+    fn testing_apply() {
+        let input = Matrix::from_rows(vec![vec![-2.0], vec![0.0], vec![3.0]]).unwrap();
+        assert_eq!(
+            Activation::Relu.apply(&input),
+            Matrix::from_rows(vec![vec![0.0], vec![0.0], vec![3.0]]).unwrap()
+        );
+        assert_eq!(
+            Activation::LeakyRelu { alpha: 0.1 }.apply(&input),
+            Matrix::from_rows(vec![vec![-0.2], vec![0.0], vec![3.0]]).unwrap()
+        );
+        assert_eq!(
+            Activation::Elu { alpha: 1.0 }.apply(&input),
+            Matrix::from_rows(vec![vec![-0.8646647167633873], vec![0.0], vec![3.0]]).unwrap()
+        );
+        assert_eq!(
+            Activation::Tanh.apply(&input),
+            Matrix::from_rows(vec![
+                vec![-0.9640275800758169],
+                vec![0.0],
+                vec![0.9950547536867305]
+            ])
+            .unwrap()
+        );
+        assert_eq!(
+            Activation::Softplus.apply(&input),
+            Matrix::from_rows(vec![
+                vec![0.1269280110429725],
+                vec![std::f64::consts::LN_2],
+                vec![3.048587351573742]
+            ])
+            .unwrap()
+        );
+        assert_eq!(Activation::Identity.apply(&input), input);
+    }
+
+    #[test]
+    fn inverse_gives_the_input_back() {
+        let input = Matrix::from_rows(vec![vec![-2.0], vec![-0.5], vec![0.25], vec![3.0]]).unwrap();
+        for activation in [
+            Activation::Sigmoid,
+            Activation::Tanh,
+            Activation::LeakyRelu { alpha: 0.1 },
+            Activation::Elu { alpha: 1.0 },
+            Activation::Softplus,
+            Activation::Identity,
+        ] {
+            let inverse = activation.inverse(&activation.apply(&input));
+            for (actual, expected) in inverse.values().zip(input.values()) {
+                assert!((actual - expected).abs() < 1e-12, "{activation:?}");
+            }
+        }
+        // the inputs of softmax are only known up to a constant
+        let inverse = Activation::Softmax.inverse(&Activation::Softmax.apply(&input));
+        let difference = inverse[(0, 0)] - input[(0, 0)];
+        for (actual, expected) in inverse.values().zip(input.values()) {
+            assert!((actual - expected - difference).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn testing_softmax() {
+        let output = Activation::Softmax.apply(
+            &Matrix::from_rows(vec![
+                vec![1.0, 1000.0],
+                vec![2.0, 1000.0],
+                vec![3.0, 1000.0],
+            ])
+            .unwrap(),
+        );
+        assert_eq!(
+            output,
+            Matrix::from_rows(vec![
+                vec![0.09003057317038046, 0.3333333333333333],
+                vec![0.24472847105479764, 0.3333333333333333],
+                vec![0.6652409557748218, 0.3333333333333333],
+            ])
+            .unwrap()
+        );
+    }
+
+    #[test]
+    fn testing_derivative() {
+        let output = Matrix::from_rows(vec![vec![0.5], vec![0.0], vec![-0.5]]).unwrap();
+        let error = Matrix::from_rows(vec![vec![2.0], vec![2.0], vec![2.0]]).unwrap();
+        assert_eq!(
+            Activation::Sigmoid.derivative(&output, &error).unwrap(),
+            Matrix::from_rows(vec![vec![0.5], vec![0.0], vec![-1.5]]).unwrap()
+        );
+        assert_eq!(
+            Activation::Tanh.derivative(&output, &error).unwrap(),
+            Matrix::from_rows(vec![vec![1.5], vec![2.0], vec![1.5]]).unwrap()
+        );
+        assert_eq!(
+            Activation::Relu.derivative(&output, &error).unwrap(),
+            Matrix::from_rows(vec![vec![2.0], vec![0.0], vec![0.0]]).unwrap()
+        );
+        assert_eq!(
+            Activation::LeakyRelu { alpha: 0.1 }
+                .derivative(&output, &error)
+                .unwrap(),
+            Matrix::from_rows(vec![vec![2.0], vec![0.2], vec![0.2]]).unwrap()
+        );
+        assert_eq!(
+            Activation::Elu { alpha: 1.0 }
+                .derivative(&output, &error)
+                .unwrap(),
+            Matrix::from_rows(vec![vec![2.0], vec![2.0], vec![1.0]]).unwrap()
+        );
+        assert_eq!(
+            Activation::Identity.derivative(&output, &error).unwrap(),
+            error
+        );
+    }
+
+    #[test]
+    fn testing_derivative_of_softmax() {
+        // the outputs of softmax add up to 1, so an error that is the same for every
+        // neuron doesn't change anything
+        let output = Matrix::from_rows(vec![vec![0.2], vec![0.3], vec![0.5]]).unwrap();
+        assert_eq!(
+            Activation::Softmax
+                .derivative(
+                    &output,
+                    &Matrix::from_rows(vec![vec![1.0], vec![1.0], vec![1.0]]).unwrap()
+                )
+                .unwrap(),
+            Matrix::from_rows(vec![vec![0.0], vec![0.0], vec![0.0]]).unwrap()
+        );
+        assert_eq!(
+            Activation::Softmax
+                .derivative(
+                    &output,
+                    &Matrix::from_rows(vec![vec![1.0], vec![0.0], vec![0.0]]).unwrap()
+                )
+                .unwrap(),
+            Matrix::from_rows(vec![vec![0.16000000000000003], vec![-0.06], vec![-0.1]]).unwrap()
+        );
+    }
+
+    #[test]
+    fn testing_derivative_ensure() {
+        let result = Activation::Sigmoid.derivative(
+            &Matrix::from_rows(vec![vec![0.5], vec![0.5]]).unwrap(),
+            &Matrix::from_rows(vec![vec![0.5]]).unwrap(),
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn serialize_activation() {
+        assert_eq!(
+            serde_json::to_string(&Activation::Sigmoid).unwrap(),
+            r#""Sigmoid""#
+        );
+        assert_eq!(
+            serde_json::from_str::<Activation>(r#"{"LeakyRelu":{"alpha":0.01}}"#).unwrap(),
+            Activation::LeakyRelu { alpha: 0.01 }
+        );
+    }
+}
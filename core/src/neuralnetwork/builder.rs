@@ -0,0 +1,221 @@
+use anyhow::{ensure, Result};
+use rand::Rng;
+
+use super::{
+    activation::Activation, initializer::Initializer, layer::Layer, neuralnetwork::NeuralNetwork,
+};
+use crate::matrix::float::Float;
+
+/// Builds a neural network layer by layer. Every layer can have its own amount of neurons
+/// and its own activation function, for example: 784 → 256 → 64 → 10
+///
+/// ```
+/// use rand::{rngs::StdRng, SeedableRng};
+/// use core::neuralnetwork::{
+///     activation::Activation, builder::NeuralNetworkBuilder, neuralnetwork::NeuralNetwork,
+/// };
+///
+/// let neural_network: NeuralNetwork = NeuralNetworkBuilder::new(784)
+///     .layer(256, Activation::Relu)
+///     .layer(64, Activation::Relu)
+///     .layer(10, Activation::Softmax)
+///     .build(&mut StdRng::seed_from_u64(42))
+///     .unwrap();
+/// assert_eq!(neural_network.layer_sizes(), vec![784, 256, 64, 10]);
+/// ```
+///
+/// A network with only an output layer (so without hidden layers) is plain logistic regression.
+#[derive(Debug, Clone)]
+pub struct NeuralNetworkBuilder {
+    amount_of_input_neurons: u32,
+    initializer: Initializer,
+    layers: Vec<(u32, Activation, Option<Initializer>)>,
+    /// The dropout and the amount of layers that were added before it
+    dropout: Vec<(usize, f64)>,
+}
+
+impl NeuralNetworkBuilder {
+    pub fn new(amount_of_input_neurons: u32) -> NeuralNetworkBuilder {
+        NeuralNetworkBuilder {
+            amount_of_input_neurons,
+            initializer: Initializer::default(),
+            layers: vec![],
+            dropout: vec![],
+        }
+    }
+
+    /// Sets the initializer of the weights of every layer that doesn't have its own
+    /// initializer, this is LeCun by default
+    pub fn initializer(mut self, initializer: Initializer) -> NeuralNetworkBuilder {
+        self.initializer = initializer;
+        self
+    }
+
+    /// Adds a layer of neurons, the last layer that is added is the output layer
+    pub fn layer(mut self, amount_of_neurons: u32, activation: Activation) -> NeuralNetworkBuilder {
+        self.layers.push((amount_of_neurons, activation, None));
+        self
+    }
+
+    /// Adds a layer of neurons with its own initializer for the weights, for example He for a
+    /// relu layer and Xavier for a sigmoid layer
+    pub fn layer_with_initializer(
+        mut self,
+        amount_of_neurons: u32,
+        activation: Activation,
+        initializer: Initializer,
+    ) -> NeuralNetworkBuilder {
+        self.layers
+            .push((amount_of_neurons, activation, Some(initializer)));
+        self
+    }
+
+    /// Drops the fraction (between 0 and 1) of the output neurons of the last added layer during
+    /// training, this should come after a hidden layer
+    pub fn dropout(mut self, dropout: f64) -> NeuralNetworkBuilder {
+        self.dropout.push((self.layers.len(), dropout));
+        self
+    }
+
+    /// Builds the neural network with random weights from the random number generator, the
+    /// float type of the values is inferred
+    pub fn build<T: Float>(self, rng: &mut impl Rng) -> Result<NeuralNetwork<T>> {
+        ensure!(
+            self.amount_of_input_neurons > 0,
+            "NeuralNetworkBuilder: the amount of input neurons should be greater than 0"
+        );
+        ensure!(
+            !self.layers.is_empty(),
+            "NeuralNetworkBuilder: the neural network should have at least an output layer"
+        );
+        ensure!(
+            self.layers
+                .iter()
+                .all(|(amount_of_neurons, _, _)| *amount_of_neurons > 0),
+            "NeuralNetworkBuilder: the amount of neurons of every layer should be greater than 0"
+        );
+        ensure!(
+            self.dropout
+                .iter()
+                .all(|(index, _)| *index > 0 && *index < self.layers.len()),
+            "NeuralNetworkBuilder: dropout should come after a hidden layer"
+        );
+        ensure!(
+            self.dropout
+                .iter()
+                .all(|(_, dropout)| (0.0..1.0).contains(dropout)),
+            "NeuralNetworkBuilder: dropout should be between 0 and 1"
+        );
+        let mut amount_of_neurons_in_previous_layer = self.amount_of_input_neurons;
+        let mut layers = Vec::with_capacity(self.layers.len());
+        for (amount_of_neurons, activation, initializer) in self.layers {
+            let initializer = initializer.unwrap_or(self.initializer);
+            layers.push(
+                Layer::from(initializer.weights(
+                    amount_of_neurons,
+                    amount_of_neurons_in_previous_layer,
+                    rng,
+                ))
+                .with_activation(activation),
+            );
+            amount_of_neurons_in_previous_layer = amount_of_neurons;
+        }
+        for (index, dropout) in self.dropout {
+            layers[index - 1].dropout = dropout;
+        }
+        Ok(NeuralNetwork { layers })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn build_neural_network_with_different_layer_sizes() {
+        let nn: NeuralNetwork = NeuralNetworkBuilder::new(5)
+            .layer(4, Activation::Relu)
+            .layer(3, Activation::Tanh)
+            .layer(2, Activation::Softmax)
+            .build(&mut StdRng::seed_from_u64(0))
+            .unwrap();
+        assert_eq!(nn.layer_sizes(), vec![5, 4, 3, 2]);
+        assert_eq!(nn.amount_of_input_neurons(), 5);
+        assert_eq!(nn.amount_of_output_neurons(), 2);
+        assert_eq!(
+            nn.layers
+                .iter()
+                .map(|layer| layer.activation)
+                .collect::<Vec<Activation>>(),
+            vec![Activation::Relu, Activation::Tanh, Activation::Softmax]
+        );
+        assert_eq!(nn.layers[0].weights.rows(), 4);
+        assert_eq!(nn.layers[0].weights.cols(), 5);
+        assert_eq!(nn.layers[2].weights.rows(), 2);
+        assert_eq!(nn.layers[2].weights.cols(), 3);
+    }
+
+    #[test]
+    fn build_neural_network_without_hidden_layers() {
+        let nn: NeuralNetwork = NeuralNetworkBuilder::new(3)
+            .layer(1, Activation::Sigmoid)
+            .build(&mut StdRng::seed_from_u64(0))
+            .unwrap();
+        assert_eq!(nn.layers.len(), 1);
+        assert_eq!(nn.layer_sizes(), vec![3, 1]);
+    }
+
+    #[test]
+    fn build_neural_network_without_layers() {
+        let result: Result<NeuralNetwork> =
+            NeuralNetworkBuilder::new(3).build(&mut StdRng::seed_from_u64(0));
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "NeuralNetworkBuilder: the neural network should have at least an output layer"
+        );
+    }
+
+    #[test]
+    fn build_neural_network_with_empty_layer() {
+        let result: Result<NeuralNetwork> = NeuralNetworkBuilder::new(3)
+            .layer(0, Activation::Sigmoid)
+            .layer(2, Activation::Sigmoid)
+            .build(&mut StdRng::seed_from_u64(0));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn build_neural_network_with_an_initializer_per_layer() {
+        let nn: NeuralNetwork = NeuralNetworkBuilder::new(3)
+            .initializer(Initializer::Constant(0.5))
+            .layer_with_initializer(4, Activation::Relu, Initializer::Zeros)
+            .layer(2, Activation::Sigmoid)
+            .build(&mut StdRng::seed_from_u64(0))
+            .unwrap();
+        assert!(nn.layers[0].weights.values().all(|value| *value == 0.0));
+        assert!(nn.layers[1].weights.values().all(|value| *value == 0.5));
+        assert!(nn.layers[1].biases.values().all(|value| *value == 0.0));
+    }
+
+    #[test]
+    fn build_neural_network_with_dropout() {
+        let nn: NeuralNetwork = NeuralNetworkBuilder::new(3)
+            .layer(4, Activation::Relu)
+            .dropout(0.5)
+            .layer(2, Activation::Sigmoid)
+            .build(&mut StdRng::seed_from_u64(0))
+            .unwrap();
+        assert_eq!(nn.layers[0].dropout, 0.5);
+        assert_eq!(nn.layers[1].dropout, 0.0);
+        let result: Result<NeuralNetwork> = NeuralNetworkBuilder::new(3)
+            .layer(4, Activation::Relu)
+            .layer(2, Activation::Sigmoid)
+            .dropout(0.5)
+            .build(&mut StdRng::seed_from_u64(0));
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "NeuralNetworkBuilder: dropout should come after a hidden layer"
+        );
+    }
+}
@@ -0,0 +1,197 @@
+use anyhow::{ensure, Result};
+use serde::{Deserialize, Serialize};
+
+use super::optimizer::LayerGradients;
+use crate::matrix::float::Float;
+
+/// Gradient clipping keeps the updates of the weights and biases small when the gradients
+/// explode, for example in a deep neural network with unbounded activations. By default the
+/// gradients are not clipped.
+///
+/// The gradients are clipped after the gradients of L1 and L2 regularization are added and
+/// before the optimizer updates the weights and biases. Clipping by value happens first, after
+/// that all gradients are scaled down together when their global norm is too large:
+/// global norm = √(Σ gradient²) of the weights and biases of all layers
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct GradientClipping {
+    /// Every gradient is clipped to be between -value and value, this can change the direction
+    /// of the gradients
+    pub value: Option<f64>,
+    /// When the global norm is larger, all gradients are multiplied by max_norm / global norm,
+    /// the direction of the gradients stays the same
+    pub global_norm: Option<f64>,
+}
+
+/// The lengths (L2 norm) of the gradients of one batch, before they are clipped
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct GradientNorms {
+    /// The norm of the gradients of the weights and biases of every layer
+    pub layers: Vec<f64>,
+    /// The norm of the gradients of all layers together
+    pub global: f64,
+    /// True when at least one gradient is changed by clipping
+    pub clipped: bool,
+}
+
+impl GradientNorms {
+    pub(crate) fn of<T: Float>(gradients: &[LayerGradients<T>]) -> GradientNorms {
+        let layers: Vec<f64> = gradients
+            .iter()
+            .map(|gradients| sum_of_squares(gradients).sqrt())
+            .collect();
+        GradientNorms {
+            global: layers.iter().map(|norm| norm * norm).sum::<f64>().sqrt(),
+            layers,
+            clipped: false,
+        }
+    }
+}
+
+impl GradientClipping {
+    pub(crate) fn validate(&self) -> Result<()> {
+        ensure!(
+            self.value.is_none_or(|value| value > 0.0),
+            "GradientClipping: value should be greater than 0"
+        );
+        ensure!(
+            self.global_norm.is_none_or(|global_norm| global_norm > 0.0),
+            "GradientClipping: global_norm should be greater than 0"
+        );
+        Ok(())
+    }
+
+    /// Clips the gradients and returns their norms from before clipping
+    pub(crate) fn apply<T: Float>(&self, gradients: &mut [LayerGradients<T>]) -> GradientNorms {
+        let mut norms = GradientNorms::of(gradients);
+        if let Some(value) = self.value {
+            let max = T::from_f64(value);
+            for gradient in gradients.iter_mut().flat_map(values_mut) {
+                if *gradient > max || *gradient < -max {
+                    *gradient = if *gradient > max { max } else { -max };
+                    norms.clipped = true;
+                }
+            }
+        }
+        if let Some(global_norm) = self.global_norm {
+            let norm = gradients.iter().map(sum_of_squares).sum::<f64>().sqrt();
+            if norm > global_norm {
+                let factor = T::from_f64(global_norm / norm);
+                for gradient in gradients.iter_mut().flat_map(values_mut) {
+                    *gradient *= factor;
+                }
+                norms.clipped = true;
+            }
+        }
+        norms
+    }
+}
+
+fn sum_of_squares<T: Float>(gradients: &LayerGradients<T>) -> f64 {
+    gradients
+        .weights
+        .values()
+        .chain(gradients.biases.values())
+        .map(|gradient| gradient.to_f64() * gradient.to_f64())
+        .sum()
+}
+
+fn values_mut<T: Float>(gradients: &mut LayerGradients<T>) -> impl Iterator<Item = &mut T> {
+    gradients
+        .weights
+        .values_mut()
+        .chain(gradients.biases.values_mut())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matrix::matrix::Matrix;
+
+    fn gradients() -> Vec<LayerGradients> {
+        vec![
+            LayerGradients {
+                weights: Matrix::from_rows(vec![vec![3.0, -4.0], vec![0.0, 0.0]]).unwrap(),
+                biases: Matrix::from_rows(vec![vec![0.0], vec![0.0]]).unwrap(),
+            },
+            LayerGradients {
+                weights: Matrix::from_rows(vec![vec![0.0, 12.0]]).unwrap(),
+                biases: Matrix::from_rows(vec![vec![0.0]]).unwrap(),
+            },
+        ]
+    }
+
+    #[test]
+    fn the_norms_are_measured_per_layer() {
+        let norms = GradientNorms::of(&gradients());
+        assert_eq!(norms.layers, vec![5.0, 12.0]);
+        assert_eq!(norms.global, 13.0);
+        assert!(!norms.clipped);
+    }
+
+    #[test]
+    fn testing_clipping_by_value() {
+        let mut gradients = gradients();
+        let norms = GradientClipping {
+            value: Some(3.5),
+            ..GradientClipping::default()
+        }
+        .apply(&mut gradients);
+        assert_eq!(norms.layers, vec![5.0, 12.0]);
+        assert!(norms.clipped);
+        assert_eq!(
+            gradients[0].weights,
+            Matrix::from_rows(vec![vec![3.0, -3.5], vec![0.0, 0.0]]).unwrap()
+        );
+        assert_eq!(
+            gradients[1].weights,
+            Matrix::from_rows(vec![vec![0.0, 3.5]]).unwrap()
+        );
+    }
+
+    #[test]
+    fn testing_clipping_by_global_norm() {
+        let mut gradients = gradients();
+        let norms = GradientClipping {
+            global_norm: Some(6.5),
+            ..GradientClipping::default()
+        }
+        .apply(&mut gradients);
+        assert_eq!(norms.global, 13.0);
+        assert!(norms.clipped);
+        // every gradient is halved, so the direction stays the same
+        assert_eq!(
+            gradients[0].weights,
+            Matrix::from_rows(vec![vec![1.5, -2.0], vec![0.0, 0.0]]).unwrap()
+        );
+        assert_eq!(
+            gradients[1].weights,
+            Matrix::from_rows(vec![vec![0.0, 6.0]]).unwrap()
+        );
+        // small gradients are not changed
+        let mut small = self::gradients();
+        let norms = GradientClipping {
+            value: Some(20.0),
+            global_norm: Some(20.0),
+        }
+        .apply(&mut small);
+        assert!(!norms.clipped);
+        assert_eq!(small, self::gradients());
+    }
+
+    #[test]
+    fn testing_validate() {
+        assert!(GradientClipping::default().validate().is_ok());
+        assert!(GradientClipping {
+            value: Some(0.0),
+            ..GradientClipping::default()
+        }
+        .validate()
+        .is_err());
+        assert!(GradientClipping {
+            global_norm: Some(-1.0),
+            ..GradientClipping::default()
+        }
+        .validate()
+        .is_err());
+    }
+}
@@ -1,8 +1,10 @@
+use crate::matrix::float::Float;
+
 #[derive(Debug, Clone)]
-pub struct ErrorRateEntry {
-    pub input: Vec<f64>,
-    pub expected_output: Vec<f64>
+pub struct ErrorRateEntry<T: Float = f64> {
+    pub input: Vec<T>,
+    pub expected_output: Vec<T>,
 }
 
 #[derive(Debug)]
-pub struct ErrorRateData<'a>(pub &'a Vec<ErrorRateEntry>);
+pub struct ErrorRateData<'a, T: Float = f64>(pub &'a Vec<ErrorRateEntry<T>>);
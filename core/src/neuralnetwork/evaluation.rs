@@ -0,0 +1,312 @@
+use anyhow::{ensure, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use super::loss::{Loss, MeanSquaredError};
+use crate::matrix::float::Float;
+
+/// The settings that are used while evaluating a neural network
+#[derive(Debug)]
+pub struct EvaluationOptions<T: Float = f64> {
+    /// The loss function that is used for the mean loss
+    pub loss: Box<dyn Loss<T>>,
+    /// An entry counts as correct for the top-k accuracy when the expected class is one of
+    /// the k output neurons with the highest value
+    pub top_k: usize,
+}
+
+impl<T: Float> Default for EvaluationOptions<T> {
+    fn default() -> Self {
+        EvaluationOptions {
+            loss: Box::new(MeanSquaredError),
+            top_k: 3,
+        }
+    }
+}
+
+/// The metrics of one class (output neuron)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClassMetrics {
+    pub class: usize,
+    /// How many of the entries that were predicted as this class are this class
+    pub precision: f64,
+    /// How many of the entries of this class were predicted as this class
+    pub recall: f64,
+    /// The harmonic mean of the precision and the recall
+    pub f1_score: f64,
+    /// The amount of entries of this class
+    pub support: u32,
+}
+
+/// The rows are the expected classes and the columns are the predicted classes,
+/// so confusion_matrix.0[3][5] is how often a 3 was predicted as a 5
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConfusionMatrix(pub Vec<Vec<u32>>);
+
+/// The result of evaluating a neural network. The class of an entry is the output neuron
+/// with the highest expected value and the predicted class is the output neuron with the
+/// highest actual value.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EvaluationReport {
+    pub amount_of_entries: u32,
+    /// The loss averaged over all entries
+    pub mean_loss: f64,
+    /// The fraction of entries where the predicted class is the expected class
+    pub accuracy: f64,
+    pub top_k: usize,
+    /// The fraction of entries where the expected class is one of the top k predictions
+    pub top_k_accuracy: f64,
+    pub classes: Vec<ClassMetrics>,
+    pub confusion_matrix: ConfusionMatrix,
+}
+
+impl fmt::Display for EvaluationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Entries: {}", self.amount_of_entries)?;
+        writeln!(f, "Mean loss: {:.6}", self.mean_loss)?;
+        writeln!(f, "Accuracy: {:.2}%", self.accuracy * 100.0)?;
+        writeln!(
+            f,
+            "Top-{} accuracy: {:.2}%",
+            self.top_k,
+            self.top_k_accuracy * 100.0
+        )?;
+        writeln!(f)?;
+        writeln!(f, "Class  Precision  Recall  F1-score  Support")?;
+        for class in self.classes.iter() {
+            writeln!(
+                f,
+                "{:>5}  {:>9.4}  {:>6.4}  {:>8.4}  {:>7}",
+                class.class, class.precision, class.recall, class.f1_score, class.support
+            )?;
+        }
+        writeln!(f)?;
+        writeln!(f, "Confusion matrix (rows: expected, columns: predicted)")?;
+        for row in self.confusion_matrix.0.iter() {
+            let row = row
+                .iter()
+                .map(|count| format!("{:>6}", count))
+                .collect::<Vec<String>>();
+            writeln!(f, "{}", row.join(""))?;
+        }
+        Ok(())
+    }
+}
+
+/// Collects the outputs of the neural network entry by entry, and turns them into a report
+/// when all entries are added
+#[derive(Debug)]
+pub(crate) struct Evaluation {
+    top_k: usize,
+    sum_of_losses: f64,
+    amount_of_entries: u32,
+    amount_of_correct_entries: u32,
+    amount_of_correct_top_k_entries: u32,
+    confusion_matrix: Vec<Vec<u32>>,
+}
+
+impl Evaluation {
+    pub(crate) fn new(amount_of_classes: usize, top_k: usize) -> Evaluation {
+        Evaluation {
+            top_k,
+            sum_of_losses: 0.0,
+            amount_of_entries: 0,
+            amount_of_correct_entries: 0,
+            amount_of_correct_top_k_entries: 0,
+            confusion_matrix: vec![vec![0; amount_of_classes]; amount_of_classes],
+        }
+    }
+
+    pub(crate) fn add(&mut self, expected: &[f64], actual: &[f64], loss: f64) -> Result<()> {
+        ensure!(
+            expected.len() == self.confusion_matrix.len() && actual.len() == expected.len(),
+            "Evaluation: expected output and actual output should have {} values",
+            self.confusion_matrix.len()
+        );
+        let expected_class = index_of_highest_value(expected);
+        let predicted_class = index_of_highest_value(actual);
+        let amount_of_higher_predictions = actual
+            .iter()
+            .filter(|value| **value > actual[expected_class])
+            .count();
+
+        self.sum_of_losses += loss;
+        self.amount_of_entries += 1;
+        if expected_class == predicted_class {
+            self.amount_of_correct_entries += 1;
+        }
+        if amount_of_higher_predictions < self.top_k {
+            self.amount_of_correct_top_k_entries += 1;
+        }
+        self.confusion_matrix[expected_class][predicted_class] += 1;
+        Ok(())
+    }
+
+    pub(crate) fn report(self) -> Result<EvaluationReport> {
+        ensure!(
+            self.amount_of_entries > 0,
+            "Evaluation: there should be at least one entry"
+        );
+        let amount_of_entries = self.amount_of_entries as f64;
+        let classes = (0..self.confusion_matrix.len())
+            .map(|class| self.class_metrics(class))
+            .collect();
+        Ok(EvaluationReport {
+            amount_of_entries: self.amount_of_entries,
+            mean_loss: self.sum_of_losses / amount_of_entries,
+            accuracy: self.amount_of_correct_entries as f64 / amount_of_entries,
+            top_k: self.top_k,
+            top_k_accuracy: self.amount_of_correct_top_k_entries as f64 / amount_of_entries,
+            classes,
+            confusion_matrix: ConfusionMatrix(self.confusion_matrix),
+        })
+    }
+
+    /// A precision or recall without any entries (division by zero) is 0
+    fn class_metrics(&self, class: usize) -> ClassMetrics {
+        let true_positives = self.confusion_matrix[class][class] as f64;
+        let support = self.confusion_matrix[class].iter().sum::<u32>();
+        let predicted = self
+            .confusion_matrix
+            .iter()
+            .map(|row| row[class])
+            .sum::<u32>();
+        let precision = divide_or_zero(true_positives, predicted as f64);
+        let recall = divide_or_zero(true_positives, support as f64);
+        ClassMetrics {
+            class,
+            precision,
+            recall,
+            f1_score: divide_or_zero(2.0 * precision * recall, precision + recall),
+            support,
+        }
+    }
+}
+
+/// The index of the highest value, the first index when multiple values are the highest
+pub(crate) fn index_of_highest_value(values: &[f64]) -> usize {
+    values
+        .iter()
+        .enumerate()
+        .fold(
+            (0, f64::NEG_INFINITY),
+            |(best_index, best), (index, value)| {
+                if *value > best {
+                    (index, *value)
+                } else {
+                    (best_index, best)
+                }
+            },
+        )
+        .0
+}
+
+fn divide_or_zero(numerator: f64, denominator: f64) -> f64 {
+    if denominator == 0.0 {
+        0.0
+    } else {
+        numerator / denominator
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn example_evaluation() -> Evaluation {
+        let mut evaluation = Evaluation::new(3, 2);
+        // correct
+        evaluation
+            .add(&[1.0, 0.0, 0.0], &[0.7, 0.2, 0.1], 0.5)
+            .unwrap();
+        // a 0 predicted as a 1, second highest output is correct
+        evaluation
+            .add(&[1.0, 0.0, 0.0], &[0.3, 0.6, 0.1], 1.0)
+            .unwrap();
+        // correct
+        evaluation
+            .add(&[0.0, 1.0, 0.0], &[0.1, 0.8, 0.1], 0.25)
+            .unwrap();
+        // a 2 predicted as a 1, lowest output is correct
+        evaluation
+            .add(&[0.0, 0.0, 1.0], &[0.3, 0.6, 0.1], 2.25)
+            .unwrap();
+        evaluation
+    }
+
+    #[test]
+    fn testing_report() {
+        let report = example_evaluation().report().unwrap();
+        assert_eq!(report.amount_of_entries, 4);
+        assert_eq!(report.mean_loss, 1.0);
+        assert_eq!(report.accuracy, 0.5);
+        assert_eq!(report.top_k, 2);
+        assert_eq!(report.top_k_accuracy, 0.75);
+        assert_eq!(
+            report.confusion_matrix,
+            ConfusionMatrix(vec![vec![1, 1, 0], vec![0, 1, 0], vec![0, 1, 0]])
+        );
+        assert_eq!(
+            report.classes,
+            vec![
+                ClassMetrics {
+                    class: 0,
+                    precision: 1.0,
+                    recall: 0.5,
+                    f1_score: 0.6666666666666666,
+                    support: 2,
+                },
+                ClassMetrics {
+                    class: 1,
+                    precision: 0.3333333333333333,
+                    recall: 1.0,
+                    f1_score: 0.5,
+                    support: 1,
+                },
+                ClassMetrics {
+                    class: 2,
+                    precision: 0.0,
+                    recall: 0.0,
+                    f1_score: 0.0,
+                    support: 1,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn a_bad_network_does_not_get_a_perfect_score() {
+        // the errors of the old error rate (expected - actual) cancelled each other out
+        let mut evaluation = Evaluation::new(2, 1);
+        evaluation.add(&[1.0, 0.0], &[0.0, 1.0], 1.0).unwrap();
+        let report = evaluation.report().unwrap();
+        assert_eq!(report.accuracy, 0.0);
+        assert_eq!(report.mean_loss, 1.0);
+    }
+
+    #[test]
+    fn serialize_and_display_report() {
+        let report = example_evaluation().report().unwrap();
+        let json = serde_json::to_string(&report).unwrap();
+        assert_eq!(
+            serde_json::from_str::<EvaluationReport>(&json).unwrap(),
+            report
+        );
+        let printed = report.to_string();
+        assert!(printed.contains("Accuracy: 50.00%"));
+        assert!(printed.contains("Top-2 accuracy: 75.00%"));
+    }
+
+    #[test]
+    fn testing_evaluation_ensure() {
+        let mut evaluation = Evaluation::new(3, 1);
+        assert!(evaluation.add(&[1.0, 0.0], &[1.0, 0.0], 0.0).is_err());
+        assert!(evaluation.report().is_err());
+    }
+
+    #[test]
+    fn testing_index_of_highest_value() {
+        assert_eq!(index_of_highest_value(&[0.1, 0.7, 0.2]), 1);
+        assert_eq!(index_of_highest_value(&[0.5, 0.5]), 0);
+    }
+}
@@ -0,0 +1,158 @@
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+
+use crate::matrix::{float::Float, matrix::Matrix};
+
+/// How the weights of a layer get their first values. Most initializers scale the random values
+/// with the fan-in (the amount of input neurons) and the fan-out (the amount of output neurons)
+/// of the layer, so the weighted input of a neuron doesn't get too big. With 784 inputs and
+/// weights between -1 and 1 a sigmoid neuron is almost always saturated, so it barely learns.
+///
+/// The biases are not initialized with the initializer, they always start at zero.
+#[derive(Debug, PartialEq, Clone, Copy, Default, Serialize, Deserialize)]
+pub enum Initializer {
+    /// A uniform distribution between -limit and limit, the weights of old versions were
+    /// uniform between -1 and 1
+    Uniform { limit: f64 },
+    /// A normal distribution with the mean and the standard deviation
+    Normal { mean: f64, standard_deviation: f64 },
+    /// Glorot/Xavier, uniform between -√(6 / (fan_in + fan_out)) and √(6 / (fan_in + fan_out)),
+    /// for sigmoid and tanh
+    XavierUniform,
+    /// Glorot/Xavier, normal distribution with a standard deviation of √(2 / (fan_in + fan_out)),
+    /// for sigmoid and tanh
+    XavierNormal,
+    /// He/Kaiming, normal distribution with a standard deviation of √(2 / fan_in), for relu
+    He,
+    /// LeCun, normal distribution with a standard deviation of √(1 / fan_in). This is the
+    /// 1/√(amount of incoming links) that the book recommends.
+    #[default]
+    LeCun,
+    /// Every weight is 0
+    Zeros,
+    /// Every weight has the same value, useful for tests
+    Constant(f64),
+}
+
+impl Initializer {
+    /// Creates the weights of a layer, with a row per output neuron and a column per input
+    /// neuron. A seeded random number generator always gives the same weights.
+    pub(crate) fn weights<T: Float>(
+        &self,
+        amount_of_output_neurons: u32,
+        amount_of_input_neurons: u32,
+        rng: &mut impl Rng,
+    ) -> Matrix<T> {
+        let fan_in = amount_of_input_neurons as f64;
+        let fan_out = amount_of_output_neurons as f64;
+        Matrix::new_with_values(
+            amount_of_output_neurons,
+            amount_of_input_neurons,
+            || match *self {
+                Initializer::Uniform { limit } => uniform(rng, limit),
+                Initializer::Normal {
+                    mean,
+                    standard_deviation,
+                } => mean + standard_deviation * standard_normal(rng),
+                Initializer::XavierUniform => uniform(rng, (6.0 / (fan_in + fan_out)).sqrt()),
+                Initializer::XavierNormal => {
+                    (2.0 / (fan_in + fan_out)).sqrt() * standard_normal(rng)
+                }
+                Initializer::He => (2.0 / fan_in).sqrt() * standard_normal(rng),
+                Initializer::LeCun => (1.0 / fan_in).sqrt() * standard_normal(rng),
+                Initializer::Zeros => 0.0,
+                Initializer::Constant(constant) => constant,
+            },
+        )
+    }
+}
+
+/// A value between -limit and limit
+fn uniform(rng: &mut impl Rng, limit: f64) -> f64 {
+    (rng.gen::<f64>() * 2.0 - 1.0) * limit
+}
+
+/// A value of the standard normal distribution (mean 0, standard deviation 1), calculated from
+/// two uniform values with the Box-Muller transform
+fn standard_normal(rng: &mut impl Rng) -> f64 {
+    // between 0 (exclusive) and 1 (inclusive), because the logarithm of 0 is infinite
+    let first = 1.0 - rng.gen::<f64>();
+    let second = rng.gen::<f64>();
+    (-2.0 * first.ln()).sqrt() * (2.0 * PI * second).cos()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    fn mean_and_standard_deviation(matrix: &Matrix) -> (f64, f64) {
+        let amount = matrix.values().count() as f64;
+        let mean = matrix.values().sum::<f64>() / amount;
+        let variance = matrix
+            .values()
+            .map(|value| (value - mean) * (value - mean))
+            .sum::<f64>()
+            / amount;
+        (mean, variance.sqrt())
+    }
+
+    #[test]
+    fn weights_are_scaled_with_the_fan_in_and_fan_out() {
+        let mut rng = StdRng::seed_from_u64(42);
+        let xavier_uniform: Matrix = Initializer::XavierUniform.weights(100, 200, &mut rng);
+        let limit = (6.0_f64 / 300.0).sqrt();
+        assert!(xavier_uniform
+            .values()
+            .all(|value| (-limit..limit).contains(value)));
+        for (initializer, expected_standard_deviation) in [
+            (Initializer::XavierNormal, (2.0_f64 / 300.0).sqrt()),
+            (Initializer::He, (2.0_f64 / 200.0).sqrt()),
+            (Initializer::LeCun, (1.0_f64 / 200.0).sqrt()),
+            (
+                Initializer::Normal {
+                    mean: 0.0,
+                    standard_deviation: 0.5,
+                },
+                0.5,
+            ),
+        ] {
+            let (mean, standard_deviation) =
+                mean_and_standard_deviation(&initializer.weights(100, 200, &mut rng));
+            assert!(mean.abs() < 0.01, "{initializer:?}");
+            assert!(
+                (standard_deviation / expected_standard_deviation - 1.0).abs() < 0.05,
+                "{initializer:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn testing_zeros_and_constant() {
+        let mut rng = StdRng::seed_from_u64(42);
+        assert_eq!(
+            Initializer::Zeros.weights::<f64>(2, 3, &mut rng),
+            Matrix::new_with_zeros(2, 3)
+        );
+        assert_eq!(
+            Initializer::Constant(0.5).weights::<f32>(1, 2, &mut rng),
+            Matrix::from_rows(vec![vec![0.5, 0.5]]).unwrap()
+        );
+    }
+
+    #[test]
+    fn the_same_seed_gives_the_same_weights() {
+        let initializer = Initializer::Uniform { limit: 1.0 };
+        let weights: Matrix = initializer.weights(3, 4, &mut StdRng::seed_from_u64(42));
+        assert!(weights.values().all(|value| (-1.0..1.0).contains(value)));
+        assert_eq!(
+            initializer.weights(3, 4, &mut StdRng::seed_from_u64(42)),
+            weights
+        );
+        assert_ne!(
+            initializer.weights(3, 4, &mut StdRng::seed_from_u64(43)),
+            weights
+        );
+    }
+}
@@ -1,8 +1,176 @@
-use serde::{Serialize, Deserialize};
+use anyhow::ensure;
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
 
-use crate::matrix::matrix::Matrix;
+use super::activation::Activation;
+use crate::matrix::{float::Float, matrix::Matrix};
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
-pub struct Layer(
-    pub Matrix
-);
\ No newline at end of file
+/// A layer holds the weights between two layers of neurons and the bias of every
+/// neuron it feeds into. The weights matrix has a row per output neuron and a column
+/// per input neuron, the biases are a column vector with a value per output neuron.
+/// The activation function is applied on the output neurons of the layer.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(try_from = "LayerRepresentation<T>", bound = "T: Float")]
+pub struct Layer<T: Float = f64> {
+    pub weights: Matrix<T>,
+    pub biases: Matrix<T>,
+    pub activation: Activation,
+    /// The fraction of the output neurons that is dropped (set to 0) during training, the
+    /// other output neurons are scaled up so querying needs no scaling (inverted dropout).
+    /// The output layer has no dropout.
+    #[serde(skip_serializing_if = "is_zero")]
+    pub dropout: f64,
+}
+
+/// Old models were serialized as a bare weights matrix, those are still accepted
+/// and get a bias of zero for every neuron. Models without an activation function
+/// use sigmoid, because that was the only activation function before.
+#[derive(Deserialize)]
+#[serde(untagged, bound = "T: Float")]
+enum LayerRepresentation<T: Float> {
+    WithBiases {
+        weights: Matrix<T>,
+        biases: Matrix<T>,
+        #[serde(default)]
+        activation: Activation,
+        #[serde(default)]
+        dropout: f64,
+    },
+    WithoutBiases(Matrix<T>),
+}
+
+impl<T: Float> TryFrom<LayerRepresentation<T>> for Layer<T> {
+    type Error = anyhow::Error;
+
+    fn try_from(value: LayerRepresentation<T>) -> Result<Self, Self::Error> {
+        match value {
+            LayerRepresentation::WithBiases {
+                weights,
+                biases,
+                activation,
+                dropout,
+            } => {
+                ensure!(
+                    biases.rows() == weights.rows() && biases.cols() == 1,
+                    "Layer: biases should be a column vector with a value per row of the weights"
+                );
+                ensure!(
+                    (0.0..1.0).contains(&dropout),
+                    "Layer: dropout should be between 0 and 1"
+                );
+                Ok(Layer {
+                    weights,
+                    biases,
+                    activation,
+                    dropout,
+                })
+            }
+            LayerRepresentation::WithoutBiases(weights) => Ok(Layer::from(weights)),
+        }
+    }
+}
+
+impl<T: Float> From<Matrix<T>> for Layer<T> {
+    /// Creates a layer from a weights matrix with a bias of zero for every neuron
+    /// and sigmoid as activation function
+    fn from(weights: Matrix<T>) -> Self {
+        let biases = Matrix::new_with_zeros(weights.rows() as u32, 1);
+        Layer {
+            weights,
+            biases,
+            activation: Activation::default(),
+            dropout: 0.0,
+        }
+    }
+}
+
+impl<T: Float> Layer<T> {
+    /// Returns the same layer with a different activation function
+    pub fn with_activation(self, activation: Activation) -> Layer<T> {
+        Layer { activation, ..self }
+    }
+
+    /// Returns the same layer with dropout during training
+    pub fn with_dropout(self, dropout: f64) -> Layer<T> {
+        Layer { dropout, ..self }
+    }
+
+    /// Converts the weights and biases to another precision, for example from f64 to f32
+    pub fn to_precision<U: Float>(&self) -> Layer<U> {
+        Layer {
+            weights: self.weights.to_precision(),
+            biases: self.biases.to_precision(),
+            activation: self.activation,
+            dropout: self.dropout,
+        }
+    }
+}
+
+fn is_zero(value: &f64) -> bool {
+    *value == 0.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deserialize_layer_without_biases() {
+        let layer: Layer = serde_json::from_str("[[0.1,0.2],[0.3,0.4],[0.5,0.6]]").unwrap();
+        assert_eq!(
+            layer,
+            Layer {
+                weights: Matrix::from_rows(vec![vec![0.1, 0.2], vec![0.3, 0.4], vec![0.5, 0.6]])
+                    .unwrap(),
+                biases: Matrix::from_rows(vec![vec![0.0], vec![0.0], vec![0.0]]).unwrap(),
+                activation: Activation::Sigmoid,
+                dropout: 0.0,
+            }
+        );
+    }
+
+    #[test]
+    fn serialize_and_deserialize_layer_with_biases() {
+        let layer = Layer {
+            weights: Matrix::from_rows(vec![vec![0.1, 0.2], vec![0.3, 0.4]]).unwrap(),
+            biases: Matrix::from_rows(vec![vec![0.5], vec![0.6]]).unwrap(),
+            activation: Activation::Relu,
+            dropout: 0.0,
+        };
+        let json = serde_json::to_string(&layer).unwrap();
+        assert_eq!(
+            json,
+            r#"{"weights":[[0.1,0.2],[0.3,0.4]],"biases":[[0.5],[0.6]],"activation":"Relu"}"#
+        );
+        assert_eq!(serde_json::from_str::<Layer>(&json).unwrap(), layer);
+    }
+
+    #[test]
+    fn deserialize_layer_without_activation() {
+        let layer: Layer =
+            serde_json::from_str(r#"{"weights":[[0.1,0.2]],"biases":[[0.5]]}"#).unwrap();
+        assert_eq!(layer.activation, Activation::Sigmoid);
+    }
+
+    #[test]
+    fn deserialize_layer_with_wrong_amount_of_biases() {
+        let result =
+            serde_json::from_str::<Layer>(r#"{"weights":[[0.1,0.2],[0.3,0.4]],"biases":[[0.5]]}"#);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn serialize_and_deserialize_layer_with_dropout() {
+        let layer = Layer::from(Matrix::from_rows(vec![vec![0.1, 0.2]]).unwrap()).with_dropout(0.5);
+        let json = serde_json::to_string(&layer).unwrap();
+        assert_eq!(
+            json,
+            r#"{"weights":[[0.1,0.2]],"biases":[[0.0]],"activation":"Sigmoid","dropout":0.5}"#
+        );
+        assert_eq!(serde_json::from_str::<Layer>(&json).unwrap(), layer);
+        assert!(serde_json::from_str::<Layer>(
+            r#"{"weights":[[0.1,0.2]],"biases":[[0.0]],"dropout":1.0}"#
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,313 @@
+use anyhow::{ensure, Result};
+use std::fmt::Debug;
+
+use super::activation::Activation;
+use crate::matrix::{
+    float::Float,
+    matrix::{matrix_rows_and_cols, Matrix},
+};
+
+/// Outputs are clamped between EPSILON and 1 - EPSILON before taking a logarithm,
+/// so an output of exactly 0 or 1 doesn't result in an infinite loss
+const EPSILON: f64 = 1e-12;
+
+/// EPSILON is too small for f32, 1 - EPSILON would be rounded to 1
+fn epsilon<T: Float>() -> T {
+    T::from_f64(EPSILON).max(T::EPSILON)
+}
+
+/// A loss function measures how far the output of the neural network is from the target.
+/// Every column of the target and the actual output belongs to one input of a batch.
+///
+/// The loss is always calculated as an f64, also for a neural network with f32 values.
+pub trait Loss<T: Float = f64>: Debug + Send + Sync {
+    /// The loss of every input (summed over all output neurons), averaged over the batch
+    fn loss(&self, target: &Matrix<T>, actual: &Matrix<T>) -> Result<f64>;
+
+    /// The error rate of the output layer, this is the negative gradient of the loss with
+    /// respect to the output of the neural network. For the mean squared error this is
+    /// target - actual, which was the error rate of the output layer in the book.
+    ///
+    /// When the loss is fused with the activation function of the output layer, the error rate
+    /// already includes the derivative of the activation function.
+    fn error(
+        &self,
+        target: &Matrix<T>,
+        actual: &Matrix<T>,
+        activation: &Activation,
+    ) -> Result<Matrix<T>>;
+
+    /// Returns true when the error rate already includes the derivative of the activation
+    /// function, so backpropagation should not apply the derivative again
+    fn is_fused_with(&self, _activation: &Activation) -> bool {
+        false
+    }
+}
+
+/// ½ * sum((target - actual)²)
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MeanSquaredError;
+
+impl<T: Float> Loss<T> for MeanSquaredError {
+    fn loss(&self, target: &Matrix<T>, actual: &Matrix<T>) -> Result<f64> {
+        average_over_batch(target, actual, |t, a| 0.5 * (t - a) * (t - a))
+    }
+
+    fn error(
+        &self,
+        target: &Matrix<T>,
+        actual: &Matrix<T>,
+        _activation: &Activation,
+    ) -> Result<Matrix<T>> {
+        target - actual
+    }
+}
+
+/// -sum(target * ln(actual) + (1 - target) * ln(1 - actual)), every output neuron is a
+/// separate yes/no prediction. Combined with sigmoid the error rate is target - actual.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BinaryCrossEntropy;
+
+impl<T: Float> Loss<T> for BinaryCrossEntropy {
+    fn loss(&self, target: &Matrix<T>, actual: &Matrix<T>) -> Result<f64> {
+        average_over_batch(target, actual, |t, a| {
+            let a = a.clamp(EPSILON, 1.0 - EPSILON);
+            -(t * a.ln() + (1.0 - t) * (1.0 - a).ln())
+        })
+    }
+
+    fn error(
+        &self,
+        target: &Matrix<T>,
+        actual: &Matrix<T>,
+        activation: &Activation,
+    ) -> Result<Matrix<T>> {
+        if Loss::<T>::is_fused_with(self, activation) {
+            return target - actual;
+        }
+        let epsilon = epsilon::<T>();
+        actual.zip_map(target, |a, t| {
+            let a = a.clamp(epsilon, T::ONE - epsilon);
+            (t - a) / (a * (T::ONE - a))
+        })
+    }
+
+    fn is_fused_with(&self, activation: &Activation) -> bool {
+        *activation == Activation::Sigmoid
+    }
+}
+
+/// -sum(target * ln(actual)), the output neurons together are one prediction of a class,
+/// for example which digit is in an image. Combined with softmax the error rate is target - actual.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CategoricalCrossEntropy;
+
+impl<T: Float> Loss<T> for CategoricalCrossEntropy {
+    fn loss(&self, target: &Matrix<T>, actual: &Matrix<T>) -> Result<f64> {
+        average_over_batch(target, actual, |t, a| -t * a.max(EPSILON).ln())
+    }
+
+    fn error(
+        &self,
+        target: &Matrix<T>,
+        actual: &Matrix<T>,
+        activation: &Activation,
+    ) -> Result<Matrix<T>> {
+        if Loss::<T>::is_fused_with(self, activation) {
+            return target - actual;
+        }
+        let epsilon = epsilon::<T>();
+        actual.zip_map(target, |a, t| t / a.max(epsilon))
+    }
+
+    fn is_fused_with(&self, activation: &Activation) -> bool {
+        *activation == Activation::Softmax
+    }
+}
+
+/// ½ * (target - actual)² when the difference is at most delta, otherwise
+/// delta * (|target - actual| - ½ * delta). Large differences count linear instead of
+/// squared, so a few outliers don't dominate the loss.
+#[derive(Debug, Clone, Copy)]
+pub struct Huber {
+    pub delta: f64,
+}
+
+impl Default for Huber {
+    fn default() -> Self {
+        Huber { delta: 1.0 }
+    }
+}
+
+impl<T: Float> Loss<T> for Huber {
+    fn loss(&self, target: &Matrix<T>, actual: &Matrix<T>) -> Result<f64> {
+        average_over_batch(target, actual, |t, a| {
+            let difference = (t - a).abs();
+            if difference <= self.delta {
+                0.5 * difference * difference
+            } else {
+                self.delta * (difference - 0.5 * self.delta)
+            }
+        })
+    }
+
+    fn error(
+        &self,
+        target: &Matrix<T>,
+        actual: &Matrix<T>,
+        _activation: &Activation,
+    ) -> Result<Matrix<T>> {
+        let delta = T::from_f64(self.delta);
+        Ok((target - actual)?.map(|difference| difference.clamp(-delta, delta)))
+    }
+}
+
+/// sum(max(0, 1 - target * actual)), the targets should be -1 or 1. Use it with an output
+/// layer that can be negative, like tanh or identity.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Hinge;
+
+impl<T: Float> Loss<T> for Hinge {
+    fn loss(&self, target: &Matrix<T>, actual: &Matrix<T>) -> Result<f64> {
+        average_over_batch(target, actual, |t, a| (1.0 - t * a).max(0.0))
+    }
+
+    fn error(
+        &self,
+        target: &Matrix<T>,
+        actual: &Matrix<T>,
+        _activation: &Activation,
+    ) -> Result<Matrix<T>> {
+        actual.zip_map(target, |a, t| if t * a < T::ONE { t } else { T::ZERO })
+    }
+}
+
+fn average_over_batch<T: Float>(
+    target: &Matrix<T>,
+    actual: &Matrix<T>,
+    loss_per_value: impl Fn(f64, f64) -> f64,
+) -> Result<f64> {
+    ensure!(
+        target.same_size(actual),
+        "Loss: actual and target should be of same size"
+    );
+    let (_, batch_size) = matrix_rows_and_cols(target);
+    let sum = target
+        .values()
+        .zip(actual.values())
+        .map(|(t, a)| loss_per_value(t.to_f64(), a.to_f64()))
+        .sum::<f64>();
+    Ok(sum / batch_size.max(1) as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn target() -> Matrix {
+        Matrix::from_rows(vec![vec![1.0, 0.0], vec![0.0, 1.0]]).unwrap()
+    }
+
+    fn actual() -> Matrix {
+        Matrix::from_rows(vec![vec![0.8, 0.5], vec![0.2, 0.5]]).unwrap()
+    }
+
+    #[test]
+    fn testing_mean_squared_error() {
+        assert_eq!(MeanSquaredError.loss(&target(), &actual()).unwrap(), 0.145);
+        assert_eq!(
+            MeanSquaredError
+                .error(&target(), &actual(), &Activation::Sigmoid)
+                .unwrap(),
+            (&target() - &actual()).unwrap()
+        );
+        assert!(!Loss::<f64>::is_fused_with(
+            &MeanSquaredError,
+            &Activation::Sigmoid
+        ));
+    }
+
+    #[test]
+    fn testing_binary_cross_entropy() {
+        assert_eq!(
+            BinaryCrossEntropy.loss(&target(), &actual()).unwrap(),
+            0.9162907318741551
+        );
+        assert_eq!(
+            BinaryCrossEntropy
+                .error(&target(), &actual(), &Activation::Sigmoid)
+                .unwrap(),
+            (&target() - &actual()).unwrap()
+        );
+        assert_eq!(
+            BinaryCrossEntropy
+                .error(&target(), &actual(), &Activation::Identity)
+                .unwrap(),
+            Matrix::from_rows(vec![vec![1.25, -2.0], vec![-1.2499999999999998, 2.0]]).unwrap()
+        );
+    }
+
+    #[test]
+    fn testing_categorical_cross_entropy() {
+        assert_eq!(
+            CategoricalCrossEntropy.loss(&target(), &actual()).unwrap(),
+            0.4581453659370775
+        );
+        assert_eq!(
+            CategoricalCrossEntropy
+                .error(&target(), &actual(), &Activation::Softmax)
+                .unwrap(),
+            (&target() - &actual()).unwrap()
+        );
+        assert!(Loss::<f64>::is_fused_with(
+            &CategoricalCrossEntropy,
+            &Activation::Softmax
+        ));
+        assert!(!Loss::<f64>::is_fused_with(
+            &CategoricalCrossEntropy,
+            &Activation::Sigmoid
+        ));
+        // a predicted chance of 0 for the correct class doesn't result in an infinite loss
+        assert!(CategoricalCrossEntropy
+            .loss(
+                &target(),
+                &Matrix::from_rows(vec![vec![0.0, 1.0], vec![1.0, 0.0]]).unwrap()
+            )
+            .unwrap()
+            .is_finite());
+    }
+
+    #[test]
+    fn testing_huber() {
+        let huber = Huber { delta: 0.25 };
+        assert_eq!(
+            huber.loss(&target(), &actual()).unwrap(),
+            0.11374999999999999
+        );
+        assert_eq!(
+            huber
+                .error(&target(), &actual(), &Activation::Identity)
+                .unwrap(),
+            Matrix::from_rows(vec![vec![0.19999999999999996, -0.25], vec![-0.2, 0.25]]).unwrap()
+        );
+    }
+
+    #[test]
+    fn testing_hinge() {
+        let target = Matrix::from_rows(vec![vec![1.0], vec![-1.0]]).unwrap();
+        let actual = Matrix::from_rows(vec![vec![2.0], vec![0.5]]).unwrap();
+        assert_eq!(Hinge.loss(&target, &actual).unwrap(), 1.5);
+        assert_eq!(
+            Hinge
+                .error(&target, &actual, &Activation::Identity)
+                .unwrap(),
+            Matrix::from_rows(vec![vec![0.0], vec![-1.0]]).unwrap()
+        );
+    }
+
+    #[test]
+    fn testing_loss_ensure() {
+        let result = MeanSquaredError.loss(&target(), &Matrix::from_rows(vec![vec![1.0]]).unwrap());
+        assert!(result.is_err());
+    }
+}
@@ -1,5 +1,16 @@
+pub mod activation;
+pub mod builder;
+pub mod clipping;
 pub mod errorrate;
+pub mod evaluation;
+pub mod initializer;
 pub mod layer;
+pub mod loss;
+#[allow(clippy::module_inception)]
 pub mod neuralnetwork;
+pub mod observer;
+pub mod optimizer;
 pub mod query;
-pub mod training;
\ No newline at end of file
+pub mod regularization;
+pub mod schedule;
+pub mod training;
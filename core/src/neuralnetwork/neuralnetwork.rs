@@ -1,54 +1,105 @@
 use super::{
+    clipping::GradientNorms,
     errorrate::ErrorRateData,
+    evaluation::{Evaluation, EvaluationOptions, EvaluationReport},
+    initializer::Initializer,
     layer::Layer,
+    loss::Loss,
+    observer::{NoObserver, TrainingControl, TrainingObserver, TrainingProgress},
+    optimizer::{LayerGradients, Optimizer},
     query::{QueryData, QueryResult, QueryResults},
-    training::TrainingData,
+    schedule::Scheduler,
+    training::{BatchMetrics, RoundMetrics, TrainingEntry, TrainingHistory, TrainingOptions},
 };
+#[cfg(feature = "parallel")]
+use crate::logic::parallel;
 use crate::{
-    logic::{backpropagation::Backpropagation, feedforward::Feedforward},
-    matrix::matrix::Matrix,
+    dataset::{dataloader::DataLoader, dataset::Dataset},
+    logic::{
+        backpropagation::Backpropagation,
+        backquery,
+        feedforward::{DropoutMasks, Feedforward, Mode},
+    },
+    matrix::{float::Float, matrix::Matrix},
 };
 use anyhow::{ensure, Context, Result};
-use serde::{Serialize, Deserialize};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
 
+/// A neural network is a list of layers, the first layer is connected to the input neurons and
+/// the last layer is the output layer. The amount of neurons in each layer is derived from the
+/// weights of the layers, use the NeuralNetworkBuilder to create a network with different sizes.
+///
+/// The values of the weights and biases are an f64 by default, a neural network with f32 values
+/// uses half the memory. Use to_precision to convert an existing neural network.
 #[derive(Debug, Serialize, Deserialize)]
-pub struct NeuralNetwork {
-    pub layers: Vec<Layer>,
-    pub amount_of_input_neurons: u32,
-    pub amount_of_hidden_neurons: u32,
-    pub amount_of_output_neurons: u32,
-    pub amount_of_hidden_layers: u32,
+#[serde(bound = "T: Float")]
+pub struct NeuralNetwork<T: Float = f64> {
+    pub layers: Vec<Layer<T>>,
 }
 
-impl NeuralNetwork {
+impl<T: Float> NeuralNetwork<T> {
+    /// Creates a neural network where every hidden layer has the same amount of neurons,
+    /// with sigmoid as activation function. The weights are initialized with LeCun (see
+    /// Initializer) and the random values come from the random number generator, so a seeded
+    /// generator (for example rand::rngs::StdRng::seed_from_u64) always creates the same
+    /// neural network.
     pub fn new_with_random_values(
         amount_of_input_neurons: u32,
         amount_of_hidden_neurons: u32,
         amount_of_output_neurons: u32,
         amount_of_hidden_layers: u32,
-    ) -> NeuralNetwork {
+        rng: &mut impl Rng,
+    ) -> NeuralNetwork<T> {
         let mut layers = Vec::with_capacity(amount_of_hidden_layers as usize + 2);
         layers.push(create_input_layer(
             amount_of_hidden_neurons,
             amount_of_input_neurons,
+            rng,
         ));
-        for layer in create_hidden_layers(amount_of_hidden_layers, amount_of_hidden_neurons) {
+        for layer in create_hidden_layers(amount_of_hidden_layers, amount_of_hidden_neurons, rng) {
             layers.push(layer);
         }
         layers.push(create_output_layer(
             amount_of_output_neurons,
             amount_of_hidden_neurons,
+            rng,
         ));
+        NeuralNetwork { layers }
+    }
+
+    pub fn amount_of_input_neurons(&self) -> u32 {
+        self.layers
+            .first()
+            .map_or(0, |layer| layer.weights.cols() as u32)
+    }
+
+    pub fn amount_of_output_neurons(&self) -> u32 {
+        self.layers
+            .last()
+            .map_or(0, |layer| layer.weights.rows() as u32)
+    }
+
+    /// The amount of neurons per layer, starting with the input neurons and ending with the
+    /// output neurons
+    pub fn layer_sizes(&self) -> Vec<u32> {
+        let mut layer_sizes = vec![self.amount_of_input_neurons()];
+        for layer in self.layers.iter() {
+            layer_sizes.push(layer.weights.rows() as u32);
+        }
+        layer_sizes
+    }
+
+    /// Converts the weights and biases to another float type, for example an f64 neural
+    /// network to an f32 neural network that is half the size
+    pub fn to_precision<U: Float>(&self) -> NeuralNetwork<U> {
         NeuralNetwork {
-            layers,
-            amount_of_input_neurons,
-            amount_of_hidden_neurons,
-            amount_of_output_neurons,
-            amount_of_hidden_layers,
+            layers: self.layers.iter().map(Layer::to_precision).collect(),
         }
     }
 
-    pub fn query(&self, input_data: &QueryData) -> Result<QueryResults> {
+    pub fn query(&self, input_data: &QueryData<T>) -> Result<QueryResults<T>> {
         let mut queryresults = Vec::new();
         for entry in input_data.0.iter() {
             queryresults.push(QueryResult(
@@ -56,126 +107,435 @@ impl NeuralNetwork {
                     .results
                     .last()
                     .context("Query: result has no last layer")?
-                    .0
-                    .iter()
-                    .flat_map(|a| a.to_owned())
-                    .collect::<Vec<f64>>(),
+                    .as_slice()
+                    .to_vec(),
             ));
         }
         Ok(QueryResults(queryresults))
     }
 
-    pub fn error_rate_of_network(&self, input_data: &ErrorRateData) -> Result<f64> {
-        let mut error_rate = 0.0;
+    /// Runs the neural network in reverse, from the target output to the input the neural
+    /// network associates with it. For a neural network that recognizes digits, a target with
+    /// 0.99 for one digit and 0.01 for the others gives an image of what that digit looks like
+    /// to the neural network. The values of the input are between 0.01 and 0.99.
+    pub fn backquery(&self, target_output: &[T]) -> Result<Vec<T>> {
+        Ok(backquery::run(&self.layers, target_output)?
+            .as_slice()
+            .to_vec())
+    }
+
+    /// Evaluates the neural network with the data, the report contains the mean loss, the
+    /// accuracy, the top-k accuracy, the metrics per class and a confusion matrix
+    pub fn evaluate(
+        &self,
+        input_data: &ErrorRateData<T>,
+        options: &EvaluationOptions<T>,
+    ) -> Result<EvaluationReport> {
+        let mut evaluation =
+            Evaluation::new(self.amount_of_output_neurons() as usize, options.top_k);
         for entry in input_data.0.iter() {
-            let actual_result = Feedforward::run(self, &entry.input)?
-                .results
-                .last()
-                .context("Query: result has no last layer")?
-                .0
-                .iter()
-                .flat_map(|a| a.to_owned())
-                .collect::<Vec<f64>>();
-            error_rate += entry
-                .expected_output
-                .iter()
-                .zip(actual_result)
-                .map(|(expected, actual)| expected - actual)
-                .sum::<f64>();
+            self.add_to_evaluation(
+                &mut evaluation,
+                &entry.input,
+                &entry.expected_output,
+                options.loss.as_ref(),
+            )?;
+        }
+        evaluation.report()
+    }
+
+    /// Evaluates the neural network with the entries of the dataset, like evaluate
+    pub fn evaluate_dataset(
+        &self,
+        dataset: &dyn Dataset<T>,
+        options: &EvaluationOptions<T>,
+    ) -> Result<EvaluationReport> {
+        self.evaluate_dataset_with(dataset, options.loss.as_ref(), options.top_k)
+    }
+
+    fn evaluate_dataset_with(
+        &self,
+        dataset: &dyn Dataset<T>,
+        loss: &dyn Loss<T>,
+        top_k: usize,
+    ) -> Result<EvaluationReport> {
+        let mut evaluation = Evaluation::new(self.amount_of_output_neurons() as usize, top_k);
+        for index in 0..dataset.len() {
+            let entry = dataset.get(index)?;
+            self.add_to_evaluation(&mut evaluation, &entry.input, &entry.expected_output, loss)?;
         }
-        Ok(error_rate)
+        evaluation.report()
     }
 
+    fn add_to_evaluation(
+        &self,
+        evaluation: &mut Evaluation,
+        input: &[T],
+        expected_output: &[T],
+        loss: &dyn Loss<T>,
+    ) -> Result<()> {
+        let feedforward = Feedforward::run(self, input)?;
+        let actual = feedforward
+            .results
+            .last()
+            .context("Evaluate: result has no last layer")?;
+        let loss = loss.loss(&Matrix::from_vec(expected_output), actual)?;
+        evaluation.add(&to_f64(expected_output), &to_f64(actual.as_slice()), loss)
+    }
+
+    /// Trains the neural network with the batches of the data loader, the optimizer decides how
+    /// the weights and biases are updated. The state of the optimizer is kept after training,
+    /// so training can be resumed with the same optimizer. The random number generator is used
+    /// to shuffle the data loader.
+    ///
+    /// When the data loader has validation data, the neural network is evaluated with it after
+    /// every round. The trained neural network has the weights and biases of the round with the
+    /// lowest validation loss, and with a patience training stops when the validation loss
+    /// didn't improve for that amount of rounds. The history has the metrics of every round.
+    ///
+    /// The learning rate schedule of the options starts at the learning rate of the optimizer,
+    /// after training the optimizer gets this learning rate back.
     pub fn train(
         self,
-        training_data: &TrainingData,
-        rounds: u32,
-        learning_rate: f64,
-    ) -> Result<NeuralNetwork> {
+        data_loader: &DataLoader<T>,
+        options: &TrainingOptions<T>,
+        optimizer: &mut dyn Optimizer<T>,
+        rng: &mut impl Rng,
+    ) -> Result<(NeuralNetwork<T>, TrainingHistory)> {
+        self.train_with_observer(data_loader, options, optimizer, rng, &mut NoObserver)
+    }
+
+    /// Trains the neural network like train, the observer gets the progress after every batch
+    /// and every round and can stop training
+    pub fn train_with_observer(
+        self,
+        data_loader: &DataLoader<T>,
+        options: &TrainingOptions<T>,
+        optimizer: &mut dyn Optimizer<T>,
+        rng: &mut impl Rng,
+        observer: &mut dyn TrainingObserver,
+    ) -> Result<(NeuralNetwork<T>, TrainingHistory)> {
         let mut nn = self;
-        for _ in 0..rounds {
-            for entry in training_data.0.iter() {
-                ensure!(
-                    entry.input.len() == nn.amount_of_input_neurons as usize,
-                    "Neuralnetwork: TrainingEntry input should be of same size as amount_of_input_neurons"
-                );
-                ensure!(
-                    entry.expected_output.len() == nn.amount_of_output_neurons as usize,
-                    "Neuralnetwork: TrainingEntry expected_output should be of same size as amount_of_output_neurons"
-                );
-                let feedforward = Feedforward::run(&nn, &entry.input)?;
-                let backpropagation = Backpropagation::run(
-                    &learning_rate,
-                    &nn.layers,
-                    &entry.expected_output,
-                    &feedforward,
-                )?;
-                nn.layers = backpropagation.new_layers;
+        let validation = data_loader.validation();
+        let initial_learning_rate = optimizer.learning_rate();
+        let mut scheduler = Scheduler::new(&options.learning_rate_schedule, initial_learning_rate)?;
+        ensure!(
+            validation.is_some() || !options.learning_rate_schedule.needs_validation(),
+            "Neuralnetwork: the learning rate schedule needs validation data"
+        );
+        let start = observer.now();
+        let mut progress = TrainingProgress {
+            round: 0,
+            amount_of_rounds: options.rounds,
+            batch: 0,
+            amount_of_batches: data_loader.amount_of_batches(),
+            step: 0,
+            loss: 0.0,
+            round_loss: 0.0,
+            elapsed: Duration::ZERO,
+            gradient_norms: GradientNorms::default(),
+        };
+        let mut history = TrainingHistory::default();
+        let mut best: Option<(u32, f64, Vec<Layer<T>>)> = None;
+        'rounds: for round in 1..=options.rounds {
+            progress.round = round;
+            progress.batch = 0;
+            let mut metrics = BatchMetrics::default();
+            for batch in data_loader.batches(rng)? {
+                optimizer.set_learning_rate(scheduler.learning_rate(round - 1, progress.step));
+                let (batch_metrics, gradient_norms) =
+                    nn.train_batch(&batch?, options, optimizer, rng)?;
+                metrics = metrics + batch_metrics;
+                progress.batch += 1;
+                progress.step += 1;
+                progress.loss = batch_metrics.mean_loss();
+                progress.round_loss = metrics.mean_loss();
+                progress.elapsed = observer.now().saturating_sub(start);
+                progress.gradient_norms = gradient_norms;
+                if observer.after_batch(&progress) == TrainingControl::Stop {
+                    history.cancelled = true;
+                    break 'rounds;
+                }
             }
+            let validation_report = match &validation {
+                Some(validation) => {
+                    Some(nn.evaluate_dataset_with(validation, options.loss.as_ref(), 1)?)
+                }
+                None => None,
+            };
+            let round_metrics = RoundMetrics {
+                round,
+                training_loss: metrics.mean_loss(),
+                training_accuracy: metrics.accuracy(),
+                validation_loss: validation_report.as_ref().map(|report| report.mean_loss),
+                validation_accuracy: validation_report.as_ref().map(|report| report.accuracy),
+                learning_rate: optimizer.learning_rate(),
+            };
+            scheduler.end_of_round(validation_report.as_ref().map(|report| report.mean_loss));
+            progress.elapsed = observer.now().saturating_sub(start);
+            let control = observer.after_round(&progress, &round_metrics);
+            history.rounds.push(round_metrics);
+            if let Some(report) = validation_report {
+                // a NaN loss is never an improvement, like in Scheduler::end_of_round
+                let best_loss = best
+                    .as_ref()
+                    .map_or(f64::INFINITY, |(_, best_loss, _)| *best_loss);
+                if report.mean_loss < best_loss {
+                    best = Some((round, report.mean_loss, nn.layers.clone()));
+                }
+                // without a best round no round improved yet, patience counts from the start
+                let best_round = best.as_ref().map_or(0, |(best_round, _, _)| *best_round);
+                if control == TrainingControl::Continue
+                    && options
+                        .patience
+                        .is_some_and(|patience| round - best_round >= patience)
+                    && round < options.rounds
+                {
+                    history.stopped_early = true;
+                    break;
+                }
+            }
+            if control == TrainingControl::Stop {
+                history.cancelled = round < options.rounds;
+                break;
+            }
+        }
+        if let Some((best_round, _, layers)) = best {
+            nn.layers = layers;
+            history.best_round = Some(best_round);
+        }
+        optimizer.set_learning_rate(initial_learning_rate);
+        Ok((nn, history))
+    }
+
+    /// Updates the weights and biases with one batch, for training step by step instead of
+    /// with train. Only the loss, the regularization and the gradient clipping of the options
+    /// are used, the dropout masks of the layers are sampled with the rng. Returns the metrics
+    /// of the batch, measured before the update, and the norms of the gradients of the batch.
+    pub fn train_batch(
+        &mut self,
+        batch: &[TrainingEntry<T>],
+        options: &TrainingOptions<T>,
+        optimizer: &mut dyn Optimizer<T>,
+        rng: &mut impl Rng,
+    ) -> Result<(BatchMetrics, GradientNorms)> {
+        let regularization = &options.regularization;
+        regularization.validate()?;
+        options.gradient_clipping.validate()?;
+        let masks = DropoutMasks::sample(&self.layers, batch.len(), rng)?;
+        let (mut gradients, mut metrics) =
+            self.gradients_of_batch(batch, options.loss.as_ref(), &masks)?;
+        metrics.sum_of_losses +=
+            regularization.penalty(&self.layers) * metrics.amount_of_entries as f64;
+        regularization.add_to_gradients(&self.layers, &mut gradients);
+        let gradient_norms = options.gradient_clipping.apply(&mut gradients);
+        optimizer.update(&mut self.layers, &gradients)?;
+        regularization.apply_max_norm(&mut self.layers);
+        Ok((metrics, gradient_norms))
+    }
+
+    /// Calculates the gradients of the weights and biases, averaged over the batch, and the
+    /// metrics of the batch. With the parallel feature a large batch is split over multiple
+    /// threads, every part of the batch gets its own columns of the dropout masks. The gradients
+    /// can then differ slightly between machines with a different amount of CPU cores, see
+    /// parallel::average_gradients_in_parallel.
+    fn gradients_of_batch(
+        &self,
+        batch: &[TrainingEntry<T>],
+        loss: &dyn Loss<T>,
+        masks: &DropoutMasks<T>,
+    ) -> Result<(Vec<LayerGradients<T>>, BatchMetrics)> {
+        #[cfg(feature = "parallel")]
+        if let Some(gradients) = parallel::average_gradients_in_parallel(
+            parallel::amount_of_threads(),
+            &(0..batch.len()).collect::<Vec<usize>>(),
+            self.multiplications_per_entry(),
+            |part| {
+                let first = part.first().copied().unwrap_or_default();
+                self.gradients_of_entries(
+                    &batch[first..first + part.len()],
+                    loss,
+                    &masks.columns(first, part.len())?,
+                )
+            },
+        ) {
+            return gradients;
         }
-        Ok(nn)
+        self.gradients_of_entries(batch, loss, masks)
+    }
+
+    fn gradients_of_entries(
+        &self,
+        entries: &[TrainingEntry<T>],
+        loss: &dyn Loss<T>,
+        masks: &DropoutMasks<T>,
+    ) -> Result<(Vec<LayerGradients<T>>, BatchMetrics)> {
+        for entry in entries.iter() {
+            ensure!(
+                entry.input.len() == self.amount_of_input_neurons() as usize,
+                "Neuralnetwork: TrainingEntry input should be of same size as amount_of_input_neurons"
+            );
+            ensure!(
+                entry.expected_output.len() == self.amount_of_output_neurons() as usize,
+                "Neuralnetwork: TrainingEntry expected_output should be of same size as amount_of_output_neurons"
+            );
+        }
+        let input = Matrix::from_columns(
+            &entries
+                .iter()
+                .map(|entry| entry.input.as_slice())
+                .collect::<Vec<&[T]>>(),
+        )?;
+        let target = Matrix::from_columns(
+            &entries
+                .iter()
+                .map(|entry| entry.expected_output.as_slice())
+                .collect::<Vec<&[T]>>(),
+        )?;
+        let feedforward = Feedforward::run_batch_in_mode(self, input, Mode::Training(masks))?;
+        let actual = feedforward
+            .results
+            .last()
+            .context("Neuralnetwork: feedforward has no last layer")?;
+        let metrics = BatchMetrics::new(loss, &target, actual)?;
+        Ok((
+            Backpropagation::run(loss, &self.layers, &target, &feedforward)?.gradients,
+            metrics,
+        ))
+    }
+
+    /// The multiplications of the feedforward and the backpropagation of one entry: every
+    /// weight is used for the output, for the error rate of the layer before it and for its
+    /// gradient
+    #[cfg(feature = "parallel")]
+    fn multiplications_per_entry(&self) -> usize {
+        3 * self
+            .layers
+            .iter()
+            .map(|layer| layer.weights.rows() * layer.weights.cols())
+            .sum::<usize>()
     }
 }
 
-fn create_input_layer(amount_of_hidden_neurons: u32, amount_of_input_neurons: u32) -> Layer {
-    Layer(Matrix::new_with_random_values(
+fn to_f64<T: Float>(values: &[T]) -> Vec<f64> {
+    values.iter().map(|value| value.to_f64()).collect()
+}
+
+fn create_input_layer<T: Float>(
+    amount_of_hidden_neurons: u32,
+    amount_of_input_neurons: u32,
+    rng: &mut impl Rng,
+) -> Layer<T> {
+    Layer::from(Initializer::default().weights(
         amount_of_hidden_neurons,
         amount_of_input_neurons,
+        rng,
     ))
 }
 
-fn create_hidden_layers(amount_of_hidden_layers: u32, amount_of_hidden_neurons: u32) -> Vec<Layer> {
+fn create_hidden_layers<T: Float>(
+    amount_of_hidden_layers: u32,
+    amount_of_hidden_neurons: u32,
+    rng: &mut impl Rng,
+) -> Vec<Layer<T>> {
     (0..amount_of_hidden_layers)
-        .into_iter()
         .map(|_| {
-            Layer(Matrix::new_with_random_values(
+            Layer::from(Initializer::default().weights(
                 amount_of_hidden_neurons,
                 amount_of_hidden_neurons,
+                rng,
             ))
         })
-        .collect::<Vec<Layer>>()
+        .collect::<Vec<Layer<T>>>()
 }
 
-fn create_output_layer(amount_of_output_neurons: u32, amount_of_hidden_neurons: u32) -> Layer {
-    Layer(Matrix::new_with_random_values(
+fn create_output_layer<T: Float>(
+    amount_of_output_neurons: u32,
+    amount_of_hidden_neurons: u32,
+    rng: &mut impl Rng,
+) -> Layer<T> {
+    Layer::from(Initializer::default().weights(
         amount_of_output_neurons,
         amount_of_hidden_neurons,
+        rng,
     ))
 }
 
 #[cfg(test)]
 mod test {
     use crate::{
+        dataset::dataloader::DataLoader,
+        logic::feedforward::DropoutMasks,
         matrix::matrix::Matrix,
         neuralnetwork::{
+            activation::Activation,
+            clipping::GradientClipping,
             errorrate::{ErrorRateData, ErrorRateEntry},
+            evaluation::EvaluationOptions,
             layer::Layer,
+            loss::{Loss, MeanSquaredError},
             neuralnetwork::NeuralNetwork,
+            observer::{TrainingControl, TrainingObserver, TrainingProgress},
+            optimizer::{Adam, Sgd},
             query::{QueryData, QueryEntry},
-            training::{TrainingData, TrainingEntry},
+            regularization::Regularization,
+            schedule::LearningRateSchedule,
+            training::{
+                RoundMetrics, TrainingData, TrainingEntry, TrainingHistory, TrainingOptions,
+            },
         },
     };
+    use anyhow::Result;
+    use rand::{rngs::StdRng, SeedableRng};
+    use std::sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    };
 
     #[test]
     fn testing_new() {
-        let nn = NeuralNetwork::new_with_random_values(3, 4, 3, 2);
-        assert_eq!(nn.amount_of_input_neurons, 3);
-        assert_eq!(nn.amount_of_hidden_neurons, 4);
-        assert_eq!(nn.amount_of_output_neurons, 3);
-        assert_eq!(nn.amount_of_hidden_layers, 2);
+        let nn: NeuralNetwork =
+            NeuralNetwork::new_with_random_values(3, 4, 3, 2, &mut StdRng::seed_from_u64(0));
+        assert_eq!(nn.amount_of_input_neurons(), 3);
+        assert_eq!(nn.amount_of_output_neurons(), 3);
+        assert_eq!(nn.layer_sizes(), vec![3, 4, 4, 4, 3]);
         assert_eq!(nn.layers.len(), 4);
-        let input_layer = &nn.layers[0].0 .0;
-        assert_eq!(input_layer.len(), 4);
-        assert_eq!(input_layer[0].len(), 3);
-        let hidden_layer_1 = &nn.layers[1].0 .0;
-        assert_eq!(hidden_layer_1.len(), 4);
-        assert_eq!(hidden_layer_1[0].len(), 4);
-        let hidden_layer_2 = &nn.layers[2].0 .0;
-        assert_eq!(hidden_layer_2.len(), 4);
-        assert_eq!(hidden_layer_2[0].len(), 4);
-        let output_layer = &nn.layers[3].0 .0;
-        assert_eq!(output_layer.len(), 3);
-        assert_eq!(output_layer[0].len(), 4);
+        let input_layer = &nn.layers[0].weights;
+        assert_eq!(input_layer.rows(), 4);
+        assert_eq!(input_layer.cols(), 3);
+        let hidden_layer_1 = &nn.layers[1].weights;
+        assert_eq!(hidden_layer_1.rows(), 4);
+        assert_eq!(hidden_layer_1.cols(), 4);
+        let hidden_layer_2 = &nn.layers[2].weights;
+        assert_eq!(hidden_layer_2.rows(), 4);
+        assert_eq!(hidden_layer_2.cols(), 4);
+        let output_layer = &nn.layers[3].weights;
+        assert_eq!(output_layer.rows(), 3);
+        assert_eq!(output_layer.cols(), 4);
+        for layer in nn.layers.iter() {
+            assert_eq!(
+                layer.biases,
+                Matrix::new_with_zeros(layer.weights.rows() as u32, 1)
+            );
+        }
+    }
+
+    #[test]
+    fn deserialize_neural_network_from_old_json_format() {
+        let nn: NeuralNetwork = serde_json::from_str(
+            r#"{"layers":[[[0.9,0.3,0.4],[0.2,0.8,0.2],[0.1,0.5,0.6]],[[0.3,0.7,0.5],[0.6,0.5,0.2],[0.8,0.1,0.9]]],"amount_of_input_neurons":3,"amount_of_hidden_neurons":3,"amount_of_output_neurons":3,"amount_of_hidden_layers":1}"#,
+        )
+        .unwrap();
+        assert_eq!(nn.layer_sizes(), vec![3, 3, 3]);
+        let actual_result = nn
+            .query(&QueryData(&vec![QueryEntry {
+                input: vec![0.9, 0.1, 0.8],
+            }]))
+            .unwrap();
+        assert_eq!(
+            actual_result.0.first().unwrap().0,
+            vec![0.7263033450139793, 0.7085980724248232, 0.778097059561142]
+        );
     }
 
     #[test]
@@ -183,22 +543,24 @@ mod test {
         let nn = NeuralNetwork {
             layers: vec![
                 // input to hidden weights
-                Layer(Matrix(vec![
-                    vec![0.9, 0.3, 0.4],
-                    vec![0.2, 0.8, 0.2],
-                    vec![0.1, 0.5, 0.6],
-                ])),
+                Layer::from(
+                    Matrix::from_rows(vec![
+                        vec![0.9, 0.3, 0.4],
+                        vec![0.2, 0.8, 0.2],
+                        vec![0.1, 0.5, 0.6],
+                    ])
+                    .unwrap(),
+                ),
                 // hidden to output weights
-                Layer(Matrix(vec![
-                    vec![0.3, 0.7, 0.5],
-                    vec![0.6, 0.5, 0.2],
-                    vec![0.8, 0.1, 0.9],
-                ])),
+                Layer::from(
+                    Matrix::from_rows(vec![
+                        vec![0.3, 0.7, 0.5],
+                        vec![0.6, 0.5, 0.2],
+                        vec![0.8, 0.1, 0.9],
+                    ])
+                    .unwrap(),
+                ),
             ],
-            amount_of_input_neurons: 3,
-            amount_of_hidden_neurons: 3,
-            amount_of_output_neurons: 3,
-            amount_of_hidden_layers: 1,
         };
         let actual_result = nn
             .query(&QueryData(&vec![QueryEntry {
@@ -206,40 +568,60 @@ mod test {
             }]))
             .unwrap();
         assert_eq!(
-            actual_result.0.get(0).unwrap().0,
+            actual_result.0.first().unwrap().0,
             vec![0.7263033450139793, 0.7085980724248232, 0.778097059561142]
         );
     }
 
     #[test]
-    fn testing_error_rate() {
+    fn testing_evaluate() {
         let nn = NeuralNetwork {
             layers: vec![
                 // input to hidden weights
-                Layer(Matrix(vec![
-                    vec![0.9, 0.3, 0.4],
-                    vec![0.2, 0.8, 0.2],
-                    vec![0.1, 0.5, 0.6],
-                ])),
+                Layer::from(
+                    Matrix::from_rows(vec![
+                        vec![0.9, 0.3, 0.4],
+                        vec![0.2, 0.8, 0.2],
+                        vec![0.1, 0.5, 0.6],
+                    ])
+                    .unwrap(),
+                ),
                 // hidden to output weights
-                Layer(Matrix(vec![
-                    vec![0.3, 0.7, 0.5],
-                    vec![0.6, 0.5, 0.2],
-                    vec![0.8, 0.1, 0.9],
-                ])),
+                Layer::from(
+                    Matrix::from_rows(vec![
+                        vec![0.3, 0.7, 0.5],
+                        vec![0.6, 0.5, 0.2],
+                        vec![0.8, 0.1, 0.9],
+                    ])
+                    .unwrap(),
+                ),
             ],
-            amount_of_input_neurons: 3,
-            amount_of_hidden_neurons: 3,
-            amount_of_output_neurons: 3,
-            amount_of_hidden_layers: 1,
         };
-        let actual_result = nn
-            .error_rate_of_network(&ErrorRateData(&vec![ErrorRateEntry {
-                input: vec![0.9, 0.1, 0.8],
-                expected_output: vec![0.7263033450139793, 0.7085980724248232, 0.778097059561142],
-            }]))
+        let report = nn
+            .evaluate(
+                &ErrorRateData(&vec![
+                    ErrorRateEntry {
+                        input: vec![0.9, 0.1, 0.8],
+                        expected_output: vec![0.0, 0.0, 1.0],
+                    },
+                    ErrorRateEntry {
+                        input: vec![0.9, 0.1, 0.8],
+                        expected_output: vec![1.0, 0.0, 0.0],
+                    },
+                ]),
+                &EvaluationOptions {
+                    top_k: 2,
+                    ..EvaluationOptions::default()
+                },
+            )
             .unwrap();
-        assert_eq!(actual_result, 0.0);
+        // the output is [0.726, 0.709, 0.778], so only the first entry is correct
+        assert_eq!(report.amount_of_entries, 2);
+        assert_eq!(report.accuracy, 0.5);
+        assert_eq!(report.top_k_accuracy, 1.0);
+        assert_eq!(report.mean_loss, 0.5653312033726221);
+        assert_eq!(report.confusion_matrix.0[0], vec![0, 0, 1]);
+        assert_eq!(report.confusion_matrix.0[2], vec![0, 0, 1]);
     }
 
     #[test]
@@ -247,42 +629,788 @@ mod test {
         let old_nn = NeuralNetwork {
             layers: vec![
                 // input to hidden weights
-                Layer(Matrix(vec![
-                    vec![0.9, 0.3, 0.4],
-                    vec![0.2, 0.8, 0.2],
-                    vec![0.1, 0.5, 0.6],
-                ])),
+                Layer::from(
+                    Matrix::from_rows(vec![
+                        vec![0.9, 0.3, 0.4],
+                        vec![0.2, 0.8, 0.2],
+                        vec![0.1, 0.5, 0.6],
+                    ])
+                    .unwrap(),
+                ),
                 // hidden to output weights
-                Layer(Matrix(vec![
-                    vec![0.3, 0.7, 0.5],
-                    vec![0.6, 0.5, 0.2],
-                    vec![0.8, 0.1, 0.9],
-                ])),
+                Layer::from(
+                    Matrix::from_rows(vec![
+                        vec![0.3, 0.7, 0.5],
+                        vec![0.6, 0.5, 0.2],
+                        vec![0.8, 0.1, 0.9],
+                    ])
+                    .unwrap(),
+                ),
             ],
-            amount_of_input_neurons: 3,
-            amount_of_hidden_neurons: 3,
-            amount_of_output_neurons: 3,
-            amount_of_hidden_layers: 1,
         };
         let input = vec![0.1, 0.2, 0.3];
         let new_nn = old_nn
             .train(
-                &TrainingData(vec![TrainingEntry {
+                &DataLoader::new(TrainingData(vec![TrainingEntry {
                     input: input.clone(),
                     expected_output: vec![0.5, 0.5, 0.5],
-                }]),
-                1,
-                0.3,
+                }])),
+                &TrainingOptions {
+                    rounds: 1,
+                    ..TrainingOptions::default()
+                },
+                &mut Sgd::new(0.3),
+                &mut StdRng::seed_from_u64(0),
             )
-            .unwrap();
+            .unwrap()
+            .0;
         let actual_result = new_nn
             .query(&QueryData(&vec![QueryEntry {
                 input: input.clone(),
             }]))
             .unwrap();
         assert_eq!(
-            actual_result.0.get(0).unwrap().0,
-            vec![0.6973393995613739, 0.673130310893913, 0.7329538405694166]
+            actual_result.0.first().unwrap().0,
+            vec![0.6929419197159212, 0.6689716638613553, 0.7280623922144926]
+        );
+    }
+
+    #[test]
+    fn testing_train_with_batch_averages_the_adjustments() {
+        let create_nn = || NeuralNetwork::<f64> {
+            layers: vec![
+                Layer::from(
+                    Matrix::from_rows(vec![vec![0.9, 0.3, 0.4], vec![0.2, 0.8, 0.2]]).unwrap(),
+                ),
+                Layer::from(Matrix::from_rows(vec![vec![0.3, 0.7], vec![0.6, 0.5]]).unwrap()),
+            ],
+        };
+        let entry = || TrainingEntry {
+            input: vec![0.1, 0.2, 0.3],
+            expected_output: vec![0.01, 0.99],
+        };
+        let trained_per_entry = create_nn()
+            .train(
+                &DataLoader::new(TrainingData(vec![entry()])),
+                &TrainingOptions::default(),
+                &mut Sgd::new(0.3),
+                &mut StdRng::seed_from_u64(0),
+            )
+            .unwrap()
+            .0;
+        let trained_with_batch = create_nn()
+            .train(
+                &DataLoader::new(TrainingData(vec![entry(), entry()])).batch_size(2),
+                &TrainingOptions::default(),
+                &mut Sgd::new(0.3),
+                &mut StdRng::seed_from_u64(0),
+            )
+            .unwrap()
+            .0;
+        for (actual, expected) in trained_with_batch
+            .layers
+            .iter()
+            .zip(trained_per_entry.layers.iter())
+        {
+            for (actual_value, expected_value) in actual
+                .weights
+                .as_slice()
+                .iter()
+                .zip(expected.weights.as_slice().iter())
+            {
+                assert!((actual_value - expected_value).abs() < 1e-15);
+            }
+        }
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn batch_split_over_threads_has_the_same_gradients() {
+        let mut nn: NeuralNetwork =
+            NeuralNetwork::new_with_random_values(3, 4, 2, 1, &mut StdRng::seed_from_u64(0));
+        nn.layers[0].dropout = 0.5;
+        let batch = (0..10)
+            .map(|i| TrainingEntry {
+                input: vec![0.1 * i as f64, 0.5, 1.0 - 0.1 * i as f64],
+                expected_output: vec![(i % 2) as f64, ((i + 1) % 2) as f64],
+            })
+            .collect::<Vec<TrainingEntry>>();
+        let loss = crate::neuralnetwork::loss::MeanSquaredError;
+        let masks =
+            DropoutMasks::sample(&nn.layers, batch.len(), &mut StdRng::seed_from_u64(0)).unwrap();
+        let expected = nn.gradients_of_entries(&batch, &loss, &masks).unwrap().0;
+        let actual = crate::logic::parallel::average_gradients_in_parallel(
+            4,
+            &(0..batch.len()).collect::<Vec<usize>>(),
+            usize::MAX,
+            |part| {
+                nn.gradients_of_entries(
+                    &batch[part[0]..part[0] + part.len()],
+                    &loss,
+                    &masks.columns(part[0], part.len())?,
+                )
+            },
+        )
+        .unwrap()
+        .unwrap()
+        .0;
+        for (actual, expected) in actual.iter().zip(expected.iter()) {
+            for (actual_value, expected_value) in actual
+                .weights
+                .values()
+                .chain(actual.biases.values())
+                .zip(expected.weights.values().chain(expected.biases.values()))
+            {
+                assert!((actual_value - expected_value).abs() < 1e-12);
+            }
+        }
+    }
+
+    #[test]
+    fn testing_train_with_batch_size_zero() {
+        let nn: NeuralNetwork =
+            NeuralNetwork::new_with_random_values(3, 3, 3, 1, &mut StdRng::seed_from_u64(0));
+        let result = nn.train(
+            &DataLoader::new(TrainingData(vec![])).batch_size(0),
+            &TrainingOptions::default(),
+            &mut Sgd::new(0.3),
+            &mut StdRng::seed_from_u64(0),
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn testing_train_can_be_resumed_with_the_same_optimizer() {
+        let create_nn = || NeuralNetwork::<f64> {
+            layers: vec![
+                Layer::from(
+                    Matrix::from_rows(vec![vec![0.9, 0.3, 0.4], vec![0.2, 0.8, 0.2]]).unwrap(),
+                ),
+                Layer::from(Matrix::from_rows(vec![vec![0.3, 0.7], vec![0.6, 0.5]]).unwrap()),
+            ],
+        };
+        let training_data = DataLoader::new(TrainingData(vec![
+            TrainingEntry {
+                input: vec![0.1, 0.2, 0.3],
+                expected_output: vec![0.01, 0.99],
+            },
+            TrainingEntry {
+                input: vec![0.3, 0.2, 0.1],
+                expected_output: vec![0.99, 0.01],
+            },
+        ]));
+        let trained_at_once = create_nn()
+            .train(
+                &training_data,
+                &TrainingOptions {
+                    rounds: 2,
+                    ..TrainingOptions::default()
+                },
+                &mut Adam::new(0.01),
+                &mut StdRng::seed_from_u64(0),
+            )
+            .unwrap()
+            .0;
+        let mut optimizer = Adam::new(0.01);
+        let trained_once = create_nn()
+            .train(
+                &training_data,
+                &TrainingOptions::default(),
+                &mut optimizer,
+                &mut StdRng::seed_from_u64(0),
+            )
+            .unwrap()
+            .0;
+        let mut resumed_optimizer: Adam =
+            serde_json::from_str(&serde_json::to_string(&optimizer).unwrap()).unwrap();
+        let trained_twice = trained_once
+            .train(
+                &training_data,
+                &TrainingOptions::default(),
+                &mut resumed_optimizer,
+                &mut StdRng::seed_from_u64(0),
+            )
+            .unwrap()
+            .0;
+        assert_eq!(trained_twice.layers, trained_at_once.layers);
+    }
+
+    #[test]
+    fn the_same_seed_gives_the_same_trained_neural_network() {
+        let train_with_seed = |seed| {
+            NeuralNetwork::new_with_random_values(3, 4, 2, 1, &mut StdRng::seed_from_u64(seed))
+                .train(
+                    &DataLoader::new(TrainingData(vec![TrainingEntry {
+                        input: vec![0.1, 0.2, 0.3],
+                        expected_output: vec![0.01, 0.99],
+                    }])),
+                    &TrainingOptions::default(),
+                    &mut Adam::new(0.01),
+                    &mut StdRng::seed_from_u64(seed),
+                )
+                .unwrap()
+                .0
+        };
+        let nn: NeuralNetwork = train_with_seed(7);
+        assert_eq!(
+            serde_json::to_string(&train_with_seed(7)).unwrap(),
+            serde_json::to_string(&nn).unwrap()
+        );
+        assert_ne!(train_with_seed(8).layers, nn.layers);
+    }
+
+    #[test]
+    fn shuffled_training_depends_on_the_seed() {
+        let training_data = DataLoader::new(TrainingData(
+            (0..8)
+                .map(|i| TrainingEntry {
+                    input: vec![0.1 * i as f64, 0.5, 1.0 - 0.1 * i as f64],
+                    expected_output: vec![(i % 2) as f64, ((i + 1) % 2) as f64],
+                })
+                .collect(),
+        ))
+        .batch_size(3);
+        let train = |training_data: &DataLoader, seed| {
+            NeuralNetwork::new_with_random_values(3, 4, 2, 1, &mut StdRng::seed_from_u64(0))
+                .train(
+                    training_data,
+                    &TrainingOptions {
+                        rounds: 3,
+                        ..TrainingOptions::default()
+                    },
+                    &mut Sgd::new(0.3),
+                    &mut StdRng::seed_from_u64(seed),
+                )
+                .unwrap()
+                .0
+        };
+        let in_order: NeuralNetwork = train(&training_data, 0);
+        let training_data = training_data.shuffle(true);
+        let shuffled = train(&training_data, 0);
+        assert_ne!(shuffled.layers, in_order.layers);
+        assert_eq!(train(&training_data, 0).layers, shuffled.layers);
+        assert_ne!(train(&training_data, 1).layers, shuffled.layers);
+    }
+
+    #[test]
+    fn training_history_without_validation() {
+        let nn: NeuralNetwork =
+            NeuralNetwork::new_with_random_values(3, 4, 2, 1, &mut StdRng::seed_from_u64(0));
+        let (_, history) = nn
+            .train(
+                &DataLoader::new(TrainingData(vec![TrainingEntry {
+                    input: vec![0.1, 0.2, 0.3],
+                    expected_output: vec![0.01, 0.99],
+                }])),
+                &TrainingOptions {
+                    rounds: 3,
+                    patience: Some(1),
+                    ..TrainingOptions::default()
+                },
+                &mut Sgd::new(0.3),
+                &mut StdRng::seed_from_u64(0),
+            )
+            .unwrap();
+        assert_eq!(history.rounds.len(), 3);
+        assert_eq!(history.best_round, None);
+        assert!(!history.stopped_early);
+        assert!(history.rounds[2].training_loss < history.rounds[0].training_loss);
+        assert_eq!(history.rounds[0].training_accuracy, 1.0);
+        assert_eq!(history.rounds[0].validation_loss, None);
+    }
+
+    #[test]
+    fn early_stopping_keeps_the_weights_of_the_best_round() {
+        // the validation data expects the opposite of the training data, so the validation loss
+        // gets worse every round
+        let training_data = TrainingData(vec![TrainingEntry {
+            input: vec![0.1, 0.2, 0.3],
+            expected_output: vec![0.01, 0.99],
+        }]);
+        let validation_data = TrainingData(vec![TrainingEntry {
+            input: vec![0.1, 0.2, 0.3],
+            expected_output: vec![0.99, 0.01],
+        }]);
+        let train = |rounds, data_loader: &DataLoader| {
+            NeuralNetwork::new_with_random_values(3, 4, 2, 1, &mut StdRng::seed_from_u64(0))
+                .train(
+                    data_loader,
+                    &TrainingOptions {
+                        rounds,
+                        patience: Some(2),
+                        ..TrainingOptions::default()
+                    },
+                    &mut Sgd::new(0.3),
+                    &mut StdRng::seed_from_u64(0),
+                )
+                .unwrap()
+        };
+        let (trained_once, _): (NeuralNetwork, _) = train(1, &DataLoader::new(&training_data));
+        let (nn, history) = train(
+            10,
+            &DataLoader::new(&training_data).validation_data(&validation_data),
+        );
+        assert_eq!(history.rounds.len(), 3);
+        assert_eq!(history.best_round, Some(1));
+        assert!(history.stopped_early);
+        assert!(
+            history.rounds[2].validation_loss.unwrap() > history.rounds[0].validation_loss.unwrap()
+        );
+        assert_eq!(history.rounds[0].validation_accuracy, Some(0.0));
+        assert_eq!(nn.layers, trained_once.layers);
+    }
+
+    /// The mean squared error, but NaN once the flag is set
+    #[derive(Debug)]
+    struct DivergingLoss(Arc<AtomicBool>);
+
+    impl Loss for DivergingLoss {
+        fn loss(&self, target: &Matrix, actual: &Matrix) -> Result<f64> {
+            match self.0.load(Ordering::Relaxed) {
+                true => Ok(f64::NAN),
+                false => MeanSquaredError.loss(target, actual),
+            }
+        }
+
+        fn error(
+            &self,
+            target: &Matrix,
+            actual: &Matrix,
+            activation: &Activation,
+        ) -> Result<Matrix> {
+            MeanSquaredError.error(target, actual, activation)
+        }
+    }
+
+    /// Sets the flag after the first round
+    struct DivergeAfterFirstRound(Arc<AtomicBool>);
+
+    impl TrainingObserver for DivergeAfterFirstRound {
+        fn after_round(
+            &mut self,
+            _progress: &TrainingProgress,
+            _metrics: &RoundMetrics,
+        ) -> TrainingControl {
+            self.0.store(true, Ordering::Relaxed);
+            TrainingControl::Continue
+        }
+    }
+
+    #[test]
+    fn a_nan_validation_loss_is_not_the_best_round() {
+        let training_data = TrainingData(vec![TrainingEntry {
+            input: vec![0.1, 0.2, 0.3],
+            expected_output: vec![0.01, 0.99],
+        }]);
+        let new_neural_network = || -> NeuralNetwork {
+            NeuralNetwork::new_with_random_values(3, 4, 2, 1, &mut StdRng::seed_from_u64(0))
+        };
+        let (trained_once, _) = new_neural_network()
+            .train(
+                &DataLoader::new(&training_data),
+                &TrainingOptions::default(),
+                &mut Sgd::new(0.3),
+                &mut StdRng::seed_from_u64(0),
+            )
+            .unwrap();
+        let diverged = Arc::new(AtomicBool::new(false));
+        let (nn, history) = new_neural_network()
+            .train_with_observer(
+                &DataLoader::new(&training_data).validation_data(&training_data),
+                &TrainingOptions {
+                    rounds: 3,
+                    loss: Box::new(DivergingLoss(diverged.clone())),
+                    patience: Some(1),
+                    ..TrainingOptions::default()
+                },
+                &mut Sgd::new(0.3),
+                &mut StdRng::seed_from_u64(0),
+                &mut DivergeAfterFirstRound(diverged),
+            )
+            .unwrap();
+        assert!(history.rounds[1].validation_loss.unwrap().is_nan());
+        assert_eq!(history.best_round, Some(1));
+        assert!(history.stopped_early);
+        assert_eq!(history.rounds.len(), 2);
+        assert_eq!(nn.layers, trained_once.layers);
+    }
+
+    #[test]
+    fn training_with_regularization() {
+        let training_data = TrainingData(vec![TrainingEntry {
+            input: vec![0.1, 0.2, 0.3],
+            expected_output: vec![0.01, 0.99],
+        }]);
+        let nn: NeuralNetwork =
+            NeuralNetwork::new_with_random_values(3, 4, 2, 1, &mut StdRng::seed_from_u64(0));
+        let regularization = Regularization {
+            l1: 0.01,
+            l2: 0.1,
+            max_norm: Some(0.5),
+        };
+        let train = |regularization| {
+            NeuralNetwork {
+                layers: nn.layers.clone(),
+            }
+            .train(
+                &DataLoader::new(&training_data),
+                &TrainingOptions {
+                    regularization,
+                    ..TrainingOptions::default()
+                },
+                &mut Sgd::new(0.3),
+                &mut StdRng::seed_from_u64(0),
+            )
+            .unwrap()
+        };
+        let (_, history) = train(Regularization::default());
+        let (regularized, regularized_history) = train(regularization);
+        assert_eq!(
+            regularized_history.rounds[0].training_loss,
+            history.rounds[0].training_loss + regularization.penalty(&nn.layers)
+        );
+        for layer in regularized.layers.iter() {
+            for row in 0..layer.weights.rows() {
+                let norm = layer.weights.row(row).iter().map(|w| w * w).sum::<f64>();
+                assert!(norm.sqrt() <= 0.5 + 1e-12);
+            }
+        }
+    }
+
+    #[test]
+    fn training_with_gradient_clipping() {
+        let batch = vec![TrainingEntry {
+            input: vec![0.1, 0.2, 0.3],
+            expected_output: vec![0.01, 0.99],
+        }];
+        let nn: NeuralNetwork =
+            NeuralNetwork::new_with_random_values(3, 4, 2, 1, &mut StdRng::seed_from_u64(0));
+        let mut clipped = NeuralNetwork {
+            layers: nn.layers.clone(),
+        };
+        let (_, norms) = clipped
+            .train_batch(
+                &batch,
+                &TrainingOptions {
+                    gradient_clipping: GradientClipping {
+                        value: None,
+                        global_norm: Some(0.001),
+                    },
+                    ..TrainingOptions::default()
+                },
+                &mut Sgd::new(1.0),
+                &mut StdRng::seed_from_u64(0),
+            )
+            .unwrap();
+        assert_eq!(norms.layers.len(), 3);
+        assert!(norms.global > 0.001);
+        assert!(norms.clipped);
+        // with a learning rate of 1 the update is as long as the clipped gradients
+        let update = nn
+            .layers
+            .iter()
+            .zip(clipped.layers.iter())
+            .flat_map(|(before, after)| {
+                before
+                    .weights
+                    .values()
+                    .zip(after.weights.values())
+                    .chain(before.biases.values().zip(after.biases.values()))
+            })
+            .map(|(before, after)| (before - after) * (before - after))
+            .sum::<f64>()
+            .sqrt();
+        assert!((update - 0.001).abs() < 1e-12);
+    }
+
+    #[test]
+    fn dropped_neurons_are_not_trained() {
+        let mut nn: NeuralNetwork =
+            NeuralNetwork::new_with_random_values(3, 8, 2, 1, &mut StdRng::seed_from_u64(0));
+        nn.layers[0].dropout = 0.5;
+        let entry = TrainingEntry {
+            input: vec![0.1, 0.2, 0.3],
+            expected_output: vec![0.01, 0.99],
+        };
+        let masks = DropoutMasks::sample(&nn.layers, 1, &mut StdRng::seed_from_u64(0)).unwrap();
+        let mask = masks.0[0].as_ref().unwrap();
+        assert!(mask.values().all(|value| *value == 0.0 || *value == 2.0));
+        assert!(mask.values().any(|value| *value == 0.0));
+        let gradients = nn
+            .gradients_of_entries(std::slice::from_ref(&entry), &MeanSquaredError, &masks)
+            .unwrap()
+            .0;
+        for (neuron, value) in mask.values().enumerate() {
+            let dropped = *value == 0.0;
+            assert_eq!(
+                gradients[0].weights.row(neuron).iter().all(|g| *g == 0.0),
+                dropped
+            );
+            assert_eq!(gradients[0].biases[(neuron, 0)] == 0.0, dropped);
+            assert_eq!(
+                gradients[1].weights.column(neuron).all(|g| *g == 0.0),
+                dropped
+            );
+        }
+        // querying uses every neuron without scaling
+        let query = QueryData(&vec![QueryEntry {
+            input: entry.input.clone(),
+        }]);
+        let with_dropout = nn.query(&query).unwrap().0.remove(0).0;
+        nn.layers[0].dropout = 0.0;
+        assert_eq!(nn.query(&query).unwrap().0.remove(0).0, with_dropout);
+    }
+
+    #[test]
+    fn dropout_on_the_output_layer_is_an_error() {
+        let mut nn: NeuralNetwork =
+            NeuralNetwork::new_with_random_values(3, 4, 2, 1, &mut StdRng::seed_from_u64(0));
+        nn.layers.last_mut().unwrap().dropout = 0.5;
+        let result = nn.train(
+            &DataLoader::new(TrainingData(vec![TrainingEntry {
+                input: vec![0.1, 0.2, 0.3],
+                expected_output: vec![0.01, 0.99],
+            }])),
+            &TrainingOptions::default(),
+            &mut Sgd::new(0.3),
+            &mut StdRng::seed_from_u64(0),
+        );
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Feedforward: the output layer should not have dropout"
+        );
+    }
+
+    #[derive(Default)]
+    struct CountingObserver {
+        progress: Vec<TrainingProgress>,
+        rounds: Vec<u32>,
+        stop_after_batches: Option<u64>,
+    }
+
+    impl TrainingObserver for CountingObserver {
+        fn after_batch(&mut self, progress: &TrainingProgress) -> TrainingControl {
+            self.progress.push(progress.clone());
+            match self.stop_after_batches {
+                Some(batches) if progress.step >= batches => TrainingControl::Stop,
+                _ => TrainingControl::Continue,
+            }
+        }
+
+        fn after_round(
+            &mut self,
+            _progress: &TrainingProgress,
+            metrics: &RoundMetrics,
+        ) -> TrainingControl {
+            self.rounds.push(metrics.round);
+            TrainingControl::Continue
+        }
+    }
+
+    fn observed_training(observer: &mut CountingObserver) -> (NeuralNetwork, TrainingHistory) {
+        let training_data = TrainingData(vec![
+            TrainingEntry {
+                input: vec![0.1, 0.2, 0.3],
+                expected_output: vec![0.01, 0.99],
+            };
+            5
+        ]);
+        NeuralNetwork::new_with_random_values(3, 4, 2, 1, &mut StdRng::seed_from_u64(0))
+            .train_with_observer(
+                &DataLoader::new(training_data).batch_size(2),
+                &TrainingOptions {
+                    rounds: 3,
+                    ..TrainingOptions::default()
+                },
+                &mut Sgd::new(0.3),
+                &mut StdRng::seed_from_u64(0),
+                observer,
+            )
+            .unwrap()
+    }
+
+    #[test]
+    fn the_observer_gets_the_progress() {
+        let mut observer = CountingObserver::default();
+        let (_, history) = observed_training(&mut observer);
+        assert!(!history.cancelled);
+        assert_eq!(observer.rounds, vec![1, 2, 3]);
+        assert_eq!(observer.progress.len(), 9);
+        let last = observer.progress.last().unwrap();
+        assert_eq!((last.round, last.amount_of_rounds), (3, 3));
+        assert_eq!((last.batch, last.amount_of_batches), (3, 3));
+        assert_eq!(last.step, 9);
+        assert_eq!(last.round_loss, history.rounds[2].training_loss);
+        assert_eq!(last.gradient_norms.layers.len(), 3);
+        assert!(!last.gradient_norms.clipped);
+        assert_eq!(observer.progress[3].batch, 1);
+        assert_eq!(observer.progress[3].round, 2);
+    }
+
+    #[test]
+    fn the_observer_can_stop_training() {
+        let mut observer = CountingObserver {
+            stop_after_batches: Some(4),
+            ..CountingObserver::default()
+        };
+        let (_, history) = observed_training(&mut observer);
+        assert!(history.cancelled);
+        assert_eq!(observer.progress.len(), 4);
+        assert_eq!(observer.rounds, vec![1]);
+        assert_eq!(history.rounds.len(), 1);
+    }
+
+    #[test]
+    fn the_learning_rate_follows_the_schedule() {
+        let training_data = TrainingData(vec![
+            TrainingEntry {
+                input: vec![0.1, 0.2, 0.3],
+                expected_output: vec![0.01, 0.99],
+            };
+            2
+        ]);
+        let train = |learning_rate_schedule, data_loader: &DataLoader| {
+            let mut optimizer = Sgd::new(0.4);
+            let nn: NeuralNetwork =
+                NeuralNetwork::new_with_random_values(3, 4, 2, 1, &mut StdRng::seed_from_u64(0));
+            let result = nn.train(
+                data_loader,
+                &TrainingOptions {
+                    rounds: 3,
+                    learning_rate_schedule,
+                    ..TrainingOptions::default()
+                },
+                &mut optimizer,
+                &mut StdRng::seed_from_u64(0),
+            );
+            assert_eq!(optimizer.learning_rate, 0.4);
+            result.map(|(_, history)| {
+                history
+                    .rounds
+                    .iter()
+                    .map(|round| round.learning_rate)
+                    .collect::<Vec<f64>>()
+            })
+        };
+        let data_loader = DataLoader::new(&training_data);
+        assert_eq!(
+            train(LearningRateSchedule::Constant, &data_loader).unwrap(),
+            vec![0.4, 0.4, 0.4]
+        );
+        assert_eq!(
+            train(
+                LearningRateSchedule::Exponential { factor: 0.5 },
+                &data_loader
+            )
+            .unwrap(),
+            vec![0.4, 0.2, 0.1]
+        );
+        // 2 batches per round, the warmup ends in the second round
+        assert_eq!(
+            train(
+                LearningRateSchedule::LinearWarmup {
+                    warmup_steps: 4,
+                    after: Box::new(LearningRateSchedule::Constant),
+                },
+                &data_loader
+            )
+            .unwrap(),
+            vec![0.2, 0.4, 0.4]
+        );
+        let reduce_on_plateau = || LearningRateSchedule::ReduceOnPlateau {
+            factor: 0.5,
+            patience: 1,
+            minimum_learning_rate: 0.0,
+        };
+        assert!(train(reduce_on_plateau(), &data_loader).is_err());
+        assert!(train(
+            reduce_on_plateau(),
+            &DataLoader::new(&training_data).validation_data(&training_data)
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn testing_evaluate_dataset() {
+        let nn: NeuralNetwork =
+            NeuralNetwork::new_with_random_values(3, 4, 2, 1, &mut StdRng::seed_from_u64(0));
+        let entry = TrainingEntry {
+            input: vec![0.1, 0.2, 0.3],
+            expected_output: vec![0.01, 0.99],
+        };
+        let from_dataset = nn
+            .evaluate_dataset(
+                &TrainingData(vec![entry.clone()]),
+                &EvaluationOptions::default(),
+            )
+            .unwrap();
+        let from_entries = nn
+            .evaluate(
+                &ErrorRateData(&vec![ErrorRateEntry {
+                    input: entry.input,
+                    expected_output: entry.expected_output,
+                }]),
+                &EvaluationOptions::default(),
+            )
+            .unwrap();
+        assert_eq!(from_dataset, from_entries);
+    }
+
+    #[test]
+    fn train_and_serialize_f32_neural_network() {
+        let nn: NeuralNetwork<f32> = NeuralNetwork {
+            layers: vec![
+                Layer::from(
+                    Matrix::from_rows(vec![vec![0.9, 0.3, 0.4], vec![0.2, 0.8, 0.2]]).unwrap(),
+                ),
+                Layer::from(Matrix::from_rows(vec![vec![0.3, 0.7], vec![0.6, 0.5]]).unwrap()),
+            ],
+        };
+        let input = vec![0.1, 0.2, 0.3];
+        let trained = nn
+            .train(
+                &DataLoader::new(TrainingData(vec![TrainingEntry {
+                    input: input.clone(),
+                    expected_output: vec![0.01, 0.99],
+                }])),
+                &TrainingOptions::default(),
+                &mut Adam::new(0.01),
+                &mut StdRng::seed_from_u64(0),
+            )
+            .unwrap()
+            .0;
+        let json = serde_json::to_string(&trained).unwrap();
+        let deserialized: NeuralNetwork<f32> = serde_json::from_str(&json).unwrap();
+        assert_eq!(deserialized.layers, trained.layers);
+        // the f32 result is close to the result of the same neural network with f64 values
+        let result_f32 = trained
+            .query(&QueryData(&vec![QueryEntry { input }]))
+            .unwrap();
+        let result_f64 = trained
+            .to_precision::<f64>()
+            .query(&QueryData(&vec![QueryEntry {
+                input: vec![0.1, 0.2, 0.3],
+            }]))
+            .unwrap();
+        for (actual, expected) in result_f32.0[0].0.iter().zip(result_f64.0[0].0.iter()) {
+            assert!((*actual as f64 - expected).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn convert_neural_network_between_precisions() {
+        let nn: NeuralNetwork =
+            NeuralNetwork::new_with_random_values(3, 4, 2, 1, &mut StdRng::seed_from_u64(0));
+        let converted = nn.to_precision::<f32>();
+        assert_eq!(converted.layer_sizes(), nn.layer_sizes());
+        assert_eq!(
+            converted.layers[0].weights[(1, 2)],
+            nn.layers[0].weights[(1, 2)] as f32
+        );
+        // every f32 value fits in an f64, so converting back doesn't change anything
+        assert_eq!(
+            converted.to_precision::<f64>().to_precision::<f32>().layers,
+            converted.layers
         );
     }
 }
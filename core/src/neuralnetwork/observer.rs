@@ -0,0 +1,71 @@
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+use super::clipping::GradientNorms;
+use super::training::RoundMetrics;
+
+/// Whether training goes on after a batch or a round
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrainingControl {
+    Continue,
+    /// Stops training, the neural network is returned as it is (or with the weights and biases
+    /// of the best round when there is validation data)
+    Stop,
+}
+
+/// How far training is, the observer gets it after every batch and every round
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrainingProgress {
+    /// The first round is 1
+    pub round: u32,
+    pub amount_of_rounds: u32,
+    /// The batches of the current round that are done, the first batch is 1
+    pub batch: usize,
+    pub amount_of_batches: usize,
+    /// The batches of all rounds that are done
+    pub step: u64,
+    /// The mean loss of the last batch
+    pub loss: f64,
+    /// The mean loss of the batches of the current round that are done
+    pub round_loss: f64,
+    /// The time since training started
+    pub elapsed: Duration,
+    /// The norms of the gradients of the last batch, before they are clipped
+    pub gradient_norms: GradientNorms,
+}
+
+/// Follows the progress of training, for example to show a progress bar. Training stops when
+/// the observer returns TrainingControl::Stop.
+pub trait TrainingObserver {
+    /// Is called after the weights and biases are updated with a batch
+    fn after_batch(&mut self, _progress: &TrainingProgress) -> TrainingControl {
+        TrainingControl::Continue
+    }
+
+    /// Is called after every round, with the metrics of the round
+    fn after_round(
+        &mut self,
+        _progress: &TrainingProgress,
+        _metrics: &RoundMetrics,
+    ) -> TrainingControl {
+        TrainingControl::Continue
+    }
+
+    /// The current time, measured from any moment, to calculate the elapsed time. The default
+    /// uses std::time::Instant, which panics in the browser (wasm32-unknown-unknown), so an
+    /// observer in the browser should return the time of the browser (performance.now()).
+    fn now(&self) -> Duration {
+        static START: OnceLock<Instant> = OnceLock::new();
+        START.get_or_init(Instant::now).elapsed()
+    }
+}
+
+/// The observer of NeuralNetwork::train, it doesn't measure the time so it works everywhere
+#[derive(Debug)]
+pub(crate) struct NoObserver;
+
+impl TrainingObserver for NoObserver {
+    fn now(&self) -> Duration {
+        Duration::ZERO
+    }
+}
@@ -0,0 +1,631 @@
+use anyhow::{ensure, Result};
+use serde::{Deserialize, Serialize};
+
+use super::layer::Layer;
+use crate::matrix::{float::Float, matrix::Matrix};
+
+/// The gradients of the weights and biases of one layer, averaged over a batch. A gradient
+/// points in the direction where the error of the neural network increases, so the optimizer
+/// moves the weights and biases in the opposite direction.
+#[derive(Debug, PartialEq, Clone)]
+pub struct LayerGradients<T: Float = f64> {
+    pub weights: Matrix<T>,
+    pub biases: Matrix<T>,
+}
+
+/// An optimizer decides how the weights and biases are updated with the gradients of a batch.
+/// Optimizers can keep state between updates (for example the momentum of every weight),
+/// every optimizer can be serialized including this state, so training can be resumed exactly
+/// where it stopped.
+///
+/// The settings of an optimizer are an f64, the state has the same float type as the layers.
+pub trait Optimizer<T: Float = f64> {
+    /// Updates the weights and biases of the layers with the gradients of one batch
+    fn update(&mut self, layers: &mut [Layer<T>], gradients: &[LayerGradients<T>]) -> Result<()>;
+
+    fn learning_rate(&self) -> f64;
+
+    fn set_learning_rate(&mut self, learning_rate: f64);
+}
+
+/// Stochastic gradient descent, this was the only optimizer before:
+/// weight = weight - α * gradient
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Sgd {
+    pub learning_rate: f64,
+}
+
+impl Sgd {
+    pub fn new(learning_rate: f64) -> Sgd {
+        Sgd { learning_rate }
+    }
+}
+
+impl<T: Float> Optimizer<T> for Sgd {
+    fn update(&mut self, layers: &mut [Layer<T>], gradients: &[LayerGradients<T>]) -> Result<()> {
+        let learning_rate = T::from_f64(self.learning_rate);
+        for parameter in parameters(layers, gradients)? {
+            for (value, gradient) in parameter
+                .values
+                .values_mut()
+                .zip(parameter.gradient.values())
+            {
+                *value -= learning_rate * *gradient;
+            }
+        }
+        Ok(())
+    }
+
+    fn learning_rate(&self) -> f64 {
+        self.learning_rate
+    }
+
+    fn set_learning_rate(&mut self, learning_rate: f64) {
+        self.learning_rate = learning_rate;
+    }
+}
+
+/// Gradient descent with momentum, the velocity of every weight keeps a part of the previous
+/// updates, so the weights keep moving in a direction that is consistent over multiple batches:
+/// velocity = μ * velocity - α * gradient
+/// weight = weight + velocity
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(bound = "T: Float")]
+pub struct Momentum<T: Float = f64> {
+    pub learning_rate: f64,
+    pub momentum: f64,
+    velocity: Vec<Matrix<T>>,
+}
+
+impl<T: Float> Momentum<T> {
+    pub fn new(learning_rate: f64, momentum: f64) -> Momentum<T> {
+        Momentum {
+            learning_rate,
+            momentum,
+            velocity: vec![],
+        }
+    }
+}
+
+impl<T: Float> Optimizer<T> for Momentum<T> {
+    fn update(&mut self, layers: &mut [Layer<T>], gradients: &[LayerGradients<T>]) -> Result<()> {
+        let parameters = parameters(layers, gradients)?;
+        initialize_state(&mut self.velocity, &parameters)?;
+        let learning_rate = T::from_f64(self.learning_rate);
+        let momentum = T::from_f64(self.momentum);
+        for (parameter, velocity) in parameters.into_iter().zip(self.velocity.iter_mut()) {
+            for ((value, gradient), velocity) in parameter
+                .values
+                .values_mut()
+                .zip(parameter.gradient.values())
+                .zip(velocity.values_mut())
+            {
+                *velocity = momentum * *velocity - learning_rate * *gradient;
+                *value += *velocity;
+            }
+        }
+        Ok(())
+    }
+
+    fn learning_rate(&self) -> f64 {
+        self.learning_rate
+    }
+
+    fn set_learning_rate(&mut self, learning_rate: f64) {
+        self.learning_rate = learning_rate;
+    }
+}
+
+/// Nesterov accelerated gradient, the same as momentum but the update looks ahead to where
+/// the velocity is taking the weight:
+/// velocity = μ * velocity - α * gradient
+/// weight = weight - μ * previous velocity + (1 + μ) * velocity
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(bound = "T: Float")]
+pub struct Nesterov<T: Float = f64> {
+    pub learning_rate: f64,
+    pub momentum: f64,
+    velocity: Vec<Matrix<T>>,
+}
+
+impl<T: Float> Nesterov<T> {
+    pub fn new(learning_rate: f64, momentum: f64) -> Nesterov<T> {
+        Nesterov {
+            learning_rate,
+            momentum,
+            velocity: vec![],
+        }
+    }
+}
+
+impl<T: Float> Optimizer<T> for Nesterov<T> {
+    fn update(&mut self, layers: &mut [Layer<T>], gradients: &[LayerGradients<T>]) -> Result<()> {
+        let parameters = parameters(layers, gradients)?;
+        initialize_state(&mut self.velocity, &parameters)?;
+        let learning_rate = T::from_f64(self.learning_rate);
+        let momentum = T::from_f64(self.momentum);
+        for (parameter, velocity) in parameters.into_iter().zip(self.velocity.iter_mut()) {
+            for ((value, gradient), velocity) in parameter
+                .values
+                .values_mut()
+                .zip(parameter.gradient.values())
+                .zip(velocity.values_mut())
+            {
+                let previous_velocity = *velocity;
+                *velocity = momentum * *velocity - learning_rate * *gradient;
+                *value += -momentum * previous_velocity + (T::ONE + momentum) * *velocity;
+            }
+        }
+        Ok(())
+    }
+
+    fn learning_rate(&self) -> f64 {
+        self.learning_rate
+    }
+
+    fn set_learning_rate(&mut self, learning_rate: f64) {
+        self.learning_rate = learning_rate;
+    }
+}
+
+/// RMSProp, every weight gets its own learning rate based on a moving average of its
+/// squared gradients:
+/// average = ρ * average + (1 - ρ) * gradient²
+/// weight = weight - α * gradient / (√average + ε)
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(bound = "T: Float")]
+pub struct RmsProp<T: Float = f64> {
+    pub learning_rate: f64,
+    pub decay_rate: f64,
+    pub epsilon: f64,
+    squared_gradients: Vec<Matrix<T>>,
+}
+
+impl<T: Float> RmsProp<T> {
+    pub fn new(learning_rate: f64) -> RmsProp<T> {
+        RmsProp {
+            learning_rate,
+            decay_rate: 0.9,
+            epsilon: 1e-8,
+            squared_gradients: vec![],
+        }
+    }
+}
+
+impl<T: Float> Optimizer<T> for RmsProp<T> {
+    fn update(&mut self, layers: &mut [Layer<T>], gradients: &[LayerGradients<T>]) -> Result<()> {
+        let parameters = parameters(layers, gradients)?;
+        initialize_state(&mut self.squared_gradients, &parameters)?;
+        let learning_rate = T::from_f64(self.learning_rate);
+        let decay_rate = T::from_f64(self.decay_rate);
+        let epsilon = T::from_f64(self.epsilon);
+        for (parameter, squared_gradients) in parameters
+            .into_iter()
+            .zip(self.squared_gradients.iter_mut())
+        {
+            for ((value, gradient), squared_gradient) in parameter
+                .values
+                .values_mut()
+                .zip(parameter.gradient.values())
+                .zip(squared_gradients.values_mut())
+            {
+                let gradient = *gradient;
+                *squared_gradient =
+                    decay_rate * *squared_gradient + (T::ONE - decay_rate) * gradient * gradient;
+                *value -= learning_rate * gradient / (squared_gradient.sqrt() + epsilon);
+            }
+        }
+        Ok(())
+    }
+
+    fn learning_rate(&self) -> f64 {
+        self.learning_rate
+    }
+
+    fn set_learning_rate(&mut self, learning_rate: f64) {
+        self.learning_rate = learning_rate;
+    }
+}
+
+/// Adagrad, every weight gets its own learning rate based on the sum of all its squared
+/// gradients, weights that are updated a lot get smaller updates:
+/// sum = sum + gradient²
+/// weight = weight - α * gradient / (√sum + ε)
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(bound = "T: Float")]
+pub struct Adagrad<T: Float = f64> {
+    pub learning_rate: f64,
+    pub epsilon: f64,
+    squared_gradients: Vec<Matrix<T>>,
+}
+
+impl<T: Float> Adagrad<T> {
+    pub fn new(learning_rate: f64) -> Adagrad<T> {
+        Adagrad {
+            learning_rate,
+            epsilon: 1e-8,
+            squared_gradients: vec![],
+        }
+    }
+}
+
+impl<T: Float> Optimizer<T> for Adagrad<T> {
+    fn update(&mut self, layers: &mut [Layer<T>], gradients: &[LayerGradients<T>]) -> Result<()> {
+        let parameters = parameters(layers, gradients)?;
+        initialize_state(&mut self.squared_gradients, &parameters)?;
+        let learning_rate = T::from_f64(self.learning_rate);
+        let epsilon = T::from_f64(self.epsilon);
+        for (parameter, squared_gradients) in parameters
+            .into_iter()
+            .zip(self.squared_gradients.iter_mut())
+        {
+            for ((value, gradient), squared_gradient) in parameter
+                .values
+                .values_mut()
+                .zip(parameter.gradient.values())
+                .zip(squared_gradients.values_mut())
+            {
+                let gradient = *gradient;
+                *squared_gradient += gradient * gradient;
+                *value -= learning_rate * gradient / (squared_gradient.sqrt() + epsilon);
+            }
+        }
+        Ok(())
+    }
+
+    fn learning_rate(&self) -> f64 {
+        self.learning_rate
+    }
+
+    fn set_learning_rate(&mut self, learning_rate: f64) {
+        self.learning_rate = learning_rate;
+    }
+}
+
+/// Adam, combines momentum with the per weight learning rate of RMSProp. Both moving
+/// averages start at zero, so they are corrected for this bias in the first steps:
+/// m = β1 * m + (1 - β1) * gradient
+/// v = β2 * v + (1 - β2) * gradient²
+/// weight = weight - α * (m / (1 - β1^t)) / (√(v / (1 - β2^t)) + ε)
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(bound = "T: Float")]
+pub struct Adam<T: Float = f64> {
+    pub learning_rate: f64,
+    pub beta1: f64,
+    pub beta2: f64,
+    pub epsilon: f64,
+    step: u32,
+    first_moments: Vec<Matrix<T>>,
+    second_moments: Vec<Matrix<T>>,
+}
+
+impl<T: Float> Adam<T> {
+    pub fn new(learning_rate: f64) -> Adam<T> {
+        Adam {
+            learning_rate,
+            beta1: 0.9,
+            beta2: 0.999,
+            epsilon: 1e-8,
+            step: 0,
+            first_moments: vec![],
+            second_moments: vec![],
+        }
+    }
+
+    /// Updates the weights with Adam, weight decay is applied on the weights (not on the
+    /// biases) separately from the gradients, this is used by AdamW
+    fn update_with_weight_decay(
+        &mut self,
+        layers: &mut [Layer<T>],
+        gradients: &[LayerGradients<T>],
+        weight_decay: f64,
+    ) -> Result<()> {
+        let parameters = parameters(layers, gradients)?;
+        initialize_state(&mut self.first_moments, &parameters)?;
+        initialize_state(&mut self.second_moments, &parameters)?;
+        self.step += 1;
+        let first_moment_correction = T::from_f64(1.0 - self.beta1.powi(self.step as i32));
+        let second_moment_correction = T::from_f64(1.0 - self.beta2.powi(self.step as i32));
+        let learning_rate = T::from_f64(self.learning_rate);
+        let beta1 = T::from_f64(self.beta1);
+        let beta2 = T::from_f64(self.beta2);
+        let epsilon = T::from_f64(self.epsilon);
+        for ((parameter, first_moments), second_moments) in parameters
+            .into_iter()
+            .zip(self.first_moments.iter_mut())
+            .zip(self.second_moments.iter_mut())
+        {
+            let weight_decay = T::from_f64(if parameter.is_bias { 0.0 } else { weight_decay });
+            for (((value, gradient), first_moment), second_moment) in parameter
+                .values
+                .values_mut()
+                .zip(parameter.gradient.values())
+                .zip(first_moments.values_mut())
+                .zip(second_moments.values_mut())
+            {
+                let gradient = *gradient;
+                *first_moment = beta1 * *first_moment + (T::ONE - beta1) * gradient;
+                *second_moment = beta2 * *second_moment + (T::ONE - beta2) * gradient * gradient;
+                *value -= learning_rate * weight_decay * *value;
+                *value -= learning_rate * (*first_moment / first_moment_correction)
+                    / ((*second_moment / second_moment_correction).sqrt() + epsilon);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<T: Float> Optimizer<T> for Adam<T> {
+    fn update(&mut self, layers: &mut [Layer<T>], gradients: &[LayerGradients<T>]) -> Result<()> {
+        self.update_with_weight_decay(layers, gradients, 0.0)
+    }
+
+    fn learning_rate(&self) -> f64 {
+        self.learning_rate
+    }
+
+    fn set_learning_rate(&mut self, learning_rate: f64) {
+        self.learning_rate = learning_rate;
+    }
+}
+
+/// AdamW, Adam with weight decay that is decoupled from the gradients. Every step the
+/// weights shrink a little bit towards zero, the biases are not decayed:
+/// weight = weight - α * λ * weight
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(bound = "T: Float")]
+pub struct AdamW<T: Float = f64> {
+    pub weight_decay: f64,
+    adam: Adam<T>,
+}
+
+impl<T: Float> AdamW<T> {
+    pub fn new(learning_rate: f64, weight_decay: f64) -> AdamW<T> {
+        AdamW {
+            weight_decay,
+            adam: Adam::new(learning_rate),
+        }
+    }
+}
+
+impl<T: Float> Optimizer<T> for AdamW<T> {
+    fn update(&mut self, layers: &mut [Layer<T>], gradients: &[LayerGradients<T>]) -> Result<()> {
+        self.adam
+            .update_with_weight_decay(layers, gradients, self.weight_decay)
+    }
+
+    fn learning_rate(&self) -> f64 {
+        self.adam.learning_rate
+    }
+
+    fn set_learning_rate(&mut self, learning_rate: f64) {
+        self.adam.learning_rate = learning_rate;
+    }
+}
+
+/// A weights or biases matrix of a layer together with its gradient
+struct Parameter<'a, T: Float> {
+    values: &'a mut Matrix<T>,
+    gradient: &'a Matrix<T>,
+    is_bias: bool,
+}
+
+/// Returns the weights and biases of every layer with their gradients, in the order:
+/// weights of layer 1, biases of layer 1, weights of layer 2, etc.
+fn parameters<'a, T: Float>(
+    layers: &'a mut [Layer<T>],
+    gradients: &'a [LayerGradients<T>],
+) -> Result<Vec<Parameter<'a, T>>> {
+    ensure!(
+        layers.len() == gradients.len(),
+        "Optimizer: there should be a gradient for every layer"
+    );
+    let mut parameters = Vec::with_capacity(layers.len() * 2);
+    for (layer, gradient) in layers.iter_mut().zip(gradients.iter()) {
+        ensure!(
+            layer.weights.same_size(&gradient.weights) && layer.biases.same_size(&gradient.biases),
+            "Optimizer: the gradients should be of same size as the weights and biases"
+        );
+        parameters.push(Parameter {
+            values: &mut layer.weights,
+            gradient: &gradient.weights,
+            is_bias: false,
+        });
+        parameters.push(Parameter {
+            values: &mut layer.biases,
+            gradient: &gradient.biases,
+            is_bias: true,
+        });
+    }
+    Ok(parameters)
+}
+
+/// The state of an optimizer has a matrix per parameter, it starts with zeros the first
+/// time the optimizer is used
+fn initialize_state<T: Float>(
+    state: &mut Vec<Matrix<T>>,
+    parameters: &[Parameter<T>],
+) -> Result<()> {
+    if state.is_empty() {
+        *state = parameters
+            .iter()
+            .map(|parameter| parameter.values.map(|_| T::ZERO))
+            .collect();
+    }
+    ensure!(
+        state.len() == parameters.len()
+            && state
+                .iter()
+                .zip(parameters.iter())
+                .all(|(state, parameter)| state.same_size(parameter.values)),
+        "Optimizer: the state of the optimizer doesn't match the layers of the neural network"
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layers() -> Vec<Layer> {
+        vec![Layer {
+            weights: Matrix::from_rows(vec![vec![1.0, -1.0]]).unwrap(),
+            biases: Matrix::from_rows(vec![vec![0.5]]).unwrap(),
+            activation: Default::default(),
+            dropout: 0.0,
+        }]
+    }
+
+    fn gradients() -> Vec<LayerGradients> {
+        vec![LayerGradients {
+            weights: Matrix::from_rows(vec![vec![0.5, -0.25]]).unwrap(),
+            biases: Matrix::from_rows(vec![vec![1.0]]).unwrap(),
+        }]
+    }
+
+    #[test]
+    fn testing_sgd() {
+        let mut layers = layers();
+        Sgd::new(0.1).update(&mut layers, &gradients()).unwrap();
+        assert_eq!(
+            layers[0].weights,
+            Matrix::from_rows(vec![vec![0.95, -0.975]]).unwrap()
+        );
+        assert_eq!(
+            layers[0].biases,
+            Matrix::from_rows(vec![vec![0.4]]).unwrap()
+        );
+    }
+
+    #[test]
+    fn testing_momentum() {
+        let mut layers = layers();
+        let mut optimizer = Momentum::new(0.1, 0.9);
+        optimizer.update(&mut layers, &gradients()).unwrap();
+        optimizer.update(&mut layers, &gradients()).unwrap();
+        // first step: velocity = -0.1 * g, second step: velocity = 0.9 * -0.1 * g - 0.1 * g
+        assert_eq!(
+            layers[0].weights,
+            Matrix::from_rows(vec![vec![0.855, -0.9275]]).unwrap()
+        );
+        assert_eq!(
+            layers[0].biases,
+            Matrix::from_rows(vec![vec![0.21000000000000002]]).unwrap()
+        );
+    }
+
+    #[test]
+    fn testing_nesterov() {
+        let mut layers = layers();
+        let mut optimizer = Nesterov::new(0.1, 0.9);
+        optimizer.update(&mut layers, &gradients()).unwrap();
+        // the first step looks ahead: -α * g - μ * α * g
+        assert_eq!(
+            layers[0].weights,
+            Matrix::from_rows(vec![vec![0.905, -0.9525]]).unwrap()
+        );
+        assert_eq!(
+            layers[0].biases,
+            Matrix::from_rows(vec![vec![0.31]]).unwrap()
+        );
+    }
+
+    #[test]
+    fn testing_rmsprop() {
+        let mut layers = layers();
+        RmsProp::new(0.1).update(&mut layers, &gradients()).unwrap();
+        // √(0.1 * g²) = 0.316.. * |g|, so every value moves 0.1 / 0.316.. in the opposite direction of g
+        assert_eq!(
+            layers[0].weights,
+            Matrix::from_rows(vec![vec![0.6837722539831608, -0.6837722739831569]]).unwrap()
+        );
+        assert_eq!(
+            layers[0].biases,
+            Matrix::from_rows(vec![vec![0.18377224398316166]]).unwrap()
+        );
+    }
+
+    #[test]
+    fn testing_adagrad() {
+        let mut layers = layers();
+        Adagrad::new(0.1).update(&mut layers, &gradients()).unwrap();
+        assert_eq!(
+            layers[0].weights,
+            Matrix::from_rows(vec![vec![0.900000002, -0.9000000039999998]]).unwrap()
+        );
+        assert_eq!(
+            layers[0].biases,
+            Matrix::from_rows(vec![vec![0.400000001]]).unwrap()
+        );
+    }
+
+    #[test]
+    fn testing_adam() {
+        let mut layers = layers();
+        Adam::new(0.1).update(&mut layers, &gradients()).unwrap();
+        // the first step of adam moves every value α in the opposite direction of the gradient
+        assert_eq!(
+            layers[0].weights,
+            Matrix::from_rows(vec![vec![0.900000002, -0.9000000039999998]]).unwrap()
+        );
+        assert_eq!(
+            layers[0].biases,
+            Matrix::from_rows(vec![vec![0.400000001]]).unwrap()
+        );
+    }
+
+    #[test]
+    fn testing_adamw_does_not_decay_biases() {
+        let mut layers = layers();
+        AdamW::new(0.1, 0.5)
+            .update(&mut layers, &gradients())
+            .unwrap();
+        assert_eq!(
+            layers[0].weights,
+            Matrix::from_rows(vec![vec![0.8500000019999999, -0.8500000039999998]]).unwrap()
+        );
+        assert_eq!(
+            layers[0].biases,
+            Matrix::from_rows(vec![vec![0.400000001]]).unwrap()
+        );
+    }
+
+    #[test]
+    fn testing_optimizer_with_wrong_gradients() {
+        let mut layers = layers();
+        let result = Adam::new(0.1).update(&mut layers, &[]);
+        assert!(result.is_err());
+        let mut optimizer = Momentum::new(0.1, 0.9);
+        optimizer.update(&mut layers, &gradients()).unwrap();
+        let mut other_layers = vec![Layer::from(
+            Matrix::from_rows(vec![vec![1.0, 2.0, 3.0]]).unwrap(),
+        )];
+        let result = optimizer.update(
+            &mut other_layers,
+            &[LayerGradients {
+                weights: Matrix::from_rows(vec![vec![1.0, 2.0, 3.0]]).unwrap(),
+                biases: Matrix::from_rows(vec![vec![1.0]]).unwrap(),
+            }],
+        );
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Optimizer: the state of the optimizer doesn't match the layers of the neural network"
+        );
+    }
+
+    #[test]
+    fn serialize_optimizer_with_state() {
+        let mut layers = layers();
+        let mut optimizer = Adam::new(0.1);
+        optimizer.update(&mut layers, &gradients()).unwrap();
+        let json = serde_json::to_string(&optimizer).unwrap();
+        let mut deserialized: Adam = serde_json::from_str(&json).unwrap();
+        assert_eq!(deserialized, optimizer);
+        let mut other_layers = layers.clone();
+        optimizer.update(&mut layers, &gradients()).unwrap();
+        deserialized
+            .update(&mut other_layers, &gradients())
+            .unwrap();
+        assert_eq!(layers, other_layers);
+    }
+}
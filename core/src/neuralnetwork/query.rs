@@ -1,13 +1,15 @@
+use crate::matrix::float::Float;
+
 #[derive(Debug)]
-pub struct QueryEntry {
-    pub input: Vec<f64>,
+pub struct QueryEntry<T: Float = f64> {
+    pub input: Vec<T>,
 }
 
 #[derive(Debug)]
-pub struct QueryData<'a>(pub &'a Vec<QueryEntry>);
+pub struct QueryData<'a, T: Float = f64>(pub &'a Vec<QueryEntry<T>>);
 
 #[derive(Debug)]
-pub struct QueryResult(pub Vec<f64>);
+pub struct QueryResult<T: Float = f64>(pub Vec<T>);
 
 #[derive(Debug)]
-pub struct QueryResults(pub Vec<QueryResult>);
\ No newline at end of file
+pub struct QueryResults<T: Float = f64>(pub Vec<QueryResult<T>>);
@@ -0,0 +1,185 @@
+use anyhow::{ensure, Result};
+use serde::{Deserialize, Serialize};
+
+use super::{layer::Layer, optimizer::LayerGradients};
+use crate::matrix::float::Float;
+
+/// Regularization keeps the weights small, so a neural network with many hidden neurons doesn't
+/// learn the training data by heart. It is only applied on the weights, the biases are never
+/// regularized. By default there is no regularization.
+///
+/// L1 and L2 add a penalty to the loss, their gradients are added to the gradients of the
+/// weights before the optimizer updates them:
+/// penalty = l1 * Σ|weight| + l2 / 2 * Σweight²
+/// gradient = gradient + l1 * sign(weight) + l2 * weight
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Regularization {
+    /// Pushes weights to exactly zero, so only the important inputs of a neuron are used
+    pub l1: f64,
+    /// Weight decay, big weights shrink more than small weights
+    pub l2: f64,
+    /// After every update the incoming weights of a neuron (a row of the weights matrix) are
+    /// scaled down when their length (L2 norm) is larger than the max norm
+    pub max_norm: Option<f64>,
+}
+
+impl Regularization {
+    pub(crate) fn validate(&self) -> Result<()> {
+        ensure!(
+            self.l1 >= 0.0 && self.l2 >= 0.0,
+            "Regularization: l1 and l2 should not be negative"
+        );
+        ensure!(
+            self.max_norm.is_none_or(|max_norm| max_norm > 0.0),
+            "Regularization: max_norm should be greater than 0"
+        );
+        Ok(())
+    }
+
+    /// The penalty of L1 and L2 for the weights of the layers, 0 without L1 and L2
+    pub fn penalty<T: Float>(&self, layers: &[Layer<T>]) -> f64 {
+        if self.l1 == 0.0 && self.l2 == 0.0 {
+            return 0.0;
+        }
+        layers
+            .iter()
+            .flat_map(|layer| layer.weights.values())
+            .map(|weight| {
+                let weight = weight.to_f64();
+                self.l1 * weight.abs() + self.l2 / 2.0 * weight * weight
+            })
+            .sum()
+    }
+
+    /// Adds the gradients of the L1 and L2 penalty to the gradients of the weights
+    pub(crate) fn add_to_gradients<T: Float>(
+        &self,
+        layers: &[Layer<T>],
+        gradients: &mut [LayerGradients<T>],
+    ) {
+        if self.l1 == 0.0 && self.l2 == 0.0 {
+            return;
+        }
+        let l1 = T::from_f64(self.l1);
+        let l2 = T::from_f64(self.l2);
+        for (layer, gradients) in layers.iter().zip(gradients.iter_mut()) {
+            for (gradient, weight) in gradients.weights.values_mut().zip(layer.weights.values()) {
+                let sign = if *weight > T::ZERO {
+                    T::ONE
+                } else if *weight < T::ZERO {
+                    -T::ONE
+                } else {
+                    T::ZERO
+                };
+                *gradient += l1 * sign + l2 * *weight;
+            }
+        }
+    }
+
+    /// Scales down the incoming weights of every neuron that are longer than the max norm
+    pub(crate) fn apply_max_norm<T: Float>(&self, layers: &mut [Layer<T>]) {
+        let Some(max_norm) = self.max_norm else {
+            return;
+        };
+        let max_norm = T::from_f64(max_norm);
+        for layer in layers.iter_mut() {
+            for row in 0..layer.weights.rows() {
+                let weights = layer.weights.row_mut(row);
+                let norm = weights
+                    .iter()
+                    .map(|weight| *weight * *weight)
+                    .sum::<T>()
+                    .sqrt();
+                if norm > max_norm {
+                    let factor = max_norm / norm;
+                    weights.iter_mut().for_each(|weight| *weight *= factor);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matrix::matrix::Matrix;
+
+    fn layers() -> Vec<Layer> {
+        vec![Layer {
+            weights: Matrix::from_rows(vec![vec![3.0, -4.0], vec![0.0, 0.5]]).unwrap(),
+            biases: Matrix::from_rows(vec![vec![10.0], vec![-10.0]]).unwrap(),
+            activation: Default::default(),
+            dropout: 0.0,
+        }]
+    }
+
+    #[test]
+    fn the_penalty_ignores_the_biases() {
+        let regularization = Regularization {
+            l1: 0.1,
+            l2: 0.2,
+            ..Regularization::default()
+        };
+        // l1: 0.1 * 7.5 = 0.75, l2: 0.1 * (9 + 16 + 0.25) = 2.525
+        assert_eq!(regularization.penalty(&layers()), 3.2750000000000004);
+        assert_eq!(Regularization::default().penalty(&layers()), 0.0);
+    }
+
+    #[test]
+    fn testing_add_to_gradients() {
+        let mut gradients = vec![LayerGradients {
+            weights: Matrix::from_rows(vec![vec![0.0, 0.0], vec![0.0, 0.0]]).unwrap(),
+            biases: Matrix::from_rows(vec![vec![0.0], vec![0.0]]).unwrap(),
+        }];
+        Regularization {
+            l1: 0.5,
+            l2: 0.25,
+            ..Regularization::default()
+        }
+        .add_to_gradients(&layers(), &mut gradients);
+        assert_eq!(
+            gradients[0].weights,
+            Matrix::from_rows(vec![vec![1.25, -1.5], vec![0.0, 0.625]]).unwrap()
+        );
+        assert_eq!(
+            gradients[0].biases,
+            Matrix::from_rows(vec![vec![0.0], vec![0.0]]).unwrap()
+        );
+    }
+
+    #[test]
+    fn max_norm_scales_down_long_rows() {
+        let mut layers = layers();
+        Regularization {
+            max_norm: Some(2.5),
+            ..Regularization::default()
+        }
+        .apply_max_norm(&mut layers);
+        // the first row has a norm of 5, the second row is shorter than the max norm
+        assert_eq!(
+            layers[0].weights,
+            Matrix::from_rows(vec![vec![1.5, -2.0], vec![0.0, 0.5]]).unwrap()
+        );
+        assert_eq!(
+            layers[0].biases,
+            Matrix::from_rows(vec![vec![10.0], vec![-10.0]]).unwrap()
+        );
+    }
+
+    #[test]
+    fn testing_validate() {
+        assert!(Regularization::default().validate().is_ok());
+        assert!(Regularization {
+            l2: -0.1,
+            ..Regularization::default()
+        }
+        .validate()
+        .is_err());
+        assert!(Regularization {
+            max_norm: Some(0.0),
+            ..Regularization::default()
+        }
+        .validate()
+        .is_err());
+    }
+}
@@ -0,0 +1,323 @@
+use anyhow::{ensure, Result};
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+
+/// How the learning rate changes during training. The learning rate of the optimizer is the
+/// initial learning rate, the schedule changes it after every round (and LinearWarmup after
+/// every batch). After training the optimizer gets its initial learning rate back.
+#[derive(Debug, PartialEq, Clone, Default, Serialize, Deserialize)]
+pub enum LearningRateSchedule {
+    /// The learning rate doesn't change
+    #[default]
+    Constant,
+    /// The learning rate is multiplied with the factor every step_size rounds
+    Step { step_size: u32, factor: f64 },
+    /// The learning rate is multiplied with the factor every round
+    Exponential { factor: f64 },
+    /// Cosine annealing with warm restarts (SGDR), the learning rate follows half a cosine from
+    /// the initial learning rate to the minimum learning rate in a period of rounds, then it
+    /// restarts at the initial learning rate. Every period is period_multiplier times longer
+    /// than the period before.
+    CosineWarmRestarts {
+        period: u32,
+        period_multiplier: u32,
+        minimum_learning_rate: f64,
+    },
+    /// The learning rate increases linearly during the first warmup_steps batches, from a
+    /// small value to the learning rate of the schedule after the warmup
+    LinearWarmup {
+        warmup_steps: u32,
+        after: Box<LearningRateSchedule>,
+    },
+    /// The learning rate is multiplied with the factor when the validation loss didn't improve
+    /// for the amount of rounds of the patience, but it doesn't go below the minimum learning
+    /// rate. This needs validation data, see DataLoader::validation_split.
+    ReduceOnPlateau {
+        factor: f64,
+        patience: u32,
+        minimum_learning_rate: f64,
+    },
+}
+
+impl LearningRateSchedule {
+    fn validate(&self) -> Result<()> {
+        match self {
+            LearningRateSchedule::Constant => {}
+            LearningRateSchedule::Step { step_size, factor } => {
+                ensure!(
+                    *step_size > 0,
+                    "LearningRateSchedule: step_size should be greater than 0"
+                );
+                ensure!(
+                    *factor > 0.0,
+                    "LearningRateSchedule: factor should be greater than 0"
+                );
+            }
+            LearningRateSchedule::Exponential { factor } => ensure!(
+                *factor > 0.0,
+                "LearningRateSchedule: factor should be greater than 0"
+            ),
+            LearningRateSchedule::CosineWarmRestarts {
+                period,
+                period_multiplier,
+                ..
+            } => ensure!(
+                *period > 0 && *period_multiplier > 0,
+                "LearningRateSchedule: period and period_multiplier should be greater than 0"
+            ),
+            LearningRateSchedule::LinearWarmup { after, .. } => after.validate()?,
+            LearningRateSchedule::ReduceOnPlateau {
+                factor, patience, ..
+            } => {
+                ensure!(
+                    *factor > 0.0 && *factor < 1.0,
+                    "LearningRateSchedule: factor should be between 0 and 1"
+                );
+                ensure!(
+                    *patience > 0,
+                    "LearningRateSchedule: patience should be greater than 0"
+                );
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn needs_validation(&self) -> bool {
+        match self {
+            LearningRateSchedule::ReduceOnPlateau { .. } => true,
+            LearningRateSchedule::LinearWarmup { after, .. } => after.needs_validation(),
+            _ => false,
+        }
+    }
+}
+
+/// Keeps track of the schedule during training
+#[derive(Debug)]
+pub(crate) struct Scheduler {
+    schedule: LearningRateSchedule,
+    initial_learning_rate: f64,
+    /// The learning rate of ReduceOnPlateau
+    plateau_learning_rate: f64,
+    best_validation_loss: f64,
+    rounds_without_improvement: u32,
+}
+
+impl Scheduler {
+    pub(crate) fn new(
+        schedule: &LearningRateSchedule,
+        initial_learning_rate: f64,
+    ) -> Result<Scheduler> {
+        schedule.validate()?;
+        Ok(Scheduler {
+            schedule: schedule.clone(),
+            initial_learning_rate,
+            plateau_learning_rate: initial_learning_rate,
+            best_validation_loss: f64::INFINITY,
+            rounds_without_improvement: 0,
+        })
+    }
+
+    /// The learning rate of a batch, the first round is 0 and step counts the batches of all
+    /// rounds (the first batch is 0)
+    pub(crate) fn learning_rate(&self, round: u32, step: u64) -> f64 {
+        self.learning_rate_of(&self.schedule, round, step)
+    }
+
+    fn learning_rate_of(&self, schedule: &LearningRateSchedule, round: u32, step: u64) -> f64 {
+        let initial = self.initial_learning_rate;
+        match schedule {
+            LearningRateSchedule::Constant => initial,
+            LearningRateSchedule::Step { step_size, factor } => {
+                initial * factor.powi((round / step_size) as i32)
+            }
+            LearningRateSchedule::Exponential { factor } => initial * factor.powi(round as i32),
+            LearningRateSchedule::CosineWarmRestarts {
+                period,
+                period_multiplier,
+                minimum_learning_rate,
+            } => {
+                let (mut round_in_period, mut period) = (round as u64, *period as u64);
+                while round_in_period >= period {
+                    round_in_period -= period;
+                    period *= *period_multiplier as u64;
+                }
+                minimum_learning_rate
+                    + (initial - minimum_learning_rate)
+                        * (1.0 + (PI * round_in_period as f64 / period as f64).cos())
+                        / 2.0
+            }
+            LearningRateSchedule::LinearWarmup {
+                warmup_steps,
+                after,
+            } => {
+                let learning_rate = self.learning_rate_of(after, round, step);
+                if step < *warmup_steps as u64 {
+                    learning_rate * (step + 1) as f64 / *warmup_steps as f64
+                } else {
+                    learning_rate
+                }
+            }
+            LearningRateSchedule::ReduceOnPlateau { .. } => self.plateau_learning_rate,
+        }
+    }
+
+    /// Is called after every round, with the validation loss of the round
+    pub(crate) fn end_of_round(&mut self, validation_loss: Option<f64>) {
+        let (factor, patience, minimum_learning_rate) = match plateau(&self.schedule) {
+            Some(plateau) => plateau,
+            None => return,
+        };
+        let validation_loss = match validation_loss {
+            Some(validation_loss) => validation_loss,
+            None => return,
+        };
+        if validation_loss < self.best_validation_loss {
+            self.best_validation_loss = validation_loss;
+            self.rounds_without_improvement = 0;
+        } else {
+            self.rounds_without_improvement += 1;
+            if self.rounds_without_improvement >= patience {
+                self.plateau_learning_rate =
+                    (self.plateau_learning_rate * factor).max(minimum_learning_rate);
+                self.rounds_without_improvement = 0;
+            }
+        }
+    }
+}
+
+/// The factor, patience and minimum learning rate of ReduceOnPlateau
+fn plateau(schedule: &LearningRateSchedule) -> Option<(f64, u32, f64)> {
+    match schedule {
+        LearningRateSchedule::ReduceOnPlateau {
+            factor,
+            patience,
+            minimum_learning_rate,
+        } => Some((*factor, *patience, *minimum_learning_rate)),
+        LearningRateSchedule::LinearWarmup { after, .. } => plateau(after),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn learning_rates(schedule: LearningRateSchedule, rounds: u32) -> Vec<f64> {
+        let scheduler = Scheduler::new(&schedule, 1.0).unwrap();
+        (0..rounds)
+            .map(|round| scheduler.learning_rate(round, 100))
+            .collect()
+    }
+
+    #[test]
+    fn testing_step_and_exponential() {
+        assert_eq!(
+            learning_rates(LearningRateSchedule::Constant, 3),
+            vec![1.0, 1.0, 1.0]
+        );
+        assert_eq!(
+            learning_rates(
+                LearningRateSchedule::Step {
+                    step_size: 2,
+                    factor: 0.5
+                },
+                5
+            ),
+            vec![1.0, 1.0, 0.5, 0.5, 0.25]
+        );
+        assert_eq!(
+            learning_rates(LearningRateSchedule::Exponential { factor: 0.5 }, 3),
+            vec![1.0, 0.5, 0.25]
+        );
+    }
+
+    #[test]
+    fn testing_cosine_warm_restarts() {
+        let learning_rates = learning_rates(
+            LearningRateSchedule::CosineWarmRestarts {
+                period: 2,
+                period_multiplier: 2,
+                minimum_learning_rate: 0.0,
+            },
+            7,
+        );
+        // a period of 2 rounds, then a period of 4 rounds
+        let expected = [
+            1.0,
+            0.5,
+            1.0,
+            0.8535533905932737,
+            0.5,
+            0.1464466094067262,
+            1.0,
+        ];
+        for (actual, expected) in learning_rates.iter().zip(expected) {
+            assert!((actual - expected).abs() < 1e-15);
+        }
+    }
+
+    #[test]
+    fn testing_linear_warmup() {
+        let scheduler = Scheduler::new(
+            &LearningRateSchedule::LinearWarmup {
+                warmup_steps: 4,
+                after: Box::new(LearningRateSchedule::Exponential { factor: 0.5 }),
+            },
+            1.0,
+        )
+        .unwrap();
+        assert_eq!(
+            (0..6)
+                .map(|step| scheduler.learning_rate(0, step))
+                .collect::<Vec<f64>>(),
+            vec![0.25, 0.5, 0.75, 1.0, 1.0, 1.0]
+        );
+        assert_eq!(scheduler.learning_rate(1, 6), 0.5);
+    }
+
+    #[test]
+    fn testing_reduce_on_plateau() {
+        let schedule = LearningRateSchedule::ReduceOnPlateau {
+            factor: 0.5,
+            patience: 2,
+            minimum_learning_rate: 0.3,
+        };
+        assert!(schedule.needs_validation());
+        let mut scheduler = Scheduler::new(&schedule, 1.0).unwrap();
+        let mut learning_rates = vec![];
+        for validation_loss in [1.0, 0.5, 0.6, 0.7, 0.4, 0.4, 0.4, 0.4, 0.4] {
+            scheduler.end_of_round(Some(validation_loss));
+            learning_rates.push(scheduler.learning_rate(0, 0));
+        }
+        assert_eq!(
+            learning_rates,
+            vec![1.0, 1.0, 1.0, 0.5, 0.5, 0.5, 0.3, 0.3, 0.3]
+        );
+    }
+
+    #[test]
+    fn testing_schedule_ensure() {
+        for schedule in [
+            LearningRateSchedule::Step {
+                step_size: 0,
+                factor: 0.5,
+            },
+            LearningRateSchedule::Exponential { factor: 0.0 },
+            LearningRateSchedule::CosineWarmRestarts {
+                period: 0,
+                period_multiplier: 1,
+                minimum_learning_rate: 0.0,
+            },
+            LearningRateSchedule::LinearWarmup {
+                warmup_steps: 10,
+                after: Box::new(LearningRateSchedule::ReduceOnPlateau {
+                    factor: 1.5,
+                    patience: 1,
+                    minimum_learning_rate: 0.0,
+                }),
+            },
+        ] {
+            assert!(Scheduler::new(&schedule, 0.1).is_err());
+        }
+    }
+}
@@ -1,8 +1,160 @@
-#[derive(Debug)]
-pub struct TrainingEntry {
-    pub input: Vec<f64>,
-    pub expected_output: Vec<f64>
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::ops::Add;
+
+use super::clipping::GradientClipping;
+use super::evaluation::index_of_highest_value;
+use super::loss::{Loss, MeanSquaredError};
+use super::regularization::Regularization;
+use super::schedule::LearningRateSchedule;
+use crate::matrix::{float::Float, matrix::Matrix};
+
+#[derive(Debug, Clone)]
+pub struct TrainingEntry<T: Float = f64> {
+    pub input: Vec<T>,
+    pub expected_output: Vec<T>,
 }
 
 #[derive(Debug)]
-pub struct TrainingData(pub Vec<TrainingEntry>);
\ No newline at end of file
+pub struct TrainingData<T: Float = f64>(pub Vec<TrainingEntry<T>>);
+
+/// The settings that are used while training a neural network
+#[derive(Debug)]
+pub struct TrainingOptions<T: Float = f64> {
+    /// How many times the whole training data is used to train the neural network
+    pub rounds: u32,
+    /// The loss function that calculates the error rate of the output layer
+    pub loss: Box<dyn Loss<T>>,
+    /// Stops training when the validation loss didn't improve for this amount of rounds.
+    /// Without validation data (see DataLoader::validation_split) all rounds are trained.
+    pub patience: Option<u32>,
+    /// How the learning rate of the optimizer changes during training
+    pub learning_rate_schedule: LearningRateSchedule,
+    /// L1, L2 and max norm of the weights, the penalty of L1 and L2 is part of the training loss
+    pub regularization: Regularization,
+    /// Clips the gradients by value and by global norm before the weights and biases are updated
+    pub gradient_clipping: GradientClipping,
+}
+
+impl<T: Float> Default for TrainingOptions<T> {
+    fn default() -> Self {
+        TrainingOptions {
+            rounds: 1,
+            loss: Box::new(MeanSquaredError),
+            patience: None,
+            learning_rate_schedule: LearningRateSchedule::Constant,
+            regularization: Regularization::default(),
+            gradient_clipping: GradientClipping::default(),
+        }
+    }
+}
+
+/// The metrics of one round of training
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RoundMetrics {
+    /// The first round is 1
+    pub round: u32,
+    /// The mean loss of the training entries, every batch is measured before the weights and
+    /// biases are updated with it. With L1 or L2 regularization the penalty is included.
+    pub training_loss: f64,
+    /// The fraction of the training entries where the predicted class is the expected class,
+    /// measured like the training loss
+    pub training_accuracy: f64,
+    /// The mean loss of the validation entries after the round, None without validation data
+    pub validation_loss: Option<f64>,
+    /// The accuracy of the validation entries after the round, None without validation data
+    pub validation_accuracy: Option<f64>,
+    /// The learning rate of the last batch of the round
+    pub learning_rate: f64,
+}
+
+/// What happened during training, round by round
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TrainingHistory {
+    pub rounds: Vec<RoundMetrics>,
+    /// The round with the lowest validation loss, the trained neural network has the weights
+    /// and biases of this round. None without validation data.
+    pub best_round: Option<u32>,
+    /// True when training stopped before the last round, because the validation loss didn't
+    /// improve for the amount of rounds of the patience
+    pub stopped_early: bool,
+    /// True when the observer stopped training, the metrics of an unfinished round are not in
+    /// the rounds
+    pub cancelled: bool,
+}
+
+/// The sum of the losses and the amount of correct predictions of one or more batches
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct BatchMetrics {
+    pub sum_of_losses: f64,
+    pub amount_of_entries: u32,
+    pub amount_of_correct_entries: u32,
+}
+
+impl BatchMetrics {
+    /// Every column of the target and the actual output is one entry of the batch
+    pub(crate) fn new<T: Float>(
+        loss: &dyn Loss<T>,
+        target: &Matrix<T>,
+        actual: &Matrix<T>,
+    ) -> Result<BatchMetrics> {
+        let predicted_class = |matrix: &Matrix<T>, col| {
+            index_of_highest_value(
+                &matrix
+                    .column(col)
+                    .map(|value| value.to_f64())
+                    .collect::<Vec<f64>>(),
+            )
+        };
+        Ok(BatchMetrics {
+            sum_of_losses: loss.loss(target, actual)? * target.cols() as f64,
+            amount_of_entries: target.cols() as u32,
+            amount_of_correct_entries: (0..target.cols())
+                .filter(|col| predicted_class(target, *col) == predicted_class(actual, *col))
+                .count() as u32,
+        })
+    }
+
+    /// 0 without entries
+    pub fn mean_loss(&self) -> f64 {
+        self.sum_of_losses / self.amount_of_entries.max(1) as f64
+    }
+
+    /// 0 without entries
+    pub fn accuracy(&self) -> f64 {
+        self.amount_of_correct_entries as f64 / self.amount_of_entries.max(1) as f64
+    }
+}
+
+impl Add for BatchMetrics {
+    type Output = BatchMetrics;
+
+    fn add(self, other: BatchMetrics) -> BatchMetrics {
+        BatchMetrics {
+            sum_of_losses: self.sum_of_losses + other.sum_of_losses,
+            amount_of_entries: self.amount_of_entries + other.amount_of_entries,
+            amount_of_correct_entries: self.amount_of_correct_entries
+                + other.amount_of_correct_entries,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn testing_batch_metrics() {
+        let target = Matrix::from_rows(vec![vec![1.0, 0.0], vec![0.0, 1.0]]).unwrap();
+        let actual = Matrix::from_rows(vec![vec![0.8, 0.6], vec![0.2, 0.4]]).unwrap();
+        let metrics = BatchMetrics::new(&MeanSquaredError, &target, &actual).unwrap();
+        // the first column is correct, the second column predicts the first class
+        assert_eq!(metrics.amount_of_entries, 2);
+        assert_eq!(metrics.amount_of_correct_entries, 1);
+        assert_eq!(metrics.accuracy(), 0.5);
+        let combined = metrics + metrics;
+        assert_eq!(combined.amount_of_entries, 4);
+        assert_eq!(combined.mean_loss(), metrics.mean_loss());
+        assert_eq!(BatchMetrics::default().mean_loss(), 0.0);
+    }
+}
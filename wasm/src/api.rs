@@ -1,16 +1,30 @@
 use crate::frontend_validation::FrontendValidation;
-use crate::neuralnetwork::{create, neural_network_from_string, neural_network_to_string, train};
+use crate::neuralnetwork::{
+    backquery, create, evaluate, neural_network_from_string, neural_network_to_string, train,
+};
 use crate::{
     base64_png::Base64Png, mnist_image::MnistImage, neuralnetwork_image::NeuralNetworkImage,
+    trainer::TrainingSession,
 };
 use anyhow::{bail, Context};
 use core::neuralnetwork::query::{QueryData, QueryEntry};
+use js_sys::Function;
+use rand::{rngs::StdRng, SeedableRng};
 use std::convert::TryFrom;
 use wasm_bindgen::{prelude::wasm_bindgen, JsValue};
 
+/// The random number generator of the demo, with a seed the results are always the same. Without
+/// a seed (undefined in javascript) every call gives other results.
+fn rng_from_seed(seed: Option<u32>) -> StdRng {
+    match seed {
+        Some(seed) => StdRng::seed_from_u64(seed as u64),
+        None => StdRng::from_entropy(),
+    }
+}
+
 #[wasm_bindgen]
-pub fn get_random_image() -> Result<String, JsValue> {
-    match Base64Png::try_from(MnistImage::get_random()) {
+pub fn get_random_image(seed: Option<u32>) -> Result<String, JsValue> {
+    match MnistImage::get_random(&mut rng_from_seed(seed)).and_then(Base64Png::try_from) {
         Ok(ok) => Ok(ok.0),
         Err(err) => Err(JsValue::from(format!(
             "Rust error in get_3_random_images: {:?}",
@@ -46,7 +60,7 @@ fn query_nn(
         .context("query_nn: cannot convert Base64Png to NeuralNetworkImage")?;
     let result = neural_network
         .query(&QueryData(&vec![QueryEntry {
-            input: neural_network_image.0,
+            input: neural_network_image.to_f32(),
         }]))
         .context("query_nn: error while querying")?;
     Ok(result
@@ -56,16 +70,81 @@ fn query_nn(
         .collect::<Vec<String>>())
 }
 
+#[wasm_bindgen]
+pub fn backquery_neuralnetwork(
+    neuralnetwork_as_string: Option<String>,
+    label: Option<i32>,
+) -> Result<String, JsValue> {
+    match backquery_nn(neuralnetwork_as_string, label) {
+        Ok(ok) => Ok(ok),
+        Err(err) => Err(JsValue::from(format!(
+            "Rust error in backquery_neuralnetwork: {:?}",
+            err
+        ))),
+    }
+}
+
+fn backquery_nn(
+    neuralnetwork_as_string: Option<String>,
+    label: Option<i32>,
+) -> anyhow::Result<String> {
+    let label = label
+        .frontend_validation(0, 9)
+        .context("backquery_nn: label is incorrect")?;
+    let neuralnetwork_as_string =
+        neuralnetwork_as_string.context("backquery_nn: neuralnetwork_as_string is empty")?;
+    let neural_network = neural_network_from_string(neuralnetwork_as_string)
+        .context("backquery_nn: problem in neural_network_from_string")?;
+    let neural_network_image =
+        backquery(&neural_network, label).context("backquery_nn: error while backquerying")?;
+    Ok(Base64Png::try_from(MnistImage::from(neural_network_image))
+        .context("backquery_nn: cannot convert MnistImage to Base64Png")?
+        .0)
+}
+
+/// Evaluates the neural network with the 10,000 images of the mnist test dataset. Returns the
+/// evaluation report as json, with among others the accuracy and the confusion matrix.
+#[wasm_bindgen]
+pub fn evaluate_neuralnetwork(neuralnetwork_as_string: Option<String>) -> Result<String, JsValue> {
+    match evaluate_nn(neuralnetwork_as_string) {
+        Ok(ok) => Ok(ok),
+        Err(err) => Err(JsValue::from(format!(
+            "Rust error in evaluate_neuralnetwork: {:?}",
+            err
+        ))),
+    }
+}
+
+fn evaluate_nn(neuralnetwork_as_string: Option<String>) -> anyhow::Result<String> {
+    let neuralnetwork_as_string =
+        neuralnetwork_as_string.context("evaluate_nn: neuralnetwork_as_string is empty")?;
+    let neural_network = neural_network_from_string(neuralnetwork_as_string)
+        .context("evaluate_nn: problem in neural_network_from_string")?;
+    let report = evaluate(&neural_network).context("evaluate_nn: error while evaluating")?;
+    serde_json::to_string(&report).context("evaluate_nn: error while converting to json")
+}
+
+/// Creates and trains a neural network with the mnist training dataset. With shuffle the training
+/// images are used in another random order every round. With a seed the neural network and the
+/// order of the images are always the same. The optional function progress gets the progress of
+/// training every 1000 images and after every round, when it returns false training stops and
+/// the neural network is returned as it is.
 #[wasm_bindgen]
 pub fn train_neuralnetwork(
     amount_of_hidden_neurons: Option<i32>,
     amount_of_training_rounds: Option<i32>,
     learning_rate: Option<f64>,
+    shuffle: Option<bool>,
+    seed: Option<u32>,
+    progress: Option<Function>,
 ) -> Result<String, JsValue> {
     match train_nn(
         amount_of_hidden_neurons,
         amount_of_training_rounds,
         learning_rate,
+        shuffle,
+        seed,
+        progress,
     ) {
         Ok(ok) => Ok(ok),
         Err(err) => Err(JsValue::from(format!(
@@ -79,50 +158,170 @@ fn train_nn(
     amount_of_hidden_neurons: Option<i32>,
     amount_of_training_rounds: Option<i32>,
     learning_rate: Option<f64>,
+    shuffle: Option<bool>,
+    seed: Option<u32>,
+    progress: Option<Function>,
 ) -> anyhow::Result<String> {
+    let (amount_of_hidden_neurons, amount_of_training_rounds, learning_rate) =
+        validate_training_settings(
+            amount_of_hidden_neurons,
+            amount_of_training_rounds,
+            learning_rate,
+        )
+        .context("train_nn: incorrect settings")?;
+    let mut rng = rng_from_seed(seed);
+    let neural_network = create(amount_of_hidden_neurons, &mut rng);
+    let trained_neural_network = train(
+        neural_network,
+        amount_of_training_rounds,
+        learning_rate,
+        shuffle.unwrap_or(false),
+        progress,
+        &mut rng,
+    )
+    .context("train_nn: error while training")?;
+    neural_network_to_string(&trained_neural_network)
+        .context("train_nn: error while converting to string")
+}
+
+/// The amount of hidden neurons, the amount of training rounds and the learning rate
+fn validate_training_settings(
+    amount_of_hidden_neurons: Option<i32>,
+    amount_of_training_rounds: Option<i32>,
+    learning_rate: Option<f64>,
+) -> anyhow::Result<(u32, u32, f64)> {
     match (
         amount_of_hidden_neurons.frontend_validation(10, 100),
         amount_of_training_rounds.frontend_validation(1, 10),
         learning_rate.frontend_validation(0.1, 1.0),
     ) {
-        (Err(err), _, _) => bail!(format!(
-            "train_nn: amount of hidden neurons is incorrect. {}",
-            err
-        )),
-        (_, Err(err), _) => bail!(format!(
-            "train_nn: amount of training rounds is incorrect. {}",
-            err
-        )),
-        (_, _, Err(err)) => bail!(format!(
-            "train_nn: value for learning rate is incorrect. {}",
-            err
+        (Err(err), _, _) => bail!(format!("amount of hidden neurons is incorrect. {}", err)),
+        (_, Err(err), _) => bail!(format!("amount of training rounds is incorrect. {}", err)),
+        (_, _, Err(err)) => bail!(format!("value for learning rate is incorrect. {}", err)),
+        (Ok(amount_of_hidden_neurons), Ok(amount_of_training_rounds), Ok(learning_rate)) => Ok((
+            amount_of_hidden_neurons,
+            amount_of_training_rounds,
+            learning_rate,
         )),
-        (Ok(amount_of_hidden_neurons), Ok(amount_of_training_rounds), Ok(learning_rate)) => {
-            let neural_network = create(amount_of_hidden_neurons);
-            let trained_neural_network =
-                train(neural_network, amount_of_training_rounds, learning_rate)
-                    .context("train_nn: error while training")?;
-            Ok(neural_network_to_string(&trained_neural_network)
-                .context("train_nn: error while converting to string")?)
-        }
+    }
+}
+
+/// Trains a neural network with the mnist training dataset step by step, so the page can show
+/// the progress between the steps and training can be paused. A trainer can be saved as json in
+/// the middle of training and continues exactly where it stopped.
+#[wasm_bindgen]
+pub struct Trainer(TrainingSession);
+
+#[wasm_bindgen]
+impl Trainer {
+    /// The settings are the same as for train_neuralnetwork
+    #[wasm_bindgen(constructor)]
+    pub fn new(
+        amount_of_hidden_neurons: Option<i32>,
+        amount_of_training_rounds: Option<i32>,
+        learning_rate: Option<f64>,
+        shuffle: Option<bool>,
+        seed: Option<u32>,
+    ) -> Result<Trainer, JsValue> {
+        validate_training_settings(
+            amount_of_hidden_neurons,
+            amount_of_training_rounds,
+            learning_rate,
+        )
+        .and_then(
+            |(amount_of_hidden_neurons, amount_of_training_rounds, learning_rate)| {
+                TrainingSession::new(
+                    amount_of_hidden_neurons,
+                    amount_of_training_rounds,
+                    learning_rate,
+                    shuffle.unwrap_or(false),
+                    seed,
+                )
+            },
+        )
+        .map(Trainer)
+        .map_err(|err| JsValue::from(format!("Rust error in Trainer::new: {:?}", err)))
+    }
+
+    /// Trains the neural network with the next images (1000 when undefined) and returns the
+    /// progress as json, with the round, the amount of images of the round that are used and
+    /// the loss and accuracy of these images
+    pub fn step(&mut self, amount_of_images: Option<u32>) -> Result<String, JsValue> {
+        self.0
+            .step(amount_of_images.unwrap_or(1000) as usize)
+            .and_then(|_| Ok(serde_json::to_string(&self.0.progress())?))
+            .map_err(|err| JsValue::from(format!("Rust error in Trainer::step: {:?}", err)))
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.0.is_finished()
+    }
+
+    /// The neural network as it is trained so far
+    pub fn neural_network(&self) -> Result<String, JsValue> {
+        neural_network_to_string(self.0.neural_network()).map_err(|err| {
+            JsValue::from(format!("Rust error in Trainer::neural_network: {:?}", err))
+        })
+    }
+
+    /// The whole state of training, to continue later on with from_json
+    pub fn to_json(&self) -> Result<String, JsValue> {
+        serde_json::to_string(&self.0)
+            .map_err(|err| JsValue::from(format!("Rust error in Trainer::to_json: {:?}", err)))
+    }
+
+    pub fn from_json(json: Option<String>) -> Result<Trainer, JsValue> {
+        json.context("Trainer::from_json: json is empty")
+            .and_then(|json| Ok(serde_json::from_str(&json)?))
+            .map(Trainer)
+            .map_err(|err| JsValue::from(format!("Rust error in Trainer::from_json: {:?}", err)))
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use super::{backquery_nn, evaluate_nn, rng_from_seed};
     use crate::{
         base64_png::Base64Png, mnist_image::MnistImage, neuralnetwork_image::NeuralNetworkImage,
     };
+    use core::neuralnetwork::evaluation::EvaluationReport;
     use std::convert::TryFrom;
 
     #[test]
     fn from_random_mnistimage_to_base64_to_nnimage_back_to_mnistimage() {
-        let random_image = MnistImage::get_random();
+        let random_image = MnistImage::get_random(&mut rng_from_seed(None)).unwrap();
         let base64png = Base64Png::try_from(random_image.clone()).unwrap();
         let nn_image = NeuralNetworkImage::try_from(base64png).unwrap();
         assert_eq!(MnistImage::from(nn_image).0, random_image.0);
     }
 
+    #[test]
+    fn the_same_seed_gives_the_same_random_image() {
+        assert_eq!(
+            MnistImage::get_random(&mut rng_from_seed(Some(3)))
+                .unwrap()
+                .0,
+            MnistImage::get_random(&mut rng_from_seed(Some(3)))
+                .unwrap()
+                .0
+        );
+    }
+
+    #[test]
+    fn backquery_pre_trained_neural_network() {
+        let neuralnetwork_as_string = include_str!("../../www/pre-trained-nn.txt").to_owned();
+        let base64png = backquery_nn(Some(neuralnetwork_as_string.clone()), Some(3)).unwrap();
+        assert!(base64png.starts_with(r#"<img src="data:image/png;base64,"#));
+        assert_eq!(
+            NeuralNetworkImage::try_from(Base64Png(base64png))
+                .unwrap()
+                .0
+                .len(),
+            784
+        );
+        assert!(backquery_nn(Some(neuralnetwork_as_string), Some(10)).is_err());
+    }
+
     #[test]
     fn test_neuralnetwork_image_try_from() {
         let base64png = Base64Png(String::from(
@@ -918,4 +1117,18 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn evaluate_pre_trained_neural_network_with_the_test_dataset() {
+        let neuralnetwork_as_string = include_str!("../../www/pre-trained-nn.txt").to_owned();
+        let report: EvaluationReport =
+            serde_json::from_str(&evaluate_nn(Some(neuralnetwork_as_string)).unwrap()).unwrap();
+        assert_eq!(report.amount_of_entries, 10000);
+        assert_eq!(report.confusion_matrix.0.len(), 10);
+        assert_eq!(
+            report.confusion_matrix.0.iter().flatten().sum::<u32>(),
+            10000
+        );
+        assert!(evaluate_nn(None).is_err());
+    }
 }
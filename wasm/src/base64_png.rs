@@ -1,5 +1,6 @@
 use crate::mnist_image::MnistImage;
 use anyhow::Context;
+use base64::{engine::general_purpose::STANDARD, Engine};
 use image::{DynamicImage, ImageBuffer, ImageOutputFormat, Luma};
 use std::{convert::TryFrom, io::Cursor};
 
@@ -19,7 +20,7 @@ impl TryFrom<MnistImage> for Base64Png {
         );
         let mut buffer = Cursor::new(vec![]);
         dynamic_image.write_to(&mut buffer, ImageOutputFormat::Png)?;
-        let result = base64::encode(&buffer.into_inner());
+        let result = STANDARD.encode(buffer.into_inner());
         Ok(Base64Png(format!(
             r#"<img src="data:image/png;base64,{result}">"#
         )))
@@ -1,6 +1,8 @@
-pub(crate) static MNIST_VERIFICATION_IMAGES: &'static [u8] =
+pub(crate) static MNIST_TEST_IMAGES: &[u8] =
     include_bytes!("../../mnist-dataset/t10k-images.idx3-ubyte");
-pub(crate) static MNIST_TRAINING_IMAGES: &'static [u8] =
+pub(crate) static MNIST_TEST_LABELS: &[u8] =
+    include_bytes!("../../mnist-dataset/t10k-labels.idx1-ubyte");
+pub(crate) static MNIST_TRAINING_IMAGES: &[u8] =
     include_bytes!("../../mnist-dataset/train-images.idx3-ubyte");
-pub(crate) static MNIST_TRAINING_LABELS: &'static [u8] =
-    include_bytes!("../../mnist-dataset/train-labels.idx1-ubyte");
\ No newline at end of file
+pub(crate) static MNIST_TRAINING_LABELS: &[u8] =
+    include_bytes!("../../mnist-dataset/train-labels.idx1-ubyte");
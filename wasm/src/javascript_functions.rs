@@ -18,4 +18,9 @@ extern "C" {
     // Multiple arguments too!
     #[wasm_bindgen(js_namespace = console, js_name = log)]
     pub fn log_many(a: &str, b: &str);
-}
\ No newline at end of file
+
+    // The time in milliseconds since the page was loaded, std::time::Instant doesn't work in
+    // the browser
+    #[wasm_bindgen(js_namespace = performance, js_name = now)]
+    pub fn performance_now() -> f64;
+}
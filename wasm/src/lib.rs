@@ -3,6 +3,9 @@ mod base64_png;
 mod files;
 mod frontend_validation;
 mod javascript_functions;
+mod mnist_dataset;
 mod mnist_image;
 mod neuralnetwork;
 mod neuralnetwork_image;
+mod progress;
+mod trainer;
@@ -0,0 +1,117 @@
+use crate::files::{
+    MNIST_TEST_IMAGES, MNIST_TEST_LABELS, MNIST_TRAINING_IMAGES, MNIST_TRAINING_LABELS,
+};
+use anyhow::{ensure, Context, Result};
+use core::dataset::{
+    dataloader::DataLoader,
+    idx::{IdxDataset, IdxFile, IdxType},
+};
+
+/// The two parts of the mnist dataset, the neural network is trained with the 60,000 training
+/// images and evaluated with the 10,000 test images it has never seen.
+/// Check README.md inside the mnist-dataset folder for the layout of the files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum MnistDataset {
+    Training,
+    Test,
+}
+
+impl MnistDataset {
+    /// The images are 28 x 28 pixels, every pixel is an unsigned byte
+    pub(crate) fn images(self) -> Result<IdxFile<'static>> {
+        let images = IdxFile::parse(match self {
+            MnistDataset::Training => MNIST_TRAINING_IMAGES,
+            MnistDataset::Test => MNIST_TEST_IMAGES,
+        })
+        .with_context(|| format!("MnistDataset: cannot read the {:?} images", self))?;
+        ensure!(
+            images.header().data_type == IdxType::UnsignedByte
+                && images.header().dimensions[1..] == [28, 28],
+            "MnistDataset: the {:?} images should be 28 x 28 unsigned bytes, not {:?} {:?}",
+            self,
+            images.header().data_type,
+            images.header().dimensions
+        );
+        Ok(images)
+    }
+
+    /// Every label is an unsigned byte between 0 and 9
+    pub(crate) fn labels(self) -> Result<IdxFile<'static>> {
+        let labels = IdxFile::parse(match self {
+            MnistDataset::Training => MNIST_TRAINING_LABELS,
+            MnistDataset::Test => MNIST_TEST_LABELS,
+        })
+        .with_context(|| format!("MnistDataset: cannot read the {:?} labels", self))?;
+        ensure!(
+            labels.header().data_type == IdxType::UnsignedByte
+                && labels.header().dimensions.len() == 1,
+            "MnistDataset: the {:?} labels should be one unsigned byte each",
+            self
+        );
+        Ok(labels)
+    }
+
+    /// The images and labels are read from the files when they are used. The pixels are
+    /// normalized like a NeuralNetworkImage and the expected output is like expected_output.
+    pub(crate) fn data_loader(self) -> Result<DataLoader<'static, f32>> {
+        let dataset = IdxDataset::new(self.images()?, self.labels()?, 10).with_context(|| {
+            format!(
+                "MnistDataset: cannot combine the {:?} images and labels",
+                self
+            )
+        })?;
+        Ok(DataLoader::new(dataset).transform(|mut entry| {
+            //normalize the values because of sigmoid (between 0 and 1)
+            entry
+                .input
+                .iter_mut()
+                .for_each(|value| *value = ((*value / 255.0) * 0.99) + 0.01);
+            entry
+                .expected_output
+                .iter_mut()
+                .for_each(|value| *value = (*value * 0.98) + 0.01);
+            entry
+        }))
+    }
+}
+
+/// The expected output of the neural network for a label, 0.99 for the output neuron of the
+/// label and 0.01 for the other output neurons
+pub(crate) fn expected_output(label: usize) -> Vec<f32> {
+    let mut expected_output = vec![0.01; 10];
+    expected_output[label] = 0.99;
+    expected_output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use core::dataset::dataset::Dataset;
+
+    #[test]
+    fn the_training_and_test_dataset_are_separate() {
+        assert_eq!(MnistDataset::Training.images().unwrap().len(), 60000);
+        assert_eq!(MnistDataset::Training.labels().unwrap().len(), 60000);
+        assert_eq!(MnistDataset::Test.images().unwrap().len(), 10000);
+        assert_eq!(MnistDataset::Test.labels().unwrap().len(), 10000);
+    }
+
+    #[test]
+    fn the_data_loader_normalizes_the_entries() {
+        let data_loader = MnistDataset::Test.data_loader().unwrap();
+        assert_eq!(data_loader.len(), 10000);
+        let entry = data_loader.get(0).unwrap();
+        let label = MnistDataset::Test
+            .labels()
+            .unwrap()
+            .item(0)
+            .unwrap()
+            .bytes()[0];
+        assert_eq!(entry.expected_output, expected_output(label as usize));
+        assert!(entry
+            .input
+            .iter()
+            .all(|value| *value >= 0.01 && *value <= 1.0));
+    }
+}
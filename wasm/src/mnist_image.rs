@@ -1,34 +1,20 @@
-use crate::{
-    files::MNIST_TRAINING_IMAGES, files::MNIST_VERIFICATION_IMAGES,
-    neuralnetwork_image::NeuralNetworkImage,
-};
+use crate::{mnist_dataset::MnistDataset, neuralnetwork_image::NeuralNetworkImage};
+use anyhow::{ensure, Context, Result};
 use rand::Rng;
 
 #[derive(Debug, Clone)]
 pub(crate) struct MnistImage(pub(crate) Vec<u8>);
 
 impl MnistImage {
-    pub(crate) fn get_random() -> Self {
-        //Skip first bytes, thats the meta data, check README.md inside the mnist-dataset folder
-        let mut rng = rand::thread_rng();
-        MnistImage(
-            MNIST_VERIFICATION_IMAGES[16..]
-                .chunks(784)
-                // take a random image between 0 and 10000 (the size of the mnist verification dataset)
-                // if we skip 0 we take the first image for the dataset, if we skip 9999 we take the last image from the dataset
-                .skip(rng.gen_range(0..=9999))
-                .take(1)
-                .flat_map(|image| image.iter().map(|value| *value).collect::<Vec<u8>>())
-                .collect::<Vec<u8>>(),
-        )
-    }
-
-    pub(crate) fn get_all_test_images() -> Vec<Self> {
-        //Skip first bytes, thats the meta data, check README.md inside the mnist-dataset folder
-        MNIST_TRAINING_IMAGES[16..]
-            .chunks(784)
-            .map(|image| MnistImage(image.iter().map(|value| *value).collect::<Vec<u8>>()))
-            .collect()
+    /// A random image of the test dataset
+    pub(crate) fn get_random(rng: &mut impl Rng) -> Result<Self> {
+        let images = MnistDataset::Test.images()?;
+        ensure!(!images.is_empty(), "MnistImage: there are no images");
+        let index = rng.gen_range(0..images.len());
+        let image = images
+            .item(index)
+            .context("MnistImage: the index is out of bounds")?;
+        Ok(MnistImage(image.bytes().to_vec()))
     }
 }
 
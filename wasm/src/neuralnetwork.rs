@@ -1,56 +1,90 @@
 use anyhow::Context;
 
 use crate::{
-    files::MNIST_TRAINING_LABELS, mnist_image::MnistImage, neuralnetwork_image::NeuralNetworkImage,
+    mnist_dataset::{expected_output, MnistDataset},
+    neuralnetwork_image::NeuralNetworkImage,
+    progress::JavaScriptProgress,
 };
 use core::neuralnetwork::{
+    evaluation::{EvaluationOptions, EvaluationReport},
     neuralnetwork::NeuralNetwork,
-    training::{TrainingData, TrainingEntry},
+    optimizer::Sgd,
+    training::TrainingOptions,
 };
-use std::convert::TryFrom;
+use js_sys::Function;
+use rand::Rng;
+
+/// The demo uses f32 values, a trained neural network is half the size of one with f64 values.
+/// A neural network that was saved with f64 values can still be loaded.
+pub(crate) type DemoNeuralNetwork = NeuralNetwork<f32>;
 
 pub(crate) fn neural_network_from_string(
     neuralnetwork_as_string: String,
-) -> anyhow::Result<NeuralNetwork> {
+) -> anyhow::Result<DemoNeuralNetwork> {
     Ok(serde_json::from_str(&neuralnetwork_as_string)?)
 }
 
-pub(crate) fn neural_network_to_string(neural_network: &NeuralNetwork) -> anyhow::Result<String> {
+pub(crate) fn neural_network_to_string(
+    neural_network: &DemoNeuralNetwork,
+) -> anyhow::Result<String> {
     Ok(serde_json::to_string(neural_network)?)
 }
 
-pub(crate) fn create(amount_of_hidden_neurons: u32) -> NeuralNetwork {
-    NeuralNetwork::new_with_random_values(784, amount_of_hidden_neurons, 10, 1)
+pub(crate) fn create(amount_of_hidden_neurons: u32, rng: &mut impl Rng) -> DemoNeuralNetwork {
+    NeuralNetwork::new_with_random_values(784, amount_of_hidden_neurons, 10, 1, rng)
 }
 
+/// Trains the neural network with the training dataset. With shuffle the images are used in
+/// another random order every round, otherwise in the order of the file. The progress of
+/// training is forwarded to the javascript function progress, which can stop training.
 pub(crate) fn train(
-    neural_network: NeuralNetwork,
+    neural_network: DemoNeuralNetwork,
     amount_of_training_rounds: u32,
     learning_rate: f64,
-) -> anyhow::Result<NeuralNetwork> {
-    let test_images: Vec<NeuralNetworkImage> = MnistImage::get_all_test_images()
-        .into_iter()
-        .map(NeuralNetworkImage::try_from)
-        .collect::<Result<Vec<NeuralNetworkImage>, _>>()
-        .context("train: cannot convert to NeuralNetworkImage")?;
-    //Skip first bytes, thats the meta data, check README.md inside the mnist-dataset folder
-    let test_labels: Vec<Vec<f64>> = MNIST_TRAINING_LABELS[8..]
-        .iter()
-        .map(|value| {
-            let mut labels = vec![0.01; 10];
-            labels[*value as usize] = 0.99;
-            labels
-        })
-        .collect::<Vec<Vec<f64>>>();
-    let training_data = TrainingData(
-        test_images
-            .into_iter()
-            .zip(test_labels.into_iter())
-            .map(|(image, label)| TrainingEntry {
-                input: image.0,
-                expected_output: label,
-            })
-            .collect::<Vec<TrainingEntry>>(),
-    );
-    neural_network.train(&training_data, amount_of_training_rounds, learning_rate)
+    shuffle: bool,
+    progress: Option<Function>,
+    rng: &mut impl Rng,
+) -> anyhow::Result<DemoNeuralNetwork> {
+    let training_data = MnistDataset::Training
+        .data_loader()
+        .context("train: cannot load the training dataset")?
+        .shuffle(shuffle);
+    let options = TrainingOptions {
+        rounds: amount_of_training_rounds,
+        ..TrainingOptions::default()
+    };
+    let mut optimizer = Sgd::new(learning_rate);
+    match progress {
+        Some(progress) => neural_network.train_with_observer(
+            &training_data,
+            &options,
+            &mut optimizer,
+            rng,
+            &mut JavaScriptProgress::new(progress),
+        ),
+        None => neural_network.train(&training_data, &options, &mut optimizer, rng),
+    }
+    .map(|(neural_network, _)| neural_network)
+}
+
+/// Evaluates the neural network with the 10,000 images of the test dataset, which are not used
+/// for training
+pub(crate) fn evaluate(neural_network: &DemoNeuralNetwork) -> anyhow::Result<EvaluationReport> {
+    let test_data = MnistDataset::Test
+        .data_loader()
+        .context("evaluate: cannot load the test dataset")?;
+    neural_network.evaluate_dataset(&test_data, &EvaluationOptions::default())
+}
+
+/// Generates the image the neural network associates with the label (a digit between 0 and 9)
+pub(crate) fn backquery(
+    neural_network: &DemoNeuralNetwork,
+    label: u32,
+) -> anyhow::Result<NeuralNetworkImage> {
+    let input = neural_network
+        .backquery(&expected_output(label as usize))
+        .context("backquery: error while backquerying")?;
+    Ok(NeuralNetworkImage(
+        input.iter().map(|value| *value as f64).collect(),
+    ))
 }
@@ -1,18 +1,26 @@
 use crate::{base64_png::Base64Png, mnist_image::MnistImage};
+use base64::{engine::general_purpose::STANDARD, Engine};
 use image::EncodableLayout;
 use std::convert::TryFrom;
 
-/// This struct is used to convert a MnistImage to a NeuralNetworkImage, so it 
+/// This struct is used to convert a MnistImage to a NeuralNetworkImage, so it
 /// can be used in the neural network
 #[derive(Debug, Clone)]
 pub(crate) struct NeuralNetworkImage(pub(crate) Vec<f64>);
 
+impl NeuralNetworkImage {
+    /// The input for the demo neural network, which uses f32 values
+    pub(crate) fn to_f32(&self) -> Vec<f32> {
+        self.0.iter().map(|value| *value as f32).collect()
+    }
+}
+
 impl TryFrom<Base64Png> for NeuralNetworkImage {
     type Error = anyhow::Error;
 
     fn try_from(value: Base64Png) -> Result<Self, Self::Error> {
         let base64_encoded_png = &value.0[32..].replace(r#"">"#, "");
-        let decoded_image = base64::decode(base64_encoded_png)?;
+        let decoded_image = STANDARD.decode(base64_encoded_png)?;
         let image = image::load_from_memory_with_format(&decoded_image, image::ImageFormat::Png)?;
         Ok(NeuralNetworkImage(
             image
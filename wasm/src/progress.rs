@@ -0,0 +1,74 @@
+use crate::javascript_functions::performance_now;
+use core::neuralnetwork::{
+    observer::{TrainingControl, TrainingObserver, TrainingProgress},
+    training::RoundMetrics,
+};
+use js_sys::{Function, JSON};
+use serde_json::json;
+use std::time::Duration;
+use wasm_bindgen::JsValue;
+
+/// With batches of 1 image a round has 60,000 batches, calling javascript after every batch
+/// would slow down training
+const BATCHES_BETWEEN_REPORTS: usize = 1000;
+
+/// Forwards the progress of training to a javascript function. The function gets an object with
+/// the round, the batch, the loss, the gradient norm of every layer and the elapsed
+/// milliseconds, when it returns false training stops.
+pub(crate) struct JavaScriptProgress {
+    callback: Function,
+}
+
+impl JavaScriptProgress {
+    pub(crate) fn new(callback: Function) -> Self {
+        JavaScriptProgress { callback }
+    }
+
+    fn report(
+        &self,
+        progress: &TrainingProgress,
+        metrics: Option<&RoundMetrics>,
+    ) -> TrainingControl {
+        let report = json!({
+            "round": progress.round,
+            "amount_of_rounds": progress.amount_of_rounds,
+            "batch": progress.batch,
+            "amount_of_batches": progress.amount_of_batches,
+            "loss": progress.round_loss,
+            "gradient_norms": progress.gradient_norms.layers,
+            "elapsed_milliseconds": progress.elapsed.as_millis() as u64,
+            "round_metrics": metrics,
+        });
+        let result = JSON::parse(&report.to_string())
+            .and_then(|report| self.callback.call1(&JsValue::NULL, &report));
+        match result {
+            Ok(value) if value == JsValue::FALSE => TrainingControl::Stop,
+            Ok(_) => TrainingControl::Continue,
+            // an exception in the callback stops training, otherwise it would be repeated for
+            // every report
+            Err(_) => TrainingControl::Stop,
+        }
+    }
+}
+
+impl TrainingObserver for JavaScriptProgress {
+    fn after_batch(&mut self, progress: &TrainingProgress) -> TrainingControl {
+        if progress.batch.is_multiple_of(BATCHES_BETWEEN_REPORTS) {
+            self.report(progress, None)
+        } else {
+            TrainingControl::Continue
+        }
+    }
+
+    fn after_round(
+        &mut self,
+        progress: &TrainingProgress,
+        metrics: &RoundMetrics,
+    ) -> TrainingControl {
+        self.report(progress, Some(metrics))
+    }
+
+    fn now(&self) -> Duration {
+        Duration::from_secs_f64(performance_now() / 1000.0)
+    }
+}
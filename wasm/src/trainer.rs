@@ -0,0 +1,207 @@
+use anyhow::Context;
+use core::{
+    dataset::dataloader::{DataLoader, DatasetCursor},
+    neuralnetwork::{
+        clipping::GradientNorms,
+        optimizer::{Optimizer, Sgd},
+        training::{BatchMetrics, RoundMetrics, TrainingOptions},
+    },
+};
+use rand::{rngs::StdRng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    mnist_dataset::MnistDataset,
+    neuralnetwork::{create, DemoNeuralNetwork},
+};
+
+/// Trains a neural network with the training dataset a few images at a time, so the page stays
+/// responsive. Everything that is needed to continue training is serialized, a paused training
+/// can be saved and resumed later on with exactly the same result.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct TrainingSession {
+    neural_network: DemoNeuralNetwork,
+    optimizer: Sgd,
+    amount_of_rounds: u32,
+    shuffle: bool,
+    /// The order of the images of a round is shuffled with a random number generator that is
+    /// seeded with the seed and the round, so it doesn't have to be saved
+    seed: u64,
+    /// The first round is 1, after the last round training is finished
+    round: u32,
+    cursor: DatasetCursor,
+    /// The metrics of the images of the current round that are used
+    metrics: BatchMetrics,
+    /// The norms of the gradients of the last batch
+    gradient_norms: GradientNorms,
+    rounds: Vec<RoundMetrics>,
+}
+
+/// How far training is, after a step
+#[derive(Debug, Serialize)]
+pub(crate) struct TrainingSessionProgress<'a> {
+    pub(crate) round: u32,
+    pub(crate) amount_of_rounds: u32,
+    /// The images of the current round that are used
+    pub(crate) images: usize,
+    pub(crate) amount_of_images: usize,
+    /// The mean loss of the images of the current round that are used
+    pub(crate) loss: f64,
+    pub(crate) accuracy: f64,
+    /// The norm of the gradients of every layer of the last batch
+    pub(crate) gradient_norms: &'a [f64],
+    pub(crate) finished: bool,
+    /// The metrics of the rounds that are finished
+    pub(crate) rounds: &'a [RoundMetrics],
+}
+
+impl TrainingSession {
+    /// Without a seed (undefined in javascript) a random seed is used
+    pub(crate) fn new(
+        amount_of_hidden_neurons: u32,
+        amount_of_rounds: u32,
+        learning_rate: f64,
+        shuffle: bool,
+        seed: Option<u32>,
+    ) -> anyhow::Result<TrainingSession> {
+        let seed = seed.map_or_else(rand::random, u64::from);
+        let data_loader = training_data(shuffle)?;
+        Ok(TrainingSession {
+            neural_network: create(amount_of_hidden_neurons, &mut StdRng::seed_from_u64(seed)),
+            optimizer: Sgd::new(learning_rate),
+            amount_of_rounds,
+            shuffle,
+            seed,
+            round: 1,
+            cursor: data_loader.cursor(&mut round_rng(seed, 1))?,
+            metrics: BatchMetrics::default(),
+            gradient_norms: GradientNorms::default(),
+            rounds: vec![],
+        })
+    }
+
+    /// Trains the neural network with the next images, at least the amount of images unless
+    /// training is finished before
+    pub(crate) fn step(&mut self, amount_of_images: usize) -> anyhow::Result<()> {
+        let data_loader = training_data(self.shuffle)?;
+        let options = TrainingOptions::default();
+        let mut used_images = 0;
+        while used_images < amount_of_images && !self.is_finished() {
+            let mut position = self.cursor.position();
+            let mut batches = data_loader.resume(self.cursor.clone())?;
+            for batch in batches.by_ref() {
+                let batch = batch?;
+                used_images += batch.len();
+                let (metrics, gradient_norms) = self.neural_network.train_batch(
+                    &batch,
+                    &options,
+                    &mut self.optimizer,
+                    &mut batch_rng(self.seed, self.round, position),
+                )?;
+                position += batch.len();
+                self.metrics = self.metrics + metrics;
+                self.gradient_norms = gradient_norms;
+                if used_images >= amount_of_images {
+                    break;
+                }
+            }
+            self.cursor = batches.cursor().clone();
+            if self.cursor.is_finished() {
+                self.next_round(&data_loader)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn next_round(&mut self, data_loader: &DataLoader<f32>) -> anyhow::Result<()> {
+        self.rounds.push(RoundMetrics {
+            round: self.round,
+            training_loss: self.metrics.mean_loss(),
+            training_accuracy: self.metrics.accuracy(),
+            validation_loss: None,
+            validation_accuracy: None,
+            learning_rate: Optimizer::<f32>::learning_rate(&self.optimizer),
+        });
+        self.metrics = BatchMetrics::default();
+        self.round += 1;
+        if !self.is_finished() {
+            self.cursor = data_loader.cursor(&mut round_rng(self.seed, self.round))?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn is_finished(&self) -> bool {
+        self.round > self.amount_of_rounds
+    }
+
+    pub(crate) fn progress(&self) -> TrainingSessionProgress<'_> {
+        let (loss, accuracy) = match self.rounds.last() {
+            Some(last_round) if self.is_finished() => {
+                (last_round.training_loss, last_round.training_accuracy)
+            }
+            _ => (self.metrics.mean_loss(), self.metrics.accuracy()),
+        };
+        TrainingSessionProgress {
+            round: self.round.min(self.amount_of_rounds),
+            amount_of_rounds: self.amount_of_rounds,
+            images: if self.is_finished() {
+                self.cursor.len()
+            } else {
+                self.cursor.position()
+            },
+            amount_of_images: self.cursor.len(),
+            loss,
+            accuracy,
+            gradient_norms: &self.gradient_norms.layers,
+            finished: self.is_finished(),
+            rounds: &self.rounds,
+        }
+    }
+
+    /// The neural network as it is trained so far
+    pub(crate) fn neural_network(&self) -> &DemoNeuralNetwork {
+        &self.neural_network
+    }
+}
+
+fn training_data(shuffle: bool) -> anyhow::Result<DataLoader<'static, f32>> {
+    Ok(MnistDataset::Training
+        .data_loader()
+        .context("TrainingSession: cannot load the training dataset")?
+        .shuffle(shuffle))
+}
+
+fn round_rng(seed: u64, round: u32) -> StdRng {
+    StdRng::seed_from_u64(seed.wrapping_add(round as u64))
+}
+
+/// The random number generator of a batch (for dropout) is seeded with the position of the
+/// batch, so a paused training continues with the same random numbers
+fn batch_rng(seed: u64, round: u32, position: usize) -> StdRng {
+    StdRng::seed_from_u64(seed ^ ((round as u64) << 32) ^ position as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TrainingSession;
+    use crate::neuralnetwork::neural_network_to_string;
+
+    #[test]
+    fn a_paused_training_session_continues_where_it_stopped() {
+        let mut trained_at_once = TrainingSession::new(10, 1, 0.3, true, Some(1)).unwrap();
+        trained_at_once.step(200).unwrap();
+        let mut paused = TrainingSession::new(10, 1, 0.3, true, Some(1)).unwrap();
+        paused.step(120).unwrap();
+        assert_eq!(paused.progress().images, 120);
+        let json = serde_json::to_string(&paused).unwrap();
+        let mut resumed: TrainingSession = serde_json::from_str(&json).unwrap();
+        resumed.step(80).unwrap();
+        assert_eq!(resumed.progress().images, 200);
+        assert_eq!(resumed.progress().loss, trained_at_once.progress().loss);
+        assert_eq!(
+            neural_network_to_string(resumed.neural_network()).unwrap(),
+            neural_network_to_string(trained_at_once.neural_network()).unwrap()
+        );
+        assert!(!resumed.is_finished());
+    }
+}
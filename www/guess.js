@@ -1,7 +1,8 @@
-import { get_random_image, query_neuralnetwork } from "./pkg/wasm.js";
+import { backquery_neuralnetwork, get_random_image, query_neuralnetwork } from "./pkg/wasm.js";
 
 document.getElementById('get3images').addEventListener('click', get3images);
 document.getElementById('guess').addEventListener('click', guessing);
+document.getElementById('backquery').addEventListener('click', backquerying);
 
 function get3images(event) {
     event.preventDefault();
@@ -48,4 +49,31 @@ function guessing(event) {
     } catch (e) {
         document.getElementById('error-message-text').innerText = e;
     }
-}
\ No newline at end of file
+}
+
+function backquerying(event) {
+    event.preventDefault();
+    try {
+        document.getElementById('error-message-text').innerText = '';
+        const neuralnetwork = document.getElementById('uploadInput').files[0];
+        if(!neuralnetwork) {
+            throw new Error("You didn't upload a neural network");
+        }
+        const reader = new FileReader();
+        reader.readAsText(neuralnetwork);
+        reader.onload = function (e) {
+            try {
+                const neuralnetworkAsText = e.target.result;
+                let images = '';
+                for (let digit = 0; digit < 10; digit++) {
+                    images += `<label>${digit}${backquery_neuralnetwork(neuralnetworkAsText, digit)}</label>`;
+                }
+                document.getElementById('backqueryimages').innerHTML = images;
+            } catch (e) {
+                document.getElementById('error-message-text').innerText = e;
+            }
+        };
+    } catch (e) {
+        document.getElementById('error-message-text').innerText = e;
+    }
+}
@@ -1,21 +1,128 @@
-import { train_neuralnetwork } from "./pkg/wasm.js";
+import { evaluate_neuralnetwork, Trainer } from "./pkg/wasm.js";
+
+// the amount of images that are used before the page shows the progress
+const IMAGES_PER_STEP = 500;
+
+let trainer = null;
+let paused = false;
+// the id of the queued trainStep, null when no step is queued, so there is never more than one
+let queuedStep = null;
+// the trained neural network is downloaded and evaluated only once per training
+let finished = false;
 
 document.getElementById('train-neural-network').addEventListener('click', train);
+document.getElementById('pause-training').addEventListener('click', pauseOrResume);
 
 function train(event) {
     event.preventDefault();
     const amountOfHiddenNeurons = document.getElementById('hidden-neurons').value;
     const amountOfTrainingRounds = document.getElementById('training-rounds').value;
     const learningRate = document.getElementById('learning-rate').value;
+    const shuffle = document.getElementById('shuffle').checked;
     try {
         document.getElementById('error-message-text').innerText = '';
-        const trainedNeuralNetwork = train_neuralnetwork(amountOfHiddenNeurons, amountOfTrainingRounds, learningRate);
-        download('your-neural-network.txt', trainedNeuralNetwork);
+        document.getElementById('evaluation').hidden = true;
+        if (trainer !== null) {
+            trainer.free();
+        }
+        trainer = new Trainer(amountOfHiddenNeurons, amountOfTrainingRounds, learningRate, shuffle);
+        clearTimeout(queuedStep);
+        queuedStep = null;
+        paused = false;
+        finished = false;
+        document.getElementById('train-neural-network').disabled = true;
+        document.getElementById('pause-training').value = 'Pause';
+        document.getElementById('pause-training').hidden = false;
+        document.getElementById('training-progress').hidden = false;
+        queueStep();
     } catch (e) {
         document.getElementById('error-message-text').innerText = e;
     }
 }
 
+// trains a few images at a time, so the page can show the progress in between
+function trainStep() {
+    queuedStep = null;
+    if (paused || finished) {
+        return;
+    }
+    try {
+        showProgress(JSON.parse(trainer.step(IMAGES_PER_STEP)));
+        if (trainer.is_finished()) {
+            finishTraining();
+        } else {
+            queueStep();
+        }
+    } catch (e) {
+        document.getElementById('error-message-text').innerText = e;
+        document.getElementById('train-neural-network').disabled = false;
+        document.getElementById('pause-training').hidden = true;
+    }
+}
+
+function queueStep() {
+    if (queuedStep === null) {
+        queuedStep = setTimeout(trainStep);
+    }
+}
+
+function pauseOrResume() {
+    paused = !paused;
+    document.getElementById('pause-training').value = paused ? 'Resume' : 'Pause';
+    if (paused) {
+        clearTimeout(queuedStep);
+        queuedStep = null;
+    } else {
+        queueStep();
+    }
+}
+
+function finishTraining() {
+    if (finished) {
+        return;
+    }
+    finished = true;
+    document.getElementById('train-neural-network').disabled = false;
+    document.getElementById('pause-training').hidden = true;
+    const trainedNeuralNetwork = trainer.neural_network();
+    download('your-neural-network.txt', trainedNeuralNetwork);
+    showEvaluation(JSON.parse(evaluate_neuralnetwork(trainedNeuralNetwork)));
+}
+
+function showProgress(progress) {
+    const done = (progress.round - 1) * progress.amount_of_images + progress.images;
+    const bar = document.getElementById('training-progress-bar');
+    bar.max = progress.amount_of_rounds * progress.amount_of_images;
+    bar.value = done;
+    document.getElementById('training-progress-text').innerText =
+        'Round ' + progress.round + ' of ' + progress.amount_of_rounds
+        + ', image ' + progress.images + ' of ' + progress.amount_of_images
+        + ', loss ' + progress.loss.toFixed(4)
+        + ', accuracy ' + (progress.accuracy * 100).toFixed(2) + '%';
+}
+
+function showEvaluation(report) {
+    document.getElementById('accuracy').innerText =
+        'Accuracy on the ' + report.amount_of_entries + ' test images: ' + (report.accuracy * 100).toFixed(2) + '%';
+    const table = document.getElementById('confusion-matrix');
+    table.innerHTML = '';
+    const header = table.insertRow();
+    header.insertCell().innerText = 'expected \\ predicted';
+    report.confusion_matrix.forEach((_, predicted) => header.insertCell().innerText = predicted);
+    report.confusion_matrix.forEach((row, expected) => {
+        const tableRow = table.insertRow();
+        tableRow.insertCell().innerText = expected;
+        row.forEach((count, predicted) => {
+            const cell = tableRow.insertCell();
+            cell.innerText = count;
+            if (expected === predicted) {
+                cell.className = 'correct';
+            }
+        });
+    });
+    document.getElementById('evaluation').hidden = false;
+}
+
 function download(filename, text) {
     const element = document.createElement('a');
     element.setAttribute('href', 'data:text/plain;charset=utf-8,' + encodeURIComponent(text));